@@ -0,0 +1,16 @@
+#version 140
+in vec3 v_normal;
+in vec4 v_col;
+uniform vec3 u_light;
+out vec4 color;
+void main() {
+    float brightness = dot(normalize(v_normal), normalize(u_light));
+    // Built with LIT defined, faces turned away from the light are drawn darker
+#ifdef LIT
+    vec4 dark_color = vec4(v_col.xyz * 0.3, v_col.w);
+#else
+    vec4 dark_color = v_col;
+#endif
+    vec4 regular_color = v_col;
+    color = mix(dark_color, regular_color, clamp(brightness, 0.0, 1.0));
+}
@@ -0,0 +1,17 @@
+#version 140
+in vec3 position;
+in vec3 normal;
+in mat4 model;
+in vec4 color;
+uniform mat4 view;
+uniform mat4 projection;
+uniform mat4 node;
+out vec3 v_normal;
+out vec4 v_col;
+
+void main() {
+    mat4 world = node * model;
+    v_col = color;
+    v_normal = transpose(inverse(mat3(world))) * normal;
+    gl_Position = projection * view * world * vec4(position, 1.0);
+}
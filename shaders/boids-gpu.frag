@@ -0,0 +1,2 @@
+// Shaded the same as everything else
+#include "base.frag"
@@ -0,0 +1,27 @@
+#version 140
+in vec3 position;
+in vec3 normal;
+in vec4 boid_position;
+in vec4 boid_velocity;
+uniform mat4 view;
+uniform mat4 projection;
+out vec3 v_normal;
+out vec4 v_col;
+
+void main() {
+    vec3 heading = vec3(0.0, 1.0, 0.0);
+    if (dot(boid_velocity.xyz, boid_velocity.xyz) > 0.0) {
+        heading = normalize(boid_velocity.xyz);
+    }
+    vec3 right = cross(heading, vec3(0.0, 0.0, 1.0));
+    if (dot(right, right) < 1e-8) {
+        right = cross(heading, vec3(1.0, 0.0, 0.0));
+    }
+    right = normalize(right);
+    mat3 basis = mat3(right, heading, cross(right, heading));
+
+    v_col = vec4(0.9, 0.9, 1.0, 1.0);
+    v_normal = basis * normal;
+    vec3 pos = basis * position + boid_position.xyz;
+    gl_Position = projection * view * vec4(pos, 1.0);
+}
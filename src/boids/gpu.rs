@@ -0,0 +1,222 @@
+use super::{tune_weights, write_boids, Boids, FlockParams};
+use drawable::shape::{HasShape, Shape};
+use drawable::{DrawUniforms, Drawable};
+use glium::backend::glutin::DisplayCreationError;
+use glium::program::ProgramCreationError;
+use glium::{glutin, Display, DrawParameters, Frame, Surface};
+use math::Vec3;
+use runnable::engine::BASE_FSHADER;
+use runnable::scene::Scene;
+use shader::{ProgramHandle, ProgramLibrary, ProgramSource, ShaderError};
+use std::io::{self, Write};
+use util::compute_container::BoidsCompute;
+use util::Manipulate;
+use winit::event::VirtualKeyCode;
+use winit::event_loop::EventLoop;
+
+// Largest position difference allowed between the CPU and GPU after the check steps
+pub const CHECK_TOLERANCE: f32 = 1e-4;
+
+// Points each instance along its velocity the same way Instance::look_along does
+const GPU_BOIDS_VSHADER: &str = include_str!("../../shaders/boids-gpu.vert");
+
+///
+/// A flat flock stepped by a compute shader instead of on the CPU.
+/// Only separation, alignment, cohesion and centering are simulated;
+/// the CPU `Boids` stays the reference for everything else.
+///
+pub struct GpuBoids {
+    compute: BoidsCompute,
+    shape: Shape,
+    // Only there once the scene's been handed the engine's program library
+    program: Option<ProgramHandle>,
+    params: FlockParams,
+}
+
+impl GpuBoids {
+    pub fn default(display: &Display, num: usize) -> Result<Self, ProgramCreationError> {
+        Self::from_boids(display, &Boids::default(display, num))
+    }
+
+    // Uploads the current state of a CPU flock, so both can be stepped side by side
+    pub fn from_boids(display: &Display, boids: &Boids) -> Result<Self, ProgramCreationError> {
+        let compute = BoidsCompute::new(display, &boids.positions(), boids.velocities())?;
+        let mut shape = Shape::triangle(display);
+        shape
+            .mut_vertices()
+            .iter_mut()
+            .for_each(|v| v.set_pos(v.pos() * 0.2));
+        shape.update_vbo();
+        Ok(Self {
+            compute,
+            shape,
+            program: None,
+            params: *boids.params(),
+        })
+    }
+
+    pub fn params_mut(&mut self) -> &mut FlockParams {
+        &mut self.params
+    }
+
+    pub fn positions(&self) -> Vec<Vec3> {
+        self.compute.read_positions()
+    }
+
+    pub fn velocities(&self) -> Vec<Vec3> {
+        self.compute.read_velocities()
+    }
+}
+
+impl Drawable for GpuBoids {
+    // Uses its own program, since the instances come from the compute buffers
+    fn draw(
+        &self,
+        target: &mut Frame,
+        programs: &ProgramLibrary,
+        params: &DrawParameters,
+        uniforms: DrawUniforms,
+    ) {
+        let program = match self.program {
+            Some(program) => programs.get(program),
+            None => return,
+        };
+        let instances = (
+            self.compute.positions().per_instance().unwrap(),
+            self.compute.velocities().per_instance().unwrap(),
+        );
+        target
+            .draw(
+                (self.shape.ref_vbo(), instances.0, instances.1),
+                self.shape.indices(),
+                program,
+                &uniforms,
+                &self.shape.draw_parameters(params),
+            )
+            .unwrap();
+    }
+
+    fn update(&mut self) {
+        self.compute.dispatch(&self.params);
+    }
+}
+
+// The instances are never read back, so there's nothing on the CPU to rotate
+impl Manipulate for GpuBoids {
+    fn rotate(&mut self, _axis: Vec3, _angle: f32) {}
+}
+
+impl Scene for GpuBoids {
+    fn dump(&self, out: &mut dyn Write) -> io::Result<()> {
+        write_boids(out, &self.positions(), &self.velocities())
+    }
+
+    fn handle_key(&mut self, key: VirtualKeyCode) {
+        tune_weights(self.params_mut(), key);
+    }
+
+    fn load_programs(&mut self, programs: &mut ProgramLibrary) -> Result<(), ShaderError> {
+        let source = ProgramSource::new(GPU_BOIDS_VSHADER, BASE_FSHADER);
+        self.program = Some(programs.handle_or_add("boids-gpu", source)?);
+        Ok(())
+    }
+}
+
+///
+/// Steps the same flock on the CPU and the GPU and returns the largest
+/// difference between their positions.
+/// Needs a GL 4.3 context; software Mesa works with LIBGL_ALWAYS_SOFTWARE=1.
+///
+pub fn compare_with_cpu(
+    display: &Display,
+    num: usize,
+    steps: usize,
+) -> Result<f32, ProgramCreationError> {
+    let mut cpu = Boids::default(display, num);
+    let mut gpu = GpuBoids::from_boids(display, &cpu)?;
+    for _ in 0..steps {
+        cpu.update();
+        gpu.update();
+    }
+    let difference = cpu
+        .positions()
+        .iter()
+        .zip(gpu.positions().iter())
+        .map(|(a, b)| (*a - *b).length())
+        .fold(0.0, f32::max);
+    Ok(difference)
+}
+
+// A hidden window with the GL 4.3 core context compute shaders need
+fn check_display(event_loop: &EventLoop<()>) -> Result<Display, DisplayCreationError> {
+    let wb = glutin::window::WindowBuilder::new().with_visible(false);
+    let cb = glutin::ContextBuilder::new()
+        .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (4, 3)))
+        .with_gl_profile(glutin::GlProfile::Core);
+    Display::new(wb, cb, event_loop)
+}
+
+///
+/// Runs `compare_with_cpu` in a hidden window and reports the result.
+/// Returns the process exit code, which is only 0 if the backends agree.
+/// Having no GL 4.3 context or compute shaders to check with counts as failing.
+///
+pub fn run_check(num: usize, steps: usize) -> i32 {
+    let event_loop = EventLoop::new();
+    let display = match check_display(&event_loop) {
+        Ok(display) => display,
+        Err(err) => {
+            eprintln!("Can't check the GPU boids, no GL 4.3 context: {}", err);
+            return 1;
+        }
+    };
+    match compare_with_cpu(&display, num, steps) {
+        Ok(difference) if difference <= CHECK_TOLERANCE => {
+            println!(
+                "GPU boids match the CPU after {} steps (max difference {:e})",
+                steps, difference
+            );
+            0
+        }
+        Ok(difference) => {
+            eprintln!(
+                "GPU boids drifted from the CPU after {} steps: max difference {:e} > {:e}",
+                steps, difference, CHECK_TOLERANCE
+            );
+            1
+        }
+        Err(ProgramCreationError::CompilationNotSupported) => {
+            eprintln!("Can't check the GPU boids, compute shaders aren't supported");
+            1
+        }
+        Err(err) => {
+            eprintln!("GPU boids shaders failed to build: {}", err);
+            1
+        }
+    }
+}
+
+// The hidden window's event loop has to be made off the main thread, which needs X11 or Wayland
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use winit::event_loop::EventLoopBuilder;
+    use winit::platform::unix::EventLoopBuilderExtUnix;
+
+    // Needs a display and GL 4.3, run it with
+    // `LIBGL_ALWAYS_SOFTWARE=1 xvfb-run cargo test -- --ignored gpu_boids`
+    #[test]
+    #[ignore]
+    fn gpu_boids_match_cpu() {
+        // Tests don't run on the main thread
+        let event_loop = EventLoopBuilder::new().with_any_thread(true).build();
+        let display = check_display(&event_loop).expect("no GL 4.3 context");
+        let difference = compare_with_cpu(&display, 500, 5).expect("compute shaders didn't build");
+        assert!(
+            difference <= CHECK_TOLERANCE,
+            "max difference {:e} > {:e}",
+            difference,
+            CHECK_TOLERANCE
+        );
+    }
+}
@@ -0,0 +1,650 @@
+pub(crate) mod gpu;
+pub(crate) mod obstacle;
+
+use self::obstacle::Obstacle;
+use camera::orbit::OrbitCamera;
+use camera::Camera;
+use drawable::shape::{HasShape, Shape};
+use drawable::shape_group::ShapeGroup;
+use drawable::{DrawUniforms, Drawable};
+use glium::{DrawParameters, Frame};
+use math::Vec3;
+use rayon::prelude::*;
+use runnable::scene::Scene;
+use shader::ProgramLibrary;
+use std::io::{self, Write};
+use util::attribute::Instance;
+use util::bufferable::{Backend, InstanceBuffer};
+use util::random;
+use util::spatial_hash::SpatialHash;
+use util::Manipulate;
+use winit::event::VirtualKeyCode;
+
+pub(crate) const NUM_BOIDS: usize = 10000;
+
+///
+/// Tuning for the flocking rules.
+/// Each rule's steering force is scaled by its weight before being summed,
+/// and boids only react to others within the perception radius.
+///
+#[derive(Copy, Clone, Debug)]
+pub struct FlockParams {
+    pub perception: f32,
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+    // Pull back towards the origin so the flock stays on screen
+    pub centering: f32,
+    pub max_speed: f32,
+    pub max_force: f32,
+    // Obstacles are steered around once a boid is within the avoid distance
+    pub avoidance: f32,
+    pub avoid_distance: f32,
+    // Predators are fled from once they're within the flee radius
+    pub flee: f32,
+    pub flee_radius: f32,
+    // Pull towards the nearest goal
+    pub seek: f32,
+    pub predator_speed: f32,
+    pub predator_force: f32,
+}
+
+impl Default for FlockParams {
+    fn default() -> Self {
+        FlockParams {
+            perception: 0.08,
+            separation: 1.5,
+            alignment: 1.0,
+            cohesion: 1.0,
+            centering: 0.0001,
+            max_speed: 0.01,
+            max_force: 0.0005,
+            avoidance: 3.0,
+            avoid_distance: 0.1,
+            flee: 3.0,
+            flee_radius: 0.25,
+            seek: 0.5,
+            predator_speed: 0.012,
+            predator_force: 0.0004,
+        }
+    }
+}
+
+// How much a key press raises or lowers a flocking weight
+const WEIGHT_STEP: f32 = 0.25;
+
+///
+/// Z and X lower and raise the separation weight, C and V alignment and B and N cohesion.
+/// Returns whether the key was one of them.
+///
+pub fn tune_weights(params: &mut FlockParams, key: VirtualKeyCode) -> bool {
+    let (weight, step) = match key {
+        VirtualKeyCode::Z => (&mut params.separation, -WEIGHT_STEP),
+        VirtualKeyCode::X => (&mut params.separation, WEIGHT_STEP),
+        VirtualKeyCode::C => (&mut params.alignment, -WEIGHT_STEP),
+        VirtualKeyCode::V => (&mut params.alignment, WEIGHT_STEP),
+        VirtualKeyCode::B => (&mut params.cohesion, -WEIGHT_STEP),
+        VirtualKeyCode::N => (&mut params.cohesion, WEIGHT_STEP),
+        _ => return false,
+    };
+    *weight = (*weight + step).max(0.0);
+    println!(
+        "Separation {}, alignment {}, cohesion {}",
+        params.separation, params.alignment, params.cohesion
+    );
+    true
+}
+
+// Turns a desired direction into a steering force towards it at full speed
+fn steer(desired: Vec3, velocity: Vec3, params: &FlockParams) -> Vec3 {
+    if desired.length_sq() == 0.0 {
+        return Vec3::ZERO;
+    }
+    (desired.normalize() * params.max_speed - velocity).clamp_length(params.max_force)
+}
+
+///
+/// Separation, alignment and cohesion forces on the boid at `index`,
+/// from every other boid within the perception radius.
+/// Returned unweighted so each can be checked on its own.
+///
+pub fn flock_forces(
+    index: usize,
+    positions: &[Vec3],
+    velocities: &[Vec3],
+    neighbours: impl Iterator<Item = usize>,
+    params: &FlockParams,
+) -> (Vec3, Vec3, Vec3) {
+    let (position, velocity) = (positions[index], velocities[index]);
+    let mut away = Vec3::ZERO;
+    let mut heading = Vec3::ZERO;
+    let mut centre = Vec3::ZERO;
+    let mut count = 0;
+
+    let perception_sq = params.perception * params.perception;
+    for other in neighbours.filter(|&other| other != index) {
+        let offset = position - positions[other];
+        let dist_sq = offset.length_sq();
+        if dist_sq == 0.0 || dist_sq > perception_sq {
+            continue;
+        }
+        // Closer boids push harder
+        away += offset / dist_sq;
+        heading += velocities[other];
+        centre += positions[other];
+        count += 1;
+    }
+
+    if count == 0 {
+        return Default::default();
+    }
+    let centre = centre / count as f32;
+    (
+        steer(away, velocity, params),
+        steer(heading, velocity, params),
+        steer(centre - position, velocity, params),
+    )
+}
+
+// Steers away from every obstacle that's closer than the avoid distance, harder the closer it is
+fn avoid_obstacles(
+    position: Vec3,
+    velocity: Vec3,
+    obstacles: &[Obstacle],
+    params: &FlockParams,
+) -> Vec3 {
+    let mut force = Vec3::ZERO;
+    for obstacle in obstacles {
+        let (distance, away) = obstacle.distance(position);
+        if distance < params.avoid_distance {
+            let urgency = 1.0 - distance.max(0.0) / params.avoid_distance;
+            force += steer(away, velocity, params) * urgency;
+        }
+    }
+    force
+}
+
+// Steers away from predators within the flee radius
+fn flee_predators(
+    position: Vec3,
+    velocity: Vec3,
+    predators: &[Vec3],
+    params: &FlockParams,
+) -> Vec3 {
+    let radius_sq = params.flee_radius * params.flee_radius;
+    let mut away = Vec3::ZERO;
+    for &predator in predators {
+        let offset = position - predator;
+        let dist_sq = offset.length_sq();
+        if dist_sq > 0.0 && dist_sq < radius_sq {
+            away += offset / dist_sq;
+        }
+    }
+    steer(away, velocity, params)
+}
+
+// Steers towards the closest goal
+fn seek_goal(position: Vec3, velocity: Vec3, goals: &[Vec3], params: &FlockParams) -> Vec3 {
+    goals
+        .iter()
+        .map(|&goal| goal - position)
+        .min_by(|a, b| a.length_sq().total_cmp(&b.length_sq()))
+        .map_or_else(Vec3::default, |to_goal| steer(to_goal, velocity, params))
+}
+
+// One boid per line as comma separated position and velocity
+fn write_boids(out: &mut dyn Write, positions: &[Vec3], velocities: &[Vec3]) -> io::Result<()> {
+    writeln!(out, "x,y,z,vx,vy,vz")?;
+    for (p, v) in positions.iter().zip(velocities.iter()) {
+        writeln!(out, "{},{},{},{},{},{}", p.x, p.y, p.z, v.x, v.y, v.z)?;
+    }
+    Ok(())
+}
+
+fn transform_positions(group: &ShapeGroup<Shape>) -> Vec<Vec3> {
+    if group.shapes.is_empty() {
+        return vec![];
+    }
+    group
+        .iter_transforms(0)
+        .map(|attr| attr.position())
+        .collect()
+}
+
+pub struct Boids {
+    shapegroup: ShapeGroup<Shape>,
+    velocities: Vec<Vec3>,
+    accelerations: Vec<Vec3>,
+    params: FlockParams,
+    spatial: SpatialHash,
+    obstacles: Vec<Obstacle>,
+    // One shape per obstacle, each with a single instance
+    obstacle_group: ShapeGroup<Shape>,
+    predator_velocities: Vec<Vec3>,
+    predator_group: ShapeGroup<Shape>,
+    goal_group: ShapeGroup<Shape>,
+    // Half the width of the cube 3D boids are kept in, None for a flat flock
+    volume: Option<f32>,
+    // Invisible walls of the volume
+    walls: Vec<Obstacle>,
+    // Positions before the last update, drawn blended with the current ones
+    previous: Vec<Vec3>,
+}
+
+impl Boids {
+    pub fn default(display: &dyn Backend, num: usize) -> Self {
+        Self::new(display, num, None)
+    }
+
+    // Boids that fly in three dimensions inside a cube of the given half width, drawn pointing where they're going
+    pub fn volume(display: &dyn Backend, num: usize, half_width: f32) -> Self {
+        Self::new(display, num, Some(half_width))
+    }
+
+    fn new(display: &dyn Backend, num: usize, volume: Option<f32>) -> Self {
+        let mut shapegroup = ShapeGroup::default();
+        let shape = match volume {
+            Some(_) => {
+                let mut triangle = Shape::triangle(display);
+                triangle
+                    .mut_vertices()
+                    .iter_mut()
+                    .for_each(|v| v.set_pos(v.pos() * 0.2));
+                triangle.update_vbo();
+                triangle
+            }
+            None => Shape::circle(display, 0.01, 10),
+        };
+        let mut attributes = vec![];
+        let mut velocities = vec![];
+        let mut accelerations = vec![];
+
+        for _ in 0..num {
+            let mut attr = Instance::random();
+            attr.set_position(Self::spawn_point(volume));
+            let mut velocity = random::in_cube(2.0) * 0.005;
+            if volume.is_none() {
+                velocity.z = 0.0;
+            }
+            attr.look_along(velocity);
+            attributes.push(attr);
+            velocities.push(velocity);
+            accelerations.push(Vec3::ZERO);
+        }
+
+        let attr = InstanceBuffer::new(display, &attributes);
+        shapegroup.push((shape, attr));
+
+        let walls = match volume {
+            Some(w) => vec![
+                Obstacle::plane([-w, 0.0, 0.0], [1.0, 0.0, 0.0]),
+                Obstacle::plane([w, 0.0, 0.0], [-1.0, 0.0, 0.0]),
+                Obstacle::plane([0.0, -w, 0.0], [0.0, 1.0, 0.0]),
+                Obstacle::plane([0.0, w, 0.0], [0.0, -1.0, 0.0]),
+                Obstacle::plane([0.0, 0.0, -w], [0.0, 0.0, 1.0]),
+                Obstacle::plane([0.0, 0.0, w], [0.0, 0.0, -1.0]),
+            ],
+            None => vec![],
+        };
+
+        Self {
+            shapegroup,
+            velocities,
+            accelerations,
+            params: FlockParams::default(),
+            spatial: SpatialHash::new(FlockParams::default().perception),
+            obstacles: vec![],
+            obstacle_group: ShapeGroup::default(),
+            predator_velocities: vec![],
+            predator_group: ShapeGroup::default(),
+            goal_group: ShapeGroup::default(),
+            volume,
+            walls,
+            previous: vec![],
+        }
+    }
+
+    // A flat flock kept between a floor and a ceiling, with spheres and segments to fly
+    // around, predators chasing it and a goal to head for
+    pub fn obstacle_course(display: &dyn Backend, num: usize) -> Self {
+        let mut boids = Self::default(display, num);
+        let obstacles = [
+            Obstacle::plane([0.0, -0.9, 0.0], [0.0, 1.0, 0.0]),
+            Obstacle::plane([0.0, 0.9, 0.0], [0.0, -1.0, 0.0]),
+            Obstacle::sphere([-0.5, 0.35, 0.0], 0.12),
+            Obstacle::sphere([0.45, -0.35, 0.0], 0.1),
+            Obstacle::sphere([0.0, 0.0, 0.0], 0.06),
+            Obstacle::segment([-0.6, -0.45, 0.0], [-0.1, -0.6, 0.0]),
+            Obstacle::segment([0.15, 0.55, 0.0], [0.55, 0.25, 0.0]),
+        ];
+        for obstacle in obstacles {
+            boids.add_obstacle(display, obstacle);
+        }
+        boids.add_predators(display, 3);
+        boids.add_goal(display, [0.7, 0.6, 0.0]);
+        boids.add_goal(display, [-0.7, -0.7, 0.0]);
+        boids
+    }
+
+    // Random position inside the volume, or on the z = 0 plane for a flat flock
+    fn spawn_point(volume: Option<f32>) -> Vec3 {
+        let mut point = random::in_cube(2.0);
+        match volume {
+            Some(half_width) => point *= half_width / 2.0,
+            None => point.z = 0.0,
+        }
+        point
+    }
+
+    pub fn add_obstacle(&mut self, display: &dyn Backend, obstacle: Obstacle) {
+        let mut attr = Instance::from(obstacle.origin());
+        attr.color = [0.6, 0.6, 0.6, 1.0];
+        self.obstacle_group.push((
+            obstacle.shape(display),
+            InstanceBuffer::new(display, &[attr]),
+        ));
+        self.obstacles.push(obstacle);
+    }
+
+    // Adds predators at random positions, rebuilding their instance buffer
+    pub fn add_predators(&mut self, display: &dyn Backend, num: usize) {
+        let mut attributes: Vec<Instance> = self
+            .predator_group
+            .transforms
+            .first()
+            .map_or_else(Vec::new, |t| t.ref_data().to_vec());
+        for _ in 0..num {
+            let mut attr = Instance::random();
+            attr.set_position(Self::spawn_point(self.volume));
+            attr.color = [1.0, 0.1, 0.1, 1.0];
+            attributes.push(attr);
+            self.predator_velocities.push(Vec3::ZERO);
+        }
+        self.predator_group = ShapeGroup::default();
+        self.predator_group.push((
+            Shape::triangle(display),
+            InstanceBuffer::new(display, &attributes),
+        ));
+    }
+
+    // Adds a goal point, rebuilding the goal instance buffer
+    pub fn add_goal(&mut self, display: &dyn Backend, position: [f32; 3]) {
+        let mut attributes: Vec<Instance> = self
+            .goal_group
+            .transforms
+            .first()
+            .map_or_else(Vec::new, |t| t.ref_data().to_vec());
+        let mut attr = Instance::from(position);
+        attr.color = [0.1, 1.0, 0.2, 1.0];
+        attributes.push(attr);
+        self.goal_group = ShapeGroup::default();
+        self.goal_group.push((
+            Shape::circle(display, 0.02, 12),
+            InstanceBuffer::new(display, &attributes),
+        ));
+    }
+
+    // Each predator chases the boid closest to it
+    fn update_predators(&mut self, boids: &[Vec3]) {
+        if self.predator_group.shapes.is_empty() || boids.is_empty() {
+            return;
+        }
+        let params = self.params;
+        let hunter = FlockParams {
+            max_speed: params.predator_speed,
+            max_force: params.predator_force,
+            ..params
+        };
+        let obstacles = &self.obstacles;
+        let walls = &self.walls;
+        let predators = transform_positions(&self.predator_group);
+        let velocities = &mut self.predator_velocities;
+        velocities
+            .par_iter_mut()
+            .zip(predators.par_iter())
+            .for_each(|(velocity, &position)| {
+                let prey = boids
+                    .iter()
+                    .min_by(|a, b| {
+                        (**a - position)
+                            .length_sq()
+                            .total_cmp(&(**b - position).length_sq())
+                    })
+                    .unwrap();
+                *velocity += steer(*prey - position, *velocity, &hunter)
+                    + avoid_obstacles(position, *velocity, obstacles, &hunter) * params.avoidance
+                    + avoid_obstacles(position, *velocity, walls, &hunter) * params.avoidance;
+                *velocity = velocity.clamp_length(hunter.max_speed);
+            });
+        for (predator, velocity) in self
+            .predator_group
+            .iter_mut_transforms(0)
+            .zip(velocities.iter())
+        {
+            *predator += *velocity;
+            predator.look_along(*velocity);
+        }
+    }
+
+    pub fn params(&self) -> &FlockParams {
+        &self.params
+    }
+
+    // Weights and limits can be changed between updates
+    pub fn params_mut(&mut self) -> &mut FlockParams {
+        &mut self.params
+    }
+
+    pub fn positions(&self) -> Vec<Vec3> {
+        transform_positions(&self.shapegroup)
+    }
+
+    pub fn velocities(&self) -> &[Vec3] {
+        &self.velocities
+    }
+
+    // Draws each boid part of the way from where it was before the last update to where it is now
+    fn draw_flock(
+        &self,
+        target: &mut Frame,
+        programs: &ProgramLibrary,
+        params: &DrawParameters,
+        uniforms: DrawUniforms,
+    ) {
+        let alpha = uniforms.alpha();
+        if alpha >= 1.0 || self.previous.len() != self.velocities.len() {
+            self.shapegroup.draw(target, programs, params, uniforms);
+            return;
+        }
+        let blended: Vec<Instance> = self
+            .shapegroup
+            .iter_transforms(0)
+            .zip(self.previous.iter())
+            .map(|(attr, &previous)| {
+                let current = attr.position();
+                let mut attr = *attr;
+                attr.set_position(previous + (current - previous) * alpha);
+                attr
+            })
+            .collect();
+        self.shapegroup.transforms[0].write(&blended);
+        self.shapegroup
+            .draw_buffers(target, programs, params, uniforms);
+    }
+}
+
+impl Drawable for Boids {
+    fn draw(
+        &self,
+        target: &mut Frame,
+        programs: &ProgramLibrary,
+        params: &DrawParameters,
+        uniforms: DrawUniforms,
+    ) {
+        self.draw_flock(target, programs, params, uniforms);
+        self.obstacle_group.draw(target, programs, params, uniforms);
+        self.predator_group.draw(target, programs, params, uniforms);
+        self.goal_group.draw(target, programs, params, uniforms);
+    }
+
+    fn update(&mut self) {
+        let positions = self.positions();
+        self.previous.clone_from(&positions);
+        self.update_predators(&positions);
+        let predators = transform_positions(&self.predator_group);
+        let goals = transform_positions(&self.goal_group);
+        let obstacles = &self.obstacles;
+        let walls = &self.walls;
+        let velocities = &self.velocities;
+        let params = self.params;
+
+        // Only boids in the cells around each boid are checked against its perception radius
+        self.spatial.set_cell_size(params.perception);
+        self.spatial.rebuild(&positions);
+        let spatial = &self.spatial;
+
+        self.accelerations
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(i, acceleration)| {
+                let neighbours = spatial.within(&positions[i], params.perception, &positions);
+                let (separation, alignment, cohesion) =
+                    flock_forces(i, &positions, velocities, neighbours, &params);
+                let (position, velocity) = (positions[i], velocities[i]);
+                *acceleration = separation * params.separation
+                    + alignment * params.alignment
+                    + cohesion * params.cohesion
+                    + avoid_obstacles(position, velocity, obstacles, &params) * params.avoidance
+                    + avoid_obstacles(position, velocity, walls, &params) * params.avoidance
+                    + flee_predators(position, velocity, &predators, &params) * params.flee
+                    + seek_goal(position, velocity, &goals, &params) * params.seek
+                    - position * params.centering;
+            });
+
+        let volume = self.volume;
+        for (ind, boid) in self.shapegroup.iter_mut_transforms(0).enumerate() {
+            let velocity = &mut self.velocities[ind];
+            *velocity += self.accelerations[ind];
+            *velocity = velocity.clamp_length(params.max_speed);
+            *boid += *velocity;
+            // Anything that gets through the walls bounces back in
+            if let Some(half_width) = volume {
+                let mut position = boid.position();
+                for axis in 0..3 {
+                    let p = position[axis];
+                    if p.abs() > half_width {
+                        position[axis] = p.clamp(-half_width, half_width);
+                        velocity[axis] = -velocity[axis];
+                    }
+                }
+                boid.set_position(position);
+            }
+            boid.look_along(*velocity);
+        }
+        self.shapegroup.update_buffers();
+    }
+}
+
+impl Manipulate for Boids {
+    fn rotate(&mut self, axis: Vec3, angle: f32) {
+        self.shapegroup.rotate(axis, angle);
+        self.obstacle_group.rotate(axis, angle);
+        self.predator_group.rotate(axis, angle);
+        self.goal_group.rotate(axis, angle);
+    }
+}
+
+impl Scene for Boids {
+    fn dump(&self, out: &mut dyn Write) -> io::Result<()> {
+        write_boids(out, &self.positions(), &self.velocities)
+    }
+
+    // Far enough back to see the whole volume, or face on to a flat flock
+    fn camera(&self) -> Box<dyn Camera> {
+        let distance = self.volume.map_or(1.0, |half_width| half_width * 2.5);
+        Box::new(OrbitCamera::new(Vec3::ZERO, distance))
+    }
+
+    fn handle_key(&mut self, key: VirtualKeyCode) {
+        tune_weights(self.params_mut(), key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-6;
+
+    // Forces on the first boid from all the others
+    fn forces_on_first(positions: &[Vec3], velocities: &[Vec3]) -> (Vec3, Vec3, Vec3) {
+        let params = FlockParams::default();
+        flock_forces(0, positions, velocities, 0..positions.len(), &params)
+    }
+
+    #[test]
+    fn separation_pushes_away_hardest_from_the_closest() {
+        let positions = [
+            Vec3::ZERO,
+            Vec3::new(0.02, 0.0, 0.0),
+            Vec3::new(-0.06, 0.0, 0.0),
+        ];
+        let (separation, _, _) = forces_on_first(&positions, &[Vec3::ZERO; 3]);
+        let max_force = FlockParams::default().max_force;
+        assert!(separation.x < 0.0);
+        assert!(separation.y.abs() < EPSILON && separation.z.abs() < EPSILON);
+        assert!((separation.length() - max_force).abs() < EPSILON);
+    }
+
+    #[test]
+    fn alignment_turns_towards_the_average_heading() {
+        let positions = [
+            Vec3::ZERO,
+            Vec3::new(0.03, 0.0, 0.0),
+            Vec3::new(0.0, 0.03, 0.0),
+        ];
+        let velocities = [
+            Vec3::new(0.01, 0.0, 0.0),
+            Vec3::new(0.0, 0.01, 0.0),
+            Vec3::new(0.0, 0.005, 0.0),
+        ];
+        let (_, alignment, _) = forces_on_first(&positions, &velocities);
+        // Full speed along y, less the velocity it already has along x
+        let expected = Vec3::new(-1.0, 1.0, 0.0).normalize() * FlockParams::default().max_force;
+        assert!(alignment.approx_eq(expected, EPSILON), "{:?}", alignment);
+    }
+
+    #[test]
+    fn cohesion_steers_towards_the_centre_of_the_neighbours() {
+        let positions = [
+            Vec3::ZERO,
+            Vec3::new(0.04, 0.02, 0.0),
+            Vec3::new(0.04, -0.02, 0.0),
+        ];
+        let (_, _, cohesion) = forces_on_first(&positions, &[Vec3::ZERO; 3]);
+        let expected = Vec3::X * FlockParams::default().max_force;
+        assert!(cohesion.approx_eq(expected, EPSILON), "{:?}", cohesion);
+    }
+
+    #[test]
+    fn boids_out_of_sight_are_ignored() {
+        let perception = FlockParams::default().perception;
+        let positions = [Vec3::ZERO, Vec3::new(perception * 1.01, 0.0, 0.0)];
+        let velocities = [Vec3::ZERO, Vec3::Y * 0.01];
+        let forces = forces_on_first(&positions, &velocities);
+        assert_eq!(forces, (Vec3::ZERO, Vec3::ZERO, Vec3::ZERO));
+        // Nor does a boid count itself
+        let forces = forces_on_first(&positions[..1], &velocities[..1]);
+        assert_eq!(forces, (Vec3::ZERO, Vec3::ZERO, Vec3::ZERO));
+    }
+
+    #[test]
+    fn weights_stay_positive() {
+        let mut params = FlockParams::default();
+        assert!(tune_weights(&mut params, VirtualKeyCode::X));
+        assert_eq!(params.separation, 1.5 + WEIGHT_STEP);
+        (0..10).for_each(|_| assert!(tune_weights(&mut params, VirtualKeyCode::B)));
+        assert_eq!(params.cohesion, 0.0);
+        assert!(!tune_weights(&mut params, VirtualKeyCode::Q));
+    }
+}
@@ -0,0 +1,100 @@
+use drawable::shape::Shape;
+use glium::index::PrimitiveType;
+use math::Vec3;
+use util::bufferable::Backend;
+use util::vertex::F32vec3;
+
+// Segments of the circle drawn for a sphere
+const SPHERE_DETAIL: u32 = 24;
+// How far a plane is drawn either side of its point
+const PLANE_EXTENT: f32 = 4.0;
+
+///
+/// Static geometry that boids steer around.
+/// Planes are one sided, boids are pushed out along the normal.
+///
+#[derive(Copy, Clone, Debug)]
+pub enum Obstacle {
+    Sphere { centre: Vec3, radius: f32 },
+    Plane { point: Vec3, normal: Vec3 },
+    Segment { start: Vec3, end: Vec3 },
+}
+
+impl Obstacle {
+    pub fn sphere(centre: [f32; 3], radius: f32) -> Self {
+        Obstacle::Sphere {
+            centre: Vec3::from(centre),
+            radius,
+        }
+    }
+
+    pub fn plane(point: [f32; 3], normal: [f32; 3]) -> Self {
+        Obstacle::Plane {
+            point: Vec3::from(point),
+            normal: Vec3::from(normal).normalize(),
+        }
+    }
+
+    pub fn segment(start: [f32; 3], end: [f32; 3]) -> Self {
+        Obstacle::Segment {
+            start: Vec3::from(start),
+            end: Vec3::from(end),
+        }
+    }
+
+    // Distance from the surface of the obstacle to `position`, negative when inside,
+    // and the direction that leads away from it
+    pub fn distance(&self, position: Vec3) -> (f32, Vec3) {
+        let (distance, away) = match *self {
+            Obstacle::Sphere { centre, radius } => {
+                let offset = position - centre;
+                (offset.length() - radius, offset)
+            }
+            Obstacle::Plane { point, normal } => ((position - point).dot(normal), normal),
+            Obstacle::Segment { start, end } => {
+                let line = end - start;
+                let t = if line.length_sq() == 0.0 {
+                    0.0
+                } else {
+                    ((position - start).dot(line) / line.length_sq()).clamp(0.0, 1.0)
+                };
+                let offset = position - (start + line * t);
+                (offset.length(), offset)
+            }
+        };
+        (distance, away.try_normalize().unwrap_or(away))
+    }
+
+    // Shape to draw the obstacle with, placed at the origin of its instance
+    pub fn shape(&self, display: &dyn Backend) -> Shape {
+        match *self {
+            Obstacle::Sphere { radius, .. } => Shape::circle(display, radius, SPHERE_DETAIL),
+            Obstacle::Plane { normal, .. } => {
+                // Any direction along the plane
+                let mut along = normal.cross(Vec3::Z);
+                if along.length_sq() < 1e-6 {
+                    along = normal.cross(Vec3::Y);
+                }
+                let along = along.normalize();
+                let vertices = [
+                    F32vec3::from(along * -PLANE_EXTENT),
+                    F32vec3::from(along * PLANE_EXTENT),
+                ];
+                Shape::from_vertices(&vertices, PrimitiveType::LinesList, display)
+            }
+            Obstacle::Segment { start, end } => {
+                let vertices = [F32vec3::default(), F32vec3::from(end - start)];
+                Shape::from_vertices(&vertices, PrimitiveType::LinesList, display)
+            }
+        }
+    }
+
+    // Where the shape's instance goes
+    pub fn origin(&self) -> [f32; 3] {
+        match *self {
+            Obstacle::Sphere { centre, .. } => centre.into(),
+            Obstacle::Plane { point, .. } => point.into(),
+            Obstacle::Segment { start, .. } => start.into(),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use super::{Camera, Mouse, FOV, ZFAR, ZNEAR};
+use math::{Mat4, Vec3};
+use std::collections::HashSet;
+use std::f32::consts::FRAC_PI_2;
+use winit::event::{ElementState, VirtualKeyCode, WindowEvent};
+
+// World units moved per second
+const MOVE_SPEED: f32 = 1.0;
+// Holding shift moves this many times faster
+const BOOST: f32 = 4.0;
+const LOOK_SPEED: f32 = 0.004;
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+///
+/// Free flying camera. W, A, S and D move along the ground, E and Q move
+/// straight up and down, and dragging with either mouse button looks around.
+/// +y is up.
+///
+#[derive(Clone, Debug)]
+pub struct FlyCamera {
+    position: Vec3,
+    // Angle around the y axis, 0 looks down -z
+    yaw: f32,
+    pitch: f32,
+    held: HashSet<VirtualKeyCode>,
+    mouse: Mouse,
+}
+
+impl Default for FlyCamera {
+    fn default() -> Self {
+        Self::new(Vec3::new(0.0, 0.0, 1.0), 0.0, 0.0)
+    }
+}
+
+impl FlyCamera {
+    pub fn new(position: Vec3, yaw: f32, pitch: f32) -> Self {
+        FlyCamera {
+            position,
+            yaw,
+            pitch: pitch.clamp(-MAX_PITCH, MAX_PITCH),
+            held: HashSet::new(),
+            mouse: Mouse::default(),
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        Vec3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            -self.pitch.cos() * self.yaw.cos(),
+        )
+    }
+
+    pub fn look(&mut self, yaw: f32, pitch: f32) {
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    // -1, 0 or 1 depending on which of the two keys are held
+    fn axis(&self, negative: VirtualKeyCode, positive: VirtualKeyCode) -> f32 {
+        self.held.contains(&positive) as i32 as f32 - self.held.contains(&negative) as i32 as f32
+    }
+}
+
+impl Camera for FlyCamera {
+    fn view(&self) -> Mat4 {
+        Mat4::look_at(self.position, self.position + self.forward(), Vec3::Y)
+    }
+
+    fn projection(&self, width: u32, height: u32) -> Mat4 {
+        Mat4::perspective(FOV, width as f32 / height as f32, ZNEAR, ZFAR)
+    }
+
+    fn handle_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(key) = input.virtual_keycode {
+                    match input.state {
+                        ElementState::Pressed => self.held.insert(key),
+                        ElementState::Released => self.held.remove(&key),
+                    };
+                }
+            }
+            // Keys let go of while another window has focus never report being released
+            WindowEvent::Focused(false) => self.held.clear(),
+            _ => (),
+        }
+        if let Some(drag) = self.mouse.handle_event(event) {
+            self.look(drag.dx * LOOK_SPEED, -drag.dy * LOOK_SPEED);
+        }
+    }
+
+    fn update(&mut self, dt: f32) {
+        // Walking ignores the pitch so looking down doesn't slow you down
+        let ground = Vec3::new(self.yaw.sin(), 0.0, -self.yaw.cos());
+        let right = Vec3::new(self.yaw.cos(), 0.0, self.yaw.sin());
+        let velocity = ground * self.axis(VirtualKeyCode::S, VirtualKeyCode::W)
+            + right * self.axis(VirtualKeyCode::A, VirtualKeyCode::D)
+            + Vec3::Y * self.axis(VirtualKeyCode::Q, VirtualKeyCode::E);
+        let velocity = match velocity.try_normalize() {
+            Some(velocity) => velocity,
+            None => return,
+        };
+        let boost = self.held.contains(&VirtualKeyCode::LShift)
+            || self.held.contains(&VirtualKeyCode::RShift);
+        let speed = if boost {
+            MOVE_SPEED * BOOST
+        } else {
+            MOVE_SPEED
+        };
+        self.position += velocity * (speed * dt);
+    }
+}
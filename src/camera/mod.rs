@@ -0,0 +1,120 @@
+pub(crate) mod fly;
+pub(crate) mod orbit;
+pub(crate) mod pan_zoom;
+
+use math::Mat4;
+use std::f32::consts::PI;
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+
+// Field of view of the perspective cameras, top to bottom
+pub const FOV: f32 = PI / 2.0;
+pub const ZNEAR: f32 = 0.1;
+pub const ZFAR: f32 = 1024.0;
+
+///
+/// Where the scene is looked at from.
+/// Cameras follow the mouse and keyboard through the window events they're
+/// handed and give the view and projection matrices the shaders draw with.
+///
+pub trait Camera {
+    fn view(&self) -> Mat4;
+    // Projection for a framebuffer of the given size in pixels
+    fn projection(&self, width: u32, height: u32) -> Mat4;
+    fn handle_event(&mut self, event: &WindowEvent);
+    // Moves by whatever is held down, `dt` is the real time since the last frame in seconds
+    fn update(&mut self, _dt: f32) {}
+    // Called with the new framebuffer size in pixels whenever it changes
+    fn resize(&mut self, _width: u32, _height: u32) {}
+}
+
+impl<T: Camera + ?Sized> Camera for Box<T> {
+    fn view(&self) -> Mat4 {
+        (**self).view()
+    }
+
+    fn projection(&self, width: u32, height: u32) -> Mat4 {
+        (**self).projection(width, height)
+    }
+
+    fn handle_event(&mut self, event: &WindowEvent) {
+        (**self).handle_event(event);
+    }
+
+    fn update(&mut self, dt: f32) {
+        (**self).update(dt);
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        (**self).resize(width, height);
+    }
+}
+
+// Scroll wheel movement in lines, trackpads report pixels instead
+fn scroll_lines(delta: &MouseScrollDelta) -> f32 {
+    match delta {
+        MouseScrollDelta::LineDelta(_, y) => *y,
+        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+    }
+}
+
+// How far the cursor moved, in pixels, while a button was held
+#[derive(Copy, Clone, Debug)]
+struct Drag {
+    button: MouseButton,
+    dx: f32,
+    dy: f32,
+}
+
+///
+/// Keeps track of the cursor and the buttons held down, so cameras can turn
+/// cursor moves into drags. Only the left and right buttons are followed,
+/// and the left one wins if both are held.
+///
+#[derive(Copy, Clone, Debug, Default)]
+struct Mouse {
+    cursor: Option<PhysicalPosition<f64>>,
+    left: bool,
+    right: bool,
+}
+
+impl Mouse {
+    fn handle_event(&mut self, event: &WindowEvent) -> Option<Drag> {
+        match event {
+            WindowEvent::MouseInput { state, button, .. } => {
+                let pressed = *state == ElementState::Pressed;
+                match button {
+                    MouseButton::Left => self.left = pressed,
+                    MouseButton::Right => self.right = pressed,
+                    _ => (),
+                }
+                None
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let last = self.cursor.replace(*position)?;
+                let button = if self.left {
+                    MouseButton::Left
+                } else if self.right {
+                    MouseButton::Right
+                } else {
+                    return None;
+                };
+                Some(Drag {
+                    button,
+                    dx: (position.x - last.x) as f32,
+                    dy: (position.y - last.y) as f32,
+                })
+            }
+            // Buttons let go of outside the window never report being released
+            WindowEvent::CursorLeft { .. } => {
+                *self = Mouse::default();
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn cursor(&self) -> Option<PhysicalPosition<f64>> {
+        self.cursor
+    }
+}
@@ -0,0 +1,205 @@
+use super::{scroll_lines, Camera, Mouse, FOV, ZFAR, ZNEAR};
+use math::{Mat4, Quaternion, Vec3};
+use winit::dpi::PhysicalPosition;
+use winit::event::{MouseButton, WindowEvent};
+
+// World units moved per pixel dragged, for each unit away from the target
+const PAN_SPEED: f32 = 0.0025;
+// Each line scrolled moves this fraction of the way to or from the target
+const ZOOM_STEP: f32 = 0.1;
+const MIN_DISTANCE: f32 = 0.05;
+
+///
+/// An arcball around a target point. Dragging with the left button rolls the
+/// scene as if the cursor were holding a ball that fills the window, so it can
+/// be turned any way at all, over the top included. Dragging with the right
+/// button moves the target across the screen and scrolling moves closer or
+/// further away.
+///
+#[derive(Copy, Clone, Debug)]
+pub struct OrbitCamera {
+    target: Vec3,
+    distance: f32,
+    // Turns camera space into world space, the eye is along its +z from the target
+    rotation: Quaternion,
+    // Framebuffer size in pixels, which the ball is fitted to
+    size: (u32, u32),
+    mouse: Mouse,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        Self::new(Vec3::ZERO, 1.0)
+    }
+}
+
+impl OrbitCamera {
+    // Looks at `target` along -z from `distance` away
+    pub fn new(target: Vec3, distance: f32) -> Self {
+        OrbitCamera {
+            target,
+            distance: distance.max(MIN_DISTANCE),
+            rotation: Quaternion::IDENTITY,
+            size: (800, 800),
+            mouse: Mouse::default(),
+        }
+    }
+
+    pub fn eye(&self) -> Vec3 {
+        self.target + self.rotation * Vec3::Z * self.distance
+    }
+
+    // The point on the ball under a pixel, with +z towards the viewer.
+    // The ball fills the shorter side of the window and pixels outside it go to its rim
+    fn ball_point(&self, pixel: PhysicalPosition<f64>) -> Vec3 {
+        let (width, height) = (self.size.0 as f32, self.size.1 as f32);
+        let radius = width.min(height) / 2.0;
+        let x = (pixel.x as f32 - width / 2.0) / radius;
+        let y = (height / 2.0 - pixel.y as f32) / radius;
+        let length_sq = x * x + y * y;
+        if length_sq > 1.0 {
+            Vec3::new(x, y, 0.0) / length_sq.sqrt()
+        } else {
+            Vec3::new(x, y, (1.0 - length_sq).sqrt())
+        }
+    }
+
+    // Rolls the ball from under one pixel to under another, the camera going the other way
+    fn turn(&mut self, from: PhysicalPosition<f64>, to: PhysicalPosition<f64>) {
+        let roll = Quaternion::from_rotation_arc(self.ball_point(from), self.ball_point(to));
+        self.rotation = (self.rotation * roll.inverse()).normalize();
+    }
+
+    // Moves the target by screen space amounts, scaled so it keeps up with the cursor
+    fn pan(&mut self, dx: f32, dy: f32) {
+        let (right, up) = (self.rotation * Vec3::X, self.rotation * Vec3::Y);
+        let scale = self.distance * PAN_SPEED;
+        self.target += right * (-dx * scale) + up * (dy * scale);
+    }
+}
+
+impl Camera for OrbitCamera {
+    fn view(&self) -> Mat4 {
+        Mat4::look_at(self.eye(), self.target, self.rotation * Vec3::Y)
+    }
+
+    fn projection(&self, width: u32, height: u32) -> Mat4 {
+        Mat4::perspective(FOV, width as f32 / height as f32, ZNEAR, ZFAR)
+    }
+
+    fn handle_event(&mut self, event: &WindowEvent) {
+        if let WindowEvent::MouseWheel { delta, .. } = event {
+            let distance = self.distance * (1.0 - ZOOM_STEP).powf(scroll_lines(delta));
+            self.distance = distance.max(MIN_DISTANCE);
+        }
+        match (self.mouse.handle_event(event), self.mouse.cursor()) {
+            (Some(drag), Some(to)) if drag.button == MouseButton::Left => {
+                let from = PhysicalPosition::new(to.x - drag.dx as f64, to.y - drag.dy as f64);
+                self.turn(from, to);
+            }
+            (Some(drag), _) => self.pan(drag.dx, drag.dy),
+            (None, _) => (),
+        }
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.size = (width.max(1), height.max(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_6;
+
+    const DISTANCE: f32 = 2.0;
+
+    fn camera() -> OrbitCamera {
+        let mut camera = OrbitCamera::new(Vec3::new(1.0, 2.0, 3.0), DISTANCE);
+        camera.resize(800, 600);
+        camera
+    }
+
+    fn pixel(x: f64, y: f64) -> PhysicalPosition<f64> {
+        PhysicalPosition::new(x, y)
+    }
+
+    // The target is always straight ahead at the same distance
+    fn check_view(camera: &OrbitCamera) {
+        let target = camera.view().transform_point(camera.target);
+        assert!(
+            target.approx_eq(Vec3::new(0.0, 0.0, -DISTANCE), 1e-4),
+            "{:?}",
+            target
+        );
+        assert!((camera.eye().distance(camera.target) - DISTANCE).abs() < 1e-4);
+    }
+
+    #[test]
+    fn starts_looking_down_minus_z() {
+        let camera = camera();
+        assert!(camera.eye().approx_eq(Vec3::new(1.0, 2.0, 5.0), 1e-6));
+        check_view(&camera);
+    }
+
+    #[test]
+    fn the_ball_fills_the_shorter_side() {
+        let camera = camera();
+        assert!(camera
+            .ball_point(pixel(400.0, 300.0))
+            .approx_eq(Vec3::Z, 1e-6));
+        assert!(camera
+            .ball_point(pixel(700.0, 300.0))
+            .approx_eq(Vec3::X, 1e-6));
+        assert!(camera
+            .ball_point(pixel(400.0, 0.0))
+            .approx_eq(Vec3::Y, 1e-6));
+        // Off the ball is on its rim
+        let rim = camera.ball_point(pixel(800.0, 300.0));
+        assert!(rim.approx_eq(Vec3::X, 1e-6), "{:?}", rim);
+    }
+
+    #[test]
+    fn dragging_rolls_the_scene_with_the_cursor() {
+        let mut camera = camera();
+        // Halfway across the ball's radius is 30 degrees around it
+        camera.turn(pixel(400.0, 300.0), pixel(550.0, 300.0));
+        // The scene turns right, so the camera swings left around it
+        let (sin, cos) = FRAC_PI_6.sin_cos();
+        let eye = camera.target + Vec3::new(-sin, 0.0, cos) * DISTANCE;
+        assert!(camera.eye().approx_eq(eye, 1e-5), "{:?}", camera.eye());
+        check_view(&camera);
+
+        camera.turn(pixel(550.0, 300.0), pixel(400.0, 300.0));
+        assert!(camera.rotation.approx_eq(Quaternion::IDENTITY, 1e-5));
+    }
+
+    #[test]
+    fn turning_goes_over_the_top() {
+        let mut camera = camera();
+        // Centre to bottom edge turns a quarter of the way round each time
+        camera.turn(pixel(400.0, 300.0), pixel(400.0, 600.0));
+        let above = camera.target + Vec3::Y * DISTANCE;
+        assert!(camera.eye().approx_eq(above, 1e-4), "{:?}", camera.eye());
+        check_view(&camera);
+
+        camera.turn(pixel(400.0, 300.0), pixel(400.0, 600.0));
+        let behind = camera.target - Vec3::Z * DISTANCE;
+        assert!(camera.eye().approx_eq(behind, 1e-4), "{:?}", camera.eye());
+        check_view(&camera);
+        // Upside down now, which a turntable can't get to
+        assert!((camera.rotation * Vec3::Y).approx_eq(-Vec3::Y, 1e-4));
+    }
+
+    #[test]
+    fn panning_follows_the_turned_screen() {
+        let mut camera = camera();
+        camera.turn(pixel(400.0, 300.0), pixel(400.0, 600.0));
+        let before = camera.target;
+        // Looking straight down, screen up is world -z
+        camera.pan(0.0, 100.0);
+        let moved = camera.target - before;
+        assert!(moved.approx_eq(Vec3::new(0.0, 0.0, -100.0 * DISTANCE * PAN_SPEED), 1e-5));
+        check_view(&camera);
+    }
+}
@@ -0,0 +1,95 @@
+use super::{scroll_lines, Camera, Mouse, ZFAR, ZNEAR};
+use math::{Mat4, Vec2};
+use winit::event::WindowEvent;
+
+// Each line scrolled zooms by this factor
+const ZOOM_STEP: f32 = 1.1;
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 1000.0;
+
+///
+/// Flat orthographic camera looking down -z, for scenes laid out on a plane.
+/// Dragging with either button pans and scrolling zooms around the cursor.
+/// At a zoom of 1 the screen is 2 units tall.
+///
+#[derive(Copy, Clone, Debug)]
+pub struct PanZoomCamera {
+    // The point in the middle of the screen
+    centre: Vec2,
+    zoom: f32,
+    // Framebuffer size in pixels, to turn cursor moves into world units
+    size: (u32, u32),
+    mouse: Mouse,
+}
+
+impl Default for PanZoomCamera {
+    fn default() -> Self {
+        PanZoomCamera {
+            centre: Vec2::ZERO,
+            zoom: 1.0,
+            size: (800, 800),
+            mouse: Mouse::default(),
+        }
+    }
+}
+
+impl PanZoomCamera {
+    fn units_per_pixel(&self) -> f32 {
+        2.0 / (self.zoom * self.size.1 as f32)
+    }
+
+    // The world point under a pixel of the framebuffer
+    fn world_at(&self, x: f64, y: f64) -> Vec2 {
+        let scale = self.units_per_pixel();
+        self.centre
+            + Vec2::new(
+                (x as f32 - self.size.0 as f32 / 2.0) * scale,
+                (self.size.1 as f32 / 2.0 - y as f32) * scale,
+            )
+    }
+
+    // Zooms by `factor`, keeping the world point under the cursor where it is
+    fn zoom_by(&mut self, factor: f32) {
+        let anchor = self.mouse.cursor().map(|c| self.world_at(c.x, c.y));
+        let before = anchor.unwrap_or(self.centre);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let after = self
+            .mouse
+            .cursor()
+            .map_or(self.centre, |c| self.world_at(c.x, c.y));
+        self.centre += before - after;
+    }
+}
+
+impl Camera for PanZoomCamera {
+    fn view(&self) -> Mat4 {
+        Mat4::from_translation(-self.centre.extend(0.0))
+    }
+
+    fn projection(&self, width: u32, height: u32) -> Mat4 {
+        let half_height = 1.0 / self.zoom;
+        let half_width = half_height * width as f32 / height as f32;
+        Mat4::ortho(
+            -half_width,
+            half_width,
+            -half_height,
+            half_height,
+            ZNEAR,
+            ZFAR,
+        )
+    }
+
+    fn handle_event(&mut self, event: &WindowEvent) {
+        if let WindowEvent::MouseWheel { delta, .. } = event {
+            self.zoom_by(ZOOM_STEP.powf(scroll_lines(delta)));
+        }
+        if let Some(drag) = self.mouse.handle_event(event) {
+            let scale = self.units_per_pixel();
+            self.centre += Vec2::new(-drag.dx, drag.dy) * scale;
+        }
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.size = (width, height);
+    }
+}
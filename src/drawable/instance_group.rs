@@ -1,29 +1,27 @@
 use super::shape::HasShape;
 use drawable::{DrawUniforms, Drawable};
-use glium::index::NoIndices;
 use glium::*;
-use rayon::prelude::*;
+use math::Vec3;
+use shader::ProgramLibrary;
 use std::ops::{Index, IndexMut};
-use util::attribute::Attr;
-use util::bufferable::{BufferObject, Bufferable};
+use util::attribute::Instance;
+use util::bufferable::{Backend, InstanceBuffer};
+use util::Manipulate;
 
 pub struct InstanceGroup<T>
 where
     T: HasShape,
 {
     shape: T,
-    transforms: BufferObject<Attr>,
+    transforms: InstanceBuffer,
 }
 
-#[allow(dead_code)]
 impl<T> InstanceGroup<T>
 where
     T: HasShape,
 {
-    pub fn new(shape: T, num: usize, display: &Display) -> Self {
-        let mut transforms = vec![Attr::default(); num];
-        transforms.par_iter_mut().for_each(|p| p.randomize());
-        let transforms = Attr::new_vbo(display, &transforms);
+    pub fn from_transforms(shape: T, transforms: &[Instance], display: &dyn Backend) -> Self {
+        let transforms = InstanceBuffer::new(display, transforms);
         InstanceGroup { shape, transforms }
     }
 }
@@ -32,7 +30,7 @@ impl<T> Index<usize> for InstanceGroup<T>
 where
     T: HasShape,
 {
-    type Output = Attr;
+    type Output = Instance;
 
     fn index(&self, index: usize) -> &Self::Output {
         &self.transforms[index]
@@ -52,18 +50,25 @@ impl<T: HasShape> Drawable for InstanceGroup<T> {
     fn draw(
         &self,
         target: &mut Frame,
-        program: &Program,
+        programs: &ProgramLibrary,
         params: &DrawParameters,
         uniforms: DrawUniforms,
     ) {
+        self.transforms.update_buffer();
         target
             .draw(
                 (self.shape.ref_vbo(), self.transforms.per_instance()),
-                &NoIndices(*self.shape.ref_index()),
-                &program,
+                self.shape.indices(),
+                programs.get(self.shape.program()),
                 &uniforms,
-                &params,
+                &self.shape.draw_parameters(params),
             )
             .unwrap();
     }
 }
+
+impl<T: HasShape> Manipulate for InstanceGroup<T> {
+    fn rotate(&mut self, axis: Vec3, angle: f32) {
+        self.transforms.rotate(axis, angle);
+    }
+}
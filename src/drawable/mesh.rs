@@ -0,0 +1,395 @@
+use math::{Vec2, Vec3};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use util::vertex::F32vec3;
+
+const TWO_PI: f32 = PI * 2.0;
+
+///
+/// Triangles on the CPU, three indices each, wound anticlockwise when seen from outside.
+/// The primitives are centred on the origin with y up, and have unit normals
+/// pointing away from the solid. Turn them into something drawable with `Shape::from_mesh`.
+///
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<F32vec3>,
+    pub indices: Vec<u32>,
+    // Whether the vertices' uv coordinates mean anything
+    pub uvs: bool,
+}
+
+impl Mesh {
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [F32vec3; 3]> + '_ {
+        self.indices.chunks_exact(3).map(move |triangle| {
+            [
+                self.vertices[triangle[0] as usize],
+                self.vertices[triangle[1] as usize],
+                self.vertices[triangle[2] as usize],
+            ]
+        })
+    }
+
+    // Averages the normals of the triangles around each vertex, bigger ones counting for more
+    pub fn smooth_normals(&mut self) {
+        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| self.vertices[triangle[i] as usize].pos());
+            let normal = (b - a).cross(c - a);
+            triangle.iter().for_each(|&i| normals[i as usize] += normal);
+        }
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = normal.try_normalize().unwrap_or(Vec3::Y).into();
+        }
+    }
+
+    // An axis aligned cube `size` across, each face split into segments by segments squares
+    pub fn cube(size: f32, segments: u32) -> Self {
+        assert!(segments >= 1, "a cube needs at least one segment per side");
+        // Each face's normal with the directions u and v run along it, u cross v being the normal
+        let faces = [
+            (Vec3::X, -Vec3::Z, Vec3::Y),
+            (-Vec3::X, Vec3::Z, Vec3::Y),
+            (Vec3::Y, Vec3::X, -Vec3::Z),
+            (-Vec3::Y, Vec3::X, Vec3::Z),
+            (Vec3::Z, Vec3::X, Vec3::Y),
+            (-Vec3::Z, -Vec3::X, Vec3::Y),
+        ];
+        let mut mesh = Mesh::with_uvs();
+        for &(normal, right, up) in faces.iter() {
+            mesh.grid(segments, segments, |u, v| {
+                let position = normal * 0.5 + right * (u - 0.5) + up * (v - 0.5);
+                (position * size, normal)
+            });
+        }
+        mesh
+    }
+
+    // A flat grid on the xz plane facing up, `cols` squares across and `rows` deep
+    pub fn plane(width: f32, depth: f32, cols: u32, rows: u32) -> Self {
+        assert!(cols >= 1 && rows >= 1, "a plane needs at least one square");
+        let mut mesh = Mesh::with_uvs();
+        mesh.grid(cols, rows, |u, v| {
+            let position = Vec3::new((u - 0.5) * width, 0.0, (0.5 - v) * depth);
+            (position, Vec3::Y)
+        });
+        mesh
+    }
+
+    ///
+    /// A sphere made of `segments` slices around the y axis and `rings` bands from pole to pole.
+    /// The seam and the poles repeat vertices so the uvs can wrap,
+    /// but the slivers that would meet at each pole are left out.
+    ///
+    pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Self {
+        assert!(
+            segments >= 3 && rings >= 2,
+            "a sphere needs at least 3 segments and 2 rings"
+        );
+        let mut mesh = Mesh::with_uvs();
+        mesh.grid(segments, rings, |u, v| {
+            let normal = around_y(u, (PI * (1.0 - v)).cos(), (PI * v).sin());
+            (normal * radius, normal)
+        });
+        mesh
+    }
+
+    ///
+    /// A sphere made by splitting each face of an icosahedron into four, `subdivisions` times.
+    /// The triangles are much more even than a uv sphere's, but the uvs are only
+    /// projected from the direction so the column of triangles on the seam smears the texture.
+    ///
+    pub fn icosphere(radius: f32, subdivisions: u32) -> Self {
+        let t = (1.0 + 5f32.sqrt()) / 2.0;
+        let mut points: Vec<Vec3> = [
+            (-1.0, t, 0.0),
+            (1.0, t, 0.0),
+            (-1.0, -t, 0.0),
+            (1.0, -t, 0.0),
+            (0.0, -1.0, t),
+            (0.0, 1.0, t),
+            (0.0, -1.0, -t),
+            (0.0, 1.0, -t),
+            (t, 0.0, -1.0),
+            (t, 0.0, 1.0),
+            (-t, 0.0, -1.0),
+            (-t, 0.0, 1.0),
+        ]
+        .iter()
+        .map(|&(x, y, z)| Vec3::new(x, y, z).normalize())
+        .collect();
+        let mut faces: Vec<[u32; 3]> = vec![
+            [0, 11, 5],
+            [0, 5, 1],
+            [0, 1, 7],
+            [0, 7, 10],
+            [0, 10, 11],
+            [1, 5, 9],
+            [5, 11, 4],
+            [11, 10, 2],
+            [10, 7, 6],
+            [7, 1, 8],
+            [3, 9, 4],
+            [3, 4, 2],
+            [3, 2, 6],
+            [3, 6, 8],
+            [3, 8, 9],
+            [4, 9, 5],
+            [2, 4, 11],
+            [6, 2, 10],
+            [8, 6, 7],
+            [9, 8, 1],
+        ];
+
+        for _ in 0..subdivisions {
+            // Edges are shared by two faces, so each midpoint is only added once
+            let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+            let mut midpoint = |a: u32, b: u32| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    let point = (points[a as usize] + points[b as usize]).normalize();
+                    points.push(point);
+                    points.len() as u32 - 1
+                })
+            };
+            faces = faces
+                .iter()
+                .flat_map(|&[a, b, c]| {
+                    let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                    vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+                })
+                .collect();
+        }
+
+        let vertices = points
+            .iter()
+            .map(|&normal| {
+                let u = (-normal.z).atan2(normal.x) / TWO_PI;
+                let uv = Vec2::new(u - u.floor(), 1.0 - normal.y.acos() / PI);
+                F32vec3::new(normal * radius, normal).with_uv(uv)
+            })
+            .collect();
+        Mesh {
+            vertices,
+            indices: faces.iter().flatten().copied().collect(),
+            uvs: true,
+        }
+    }
+
+    // A capped tube along the y axis, split into `stacks` bands up its side
+    pub fn cylinder(radius: f32, height: f32, segments: u32, stacks: u32) -> Self {
+        assert!(
+            segments >= 3 && stacks >= 1,
+            "a cylinder needs at least 3 segments and 1 stack"
+        );
+        let mut mesh = Mesh::with_uvs();
+        mesh.grid(segments, stacks, |u, v| {
+            let normal = around_y(u, 0.0, 1.0);
+            let position = normal * radius + Vec3::Y * (v - 0.5) * height;
+            (position, normal)
+        });
+        mesh.cap(radius, -height / 2.0, segments, -Vec3::Y);
+        mesh.cap(radius, height / 2.0, segments, Vec3::Y);
+        mesh
+    }
+
+    // A cone along the y axis with its point at the top, split into `stacks` bands up its side
+    pub fn cone(radius: f32, height: f32, segments: u32, stacks: u32) -> Self {
+        assert!(
+            segments >= 3 && stacks >= 1,
+            "a cone needs at least 3 segments and 1 stack"
+        );
+        let mut mesh = Mesh::with_uvs();
+        mesh.grid(segments, stacks, |u, v| {
+            let out = around_y(u, 0.0, 1.0);
+            let position = out * radius * (1.0 - v) + Vec3::Y * (v - 0.5) * height;
+            // Leans up by the slope of the side, the point gets one per segment
+            let normal = (out * height + Vec3::Y * radius).normalize();
+            (position, normal)
+        });
+        mesh.cap(radius, -height / 2.0, segments, -Vec3::Y);
+        mesh
+    }
+
+    // A ring around the y axis, `major` out to the middle of a tube `minor` in radius
+    pub fn torus(major: f32, minor: f32, segments: u32, sides: u32) -> Self {
+        assert!(
+            segments >= 3 && sides >= 3,
+            "a torus needs at least 3 segments and 3 sides"
+        );
+        let mut mesh = Mesh::with_uvs();
+        mesh.grid(segments, sides, |u, v| {
+            let out = around_y(u, 0.0, 1.0);
+            let angle = TWO_PI * v;
+            let normal = out * angle.cos() + Vec3::Y * angle.sin();
+            (out * major + normal * minor, normal)
+        });
+        mesh
+    }
+
+    fn with_uvs() -> Self {
+        Mesh {
+            uvs: true,
+            ..Mesh::default()
+        }
+    }
+
+    ///
+    /// Adds a (cols + 1) by (rows + 1) grid of vertices from `at`, which is given u and v
+    /// from 0 to 1 and returns a position and normal, and two triangles for each square.
+    /// The outside is the side u cross v points to. Triangles that have no area,
+    /// like the ones meeting at a pole, are skipped.
+    ///
+    fn grid<F>(&mut self, cols: u32, rows: u32, at: F)
+    where
+        F: Fn(f32, f32) -> (Vec3, Vec3),
+    {
+        let start = self.vertices.len() as u32;
+        for row in 0..=rows {
+            for col in 0..=cols {
+                let (u, v) = (col as f32 / cols as f32, row as f32 / rows as f32);
+                let (position, normal) = at(u, v);
+                let vertex = F32vec3::new(position, normal).with_uv(Vec2::new(u, v));
+                self.vertices.push(vertex);
+            }
+        }
+        for row in 0..rows {
+            for col in 0..cols {
+                let corner = start + row * (cols + 1) + col;
+                let above = corner + cols + 1;
+                self.triangle([corner, corner + 1, above + 1]);
+                self.triangle([corner, above + 1, above]);
+            }
+        }
+    }
+
+    // A disc at height `y` facing along `normal`, which is up or down the y axis
+    fn cap(&mut self, radius: f32, y: f32, segments: u32, normal: Vec3) {
+        let centre = self.vertices.len() as u32;
+        let middle = F32vec3::new(Vec3::Y * y, normal).with_uv(Vec2::new(0.5, 0.5));
+        self.vertices.push(middle);
+        for segment in 0..segments {
+            let out = around_y(segment as f32 / segments as f32, 0.0, 1.0);
+            let uv = Vec2::new(0.5 + out.x * 0.5, 0.5 - out.z * 0.5 * normal.y);
+            let vertex = F32vec3::new(out * radius + Vec3::Y * y, normal).with_uv(uv);
+            self.vertices.push(vertex);
+        }
+        for segment in 0..segments {
+            let (a, b) = (centre + 1 + segment, centre + 1 + (segment + 1) % segments);
+            if normal.y > 0.0 {
+                self.triangle([centre, a, b]);
+            } else {
+                self.triangle([centre, b, a]);
+            }
+        }
+    }
+
+    fn triangle(&mut self, triangle: [u32; 3]) {
+        let [a, b, c] = triangle.map(|i| self.vertices[i as usize].pos());
+        let (ab, ac) = (b - a, c - a);
+        if ab.cross(ac).length() > 1e-6 * (ab.length_sq() + ac.length_sq()) {
+            self.indices.extend_from_slice(&triangle);
+        }
+    }
+}
+
+// `u` of the way anticlockwise around the y axis seen from above, starting on +x
+fn around_y(u: f32, y: f32, radius: f32) -> Vec3 {
+    let angle = TWO_PI * u;
+    Vec3::new(angle.cos() * radius, y, -angle.sin() * radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///
+    /// Checks the counts, that every normal is a unit vector pointing away from
+    /// `inside`, which gives a point inside the solid near a vertex, and that every
+    /// triangle is wound anticlockwise seen from the side its normals point to.
+    ///
+    fn check(mesh: &Mesh, vertices: usize, triangles: usize, inside: impl Fn(Vec3) -> Vec3) {
+        assert_eq!(mesh.vertices.len(), vertices);
+        assert_eq!(mesh.triangle_count(), triangles);
+        for vertex in &mesh.vertices {
+            let (position, normal) = (vertex.pos(), Vec3::from(vertex.normal));
+            assert!((normal.length() - 1.0).abs() < 1e-5, "{:?}", vertex);
+            assert!(
+                normal.dot(position - inside(position)) > 0.0,
+                "{:?}",
+                vertex
+            );
+            assert!(vertex.uv.iter().all(|uv| (0.0..=1.0).contains(uv)));
+        }
+        for triangle in mesh.triangles() {
+            let [a, b, c] = triangle.map(|vertex| vertex.pos());
+            let normals = triangle
+                .iter()
+                .fold(Vec3::ZERO, |sum, vertex| sum + Vec3::from(vertex.normal));
+            assert!((b - a).cross(c - a).dot(normals) > 0.0, "{:?}", triangle);
+        }
+    }
+
+    #[test]
+    fn cube() {
+        check(&Mesh::cube(2.0, 3), 6 * 4 * 4, 12 * 3 * 3, |_| Vec3::ZERO);
+    }
+
+    #[test]
+    fn plane() {
+        let mesh = Mesh::plane(2.0, 1.0, 4, 3);
+        check(&mesh, 5 * 4, 2 * 4 * 3, |p| p - Vec3::Y);
+    }
+
+    #[test]
+    fn uv_sphere() {
+        // The triangles that would meet at the poles have no area and are left out
+        let mesh = Mesh::uv_sphere(1.5, 16, 8);
+        check(&mesh, 17 * 9, 2 * 16 * 7, |_| Vec3::ZERO);
+    }
+
+    #[test]
+    fn icosphere() {
+        check(&Mesh::icosphere(1.0, 0), 12, 20, |_| Vec3::ZERO);
+        check(&Mesh::icosphere(1.0, 2), 10 * 16 + 2, 20 * 16, |_| {
+            Vec3::ZERO
+        });
+    }
+
+    #[test]
+    fn cylinder() {
+        // The side, then a centre and a ring for each cap
+        let mesh = Mesh::cylinder(0.5, 2.0, 12, 3);
+        check(&mesh, 13 * 4 + 2 * 13, 2 * 12 * 3 + 2 * 12, |_| Vec3::ZERO);
+    }
+
+    #[test]
+    fn cone() {
+        // Every square in the top band loses the triangle that would meet at the point
+        let mesh = Mesh::cone(1.0, 2.0, 12, 3);
+        let centroid = Vec3::new(0.0, -0.5, 0.0);
+        check(&mesh, 13 * 4 + 13, 2 * 12 * 3 - 12 + 12, |_| centroid);
+    }
+
+    #[test]
+    fn torus() {
+        let (major, minor) = (1.0, 0.25);
+        let mesh = Mesh::torus(major, minor, 16, 8);
+        // Normals point away from the middle of the tube
+        let middle = |p: Vec3| Vec3::new(p.x, 0.0, p.z).normalize() * major;
+        check(&mesh, 17 * 9, 2 * 16 * 8, middle);
+    }
+
+    #[test]
+    fn smoothing_a_sphere_keeps_its_normals_pointing_out() {
+        let mut mesh = Mesh::icosphere(2.0, 2);
+        mesh.smooth_normals();
+        check(&mesh, 162, 320, |_| Vec3::ZERO);
+        for vertex in &mesh.vertices {
+            let radial = vertex.pos().normalize();
+            assert!(Vec3::from(vertex.normal).dot(radial) > 0.99, "{:?}", vertex);
+        }
+    }
+}
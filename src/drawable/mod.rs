@@ -1,47 +1,67 @@
 pub(crate) mod instance_group;
+pub(crate) mod mesh;
+pub(crate) mod scene_graph;
 pub(crate) mod shape;
 pub(crate) mod shape_group;
 
+use camera::{Camera, FOV, ZFAR, ZNEAR};
 use glium::uniforms::UniformValue;
-use glium::{DrawParameters, Frame, Program};
-use std::f32::consts::PI;
+use glium::{DrawParameters, Frame};
+use math::Mat4;
+use shader::ProgramLibrary;
 
 #[derive(Copy, Clone)]
 pub struct DrawUniforms {
-    perspective: [[f32; 4]; 4],
+    view: Mat4,
+    projection: Mat4,
+    // World transform of the scene graph node being drawn, applied on top of each instance's own
+    node: Mat4,
     u_light: [f32; 3],
+    // How far the frame is between the previous simulation tick and the latest, not sent to shaders
+    alpha: f32,
 }
 
+// Looks down -z from the origin with a square framebuffer
 impl Default for DrawUniforms {
     fn default() -> Self {
-        let u_light = [-1.0, 0.4, 0.9f32];
-        let perspective = {
-            let (width, height) = (800.0, 800.0);
-            let aspect_ratio = height as f32 / width as f32;
-
-            let fov: f32 = PI / 2.0;
-            let zfar = 1024.0;
-            let znear = 0.1;
-
-            let f = 1.0 / (fov / 2.0).tan();
-
-            [
-                [f * aspect_ratio, 0.0, 0.0, 0.0],
-                [0.0, f, 0.0, 0.0],
-                [0.0, 0.0, (zfar + znear) / (zfar - znear), 1.0],
-                [0.0, 0.0, -(2.0 * zfar * znear) / (zfar - znear), 0.0],
-            ]
-        };
+        Self::new(Mat4::IDENTITY, Mat4::perspective(FOV, 1.0, ZNEAR, ZFAR))
+    }
+}
+
+impl DrawUniforms {
+    pub fn new(view: Mat4, projection: Mat4) -> Self {
         Self {
-            u_light,
-            perspective,
+            view,
+            projection,
+            node: Mat4::IDENTITY,
+            u_light: [-1.0, 0.4, 0.9],
+            alpha: 1.0,
         }
     }
+
+    // Uniforms for what `camera` sees on a framebuffer of the given size in pixels
+    pub fn from_camera(camera: &dyn Camera, width: u32, height: u32) -> Self {
+        Self::new(camera.view(), camera.projection(width, height))
+    }
+
+    pub fn with_alpha(self, alpha: f32) -> Self {
+        Self { alpha, ..self }
+    }
+
+    pub fn with_node(self, node: Mat4) -> Self {
+        Self { node, ..self }
+    }
+
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
 }
 
 impl glium::uniforms::Uniforms for DrawUniforms {
     fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
-        f("perspective", UniformValue::Mat4(self.perspective));
+        f("view", UniformValue::Mat4(self.view.cols));
+        f("projection", UniformValue::Mat4(self.projection.cols));
+        f("node", UniformValue::Mat4(self.node.cols));
         f("u_light", UniformValue::Vec3(self.u_light));
     }
 }
@@ -50,10 +70,33 @@ pub trait Drawable {
     fn draw(
         &self,
         target: &mut Frame,
-        program: &Program,
+        programs: &ProgramLibrary,
         params: &DrawParameters,
         uniforms: DrawUniforms,
     );
 
     fn update(&mut self) {}
+
+    // Called with the new framebuffer size in pixels whenever it changes
+    fn resize(&mut self, _width: u32, _height: u32) {}
+}
+
+impl<T: Drawable + ?Sized> Drawable for Box<T> {
+    fn draw(
+        &self,
+        target: &mut Frame,
+        programs: &ProgramLibrary,
+        params: &DrawParameters,
+        uniforms: DrawUniforms,
+    ) {
+        (**self).draw(target, programs, params, uniforms);
+    }
+
+    fn update(&mut self) {
+        (**self).update();
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        (**self).resize(width, height);
+    }
 }
@@ -0,0 +1,254 @@
+use drawable::instance_group::InstanceGroup;
+use drawable::shape::Shape;
+use drawable::{DrawUniforms, Drawable};
+use glium::{DrawParameters, Frame};
+use math::{Mat4, Transform, Vec3};
+use shader::ProgramLibrary;
+use std::cell::Cell;
+use util::attribute::Instance;
+use util::bufferable::Backend;
+use util::Manipulate;
+
+// Handle to a node, only meaningful to the graph that made it
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+struct Node {
+    local: Transform,
+    // Local to world matrix, out of date while `dirty` is set
+    world: Cell<Mat4>,
+    dirty: Cell<bool>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    attachments: Vec<InstanceGroup<Shape>>,
+}
+
+///
+/// A tree of transforms with shapes hanging off it.
+/// Each node is placed relative to its parent, so moving or turning a node
+/// carries everything below it along. World matrices are only worked out
+/// when they're asked for, then kept until the node or one above it changes.
+///
+#[derive(Default)]
+pub struct SceneGraph {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_root(&mut self, local: Transform) -> NodeId {
+        self.add_node(None, local)
+    }
+
+    pub fn add_child(&mut self, parent: NodeId, local: Transform) -> NodeId {
+        self.add_node(Some(parent), local)
+    }
+
+    fn add_node(&mut self, parent: Option<NodeId>, local: Transform) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node {
+            local,
+            world: Cell::new(Mat4::IDENTITY),
+            dirty: Cell::new(true),
+            parent,
+            children: vec![],
+            attachments: vec![],
+        });
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        id
+    }
+
+    // Changes a node's transform in place, everything below it follows
+    pub fn update_local<F: FnOnce(&mut Transform)>(&mut self, id: NodeId, f: F) {
+        f(&mut self.nodes[id.0].local);
+        self.mark_dirty(id);
+    }
+
+    pub fn rotate_node(&mut self, id: NodeId, axis: Vec3, angle: f32) {
+        self.update_local(id, |t| t.rotate(axis, angle));
+    }
+
+    // A node whose world matrix is out of date has every node below it out of date too,
+    // so there's no need to go further down once one is found already marked
+    fn mark_dirty(&self, id: NodeId) {
+        let node = &self.nodes[id.0];
+        if node.dirty.replace(true) {
+            return;
+        }
+        node.children
+            .iter()
+            .for_each(|&child| self.mark_dirty(child));
+    }
+
+    // Takes points from the node's space to the world's
+    pub fn world_matrix(&self, id: NodeId) -> Mat4 {
+        let node = &self.nodes[id.0];
+        if node.dirty.get() {
+            let local = node.local.matrix();
+            let world = match node.parent {
+                Some(parent) => self.world_matrix(parent) * local,
+                None => local,
+            };
+            node.world.set(world);
+            node.dirty.set(false);
+        }
+        node.world.get()
+    }
+
+    pub fn world_position(&self, id: NodeId) -> Vec3 {
+        self.world_matrix(id).transform_point(Vec3::ZERO)
+    }
+
+    // Hangs a single copy of `shape` off the node, drawn at the node's origin
+    pub fn attach_shape(
+        &mut self,
+        id: NodeId,
+        shape: Shape,
+        display: &dyn Backend,
+    ) -> &mut InstanceGroup<Shape> {
+        let group = InstanceGroup::from_transforms(shape, &[Instance::default()], display);
+        self.attach_instances(id, group);
+        let attachments = &mut self.nodes[id.0].attachments;
+        attachments.last_mut().unwrap()
+    }
+
+    // Instances are placed relative to the node they hang off
+    pub fn attach_instances(&mut self, id: NodeId, group: InstanceGroup<Shape>) {
+        self.nodes[id.0].attachments.push(group);
+    }
+}
+
+impl Drawable for SceneGraph {
+    fn draw(
+        &self,
+        target: &mut Frame,
+        programs: &ProgramLibrary,
+        params: &DrawParameters,
+        uniforms: DrawUniforms,
+    ) {
+        for (index, node) in self.nodes.iter().enumerate() {
+            if node.attachments.is_empty() {
+                continue;
+            }
+            let uniforms = uniforms.with_node(self.world_matrix(NodeId(index)));
+            node.attachments
+                .iter()
+                .for_each(|group| group.draw(target, programs, params, uniforms));
+        }
+    }
+}
+
+// Turns each tree around its root, taking the rest of it along
+impl Manipulate for SceneGraph {
+    fn rotate(&mut self, axis: Vec3, angle: f32) {
+        for index in 0..self.roots.len() {
+            let root = self.roots[index];
+            self.rotate_node(root, axis, angle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+    use util::bufferable::Headless;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn is_dirty(graph: &SceneGraph, id: NodeId) -> bool {
+        graph.nodes[id.0].dirty.get()
+    }
+
+    // A root with two children one unit either side of it, and a grandchild one unit above the first
+    fn family() -> (SceneGraph, [NodeId; 4]) {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_root(Transform::IDENTITY);
+        let left = graph.add_child(root, Transform::from_translation(-Vec3::X));
+        let right = graph.add_child(root, Transform::from_translation(Vec3::X));
+        let above = graph.add_child(left, Transform::from_translation(Vec3::Y));
+        (graph, [root, left, right, above])
+    }
+
+    #[test]
+    fn moving_a_parent_carries_its_children() {
+        let (mut graph, [root, left, right, above]) = family();
+        assert!(graph
+            .world_position(above)
+            .approx_eq(Vec3::new(-1.0, 1.0, 0.0), EPSILON));
+
+        graph.update_local(root, |t| t.translate(Vec3::new(0.0, 0.0, 2.0)));
+        let expected = [(left, -1.0, 0.0), (right, 1.0, 0.0), (above, -1.0, 1.0)];
+        for &(id, x, y) in expected.iter() {
+            let position = graph.world_position(id);
+            assert!(
+                position.approx_eq(Vec3::new(x, y, 2.0), EPSILON),
+                "{:?}",
+                position
+            );
+        }
+
+        // A quarter turn about z takes +x to +y and +y to -x
+        graph.rotate_node(root, Vec3::Z, FRAC_PI_2);
+        let position = graph.world_position(above);
+        assert!(
+            position.approx_eq(Vec3::new(-1.0, -1.0, 2.0), EPSILON),
+            "{:?}",
+            position
+        );
+    }
+
+    #[test]
+    fn world_matrices_are_only_worked_out_when_asked_for() {
+        let (mut graph, [root, left, right, above]) = family();
+        assert!([root, left, right, above]
+            .iter()
+            .all(|&id| is_dirty(&graph, id)));
+
+        graph.world_matrix(above);
+        // Asking for a node brings it and everything above it up to date, and nothing else
+        assert!(!is_dirty(&graph, above) && !is_dirty(&graph, left) && !is_dirty(&graph, root));
+        assert!(is_dirty(&graph, right));
+
+        graph.world_matrix(right);
+        graph.update_local(root, |t| t.translate(Vec3::Z));
+        assert!([root, left, right, above]
+            .iter()
+            .all(|&id| is_dirty(&graph, id)));
+    }
+
+    #[test]
+    fn changing_a_child_leaves_its_parent_and_siblings_alone() {
+        let (mut graph, [root, left, right, above]) = family();
+        let before = graph.world_matrix(right);
+        graph.world_matrix(above);
+
+        graph.update_local(left, |t| t.translate(Vec3::Z));
+        assert!(is_dirty(&graph, left) && is_dirty(&graph, above));
+        assert!(!is_dirty(&graph, root) && !is_dirty(&graph, right));
+        // The sibling's cached matrix is still the one it had
+        assert_eq!(graph.nodes[right.0].world.get(), before);
+        assert!(graph
+            .world_position(above)
+            .approx_eq(Vec3::new(-1.0, 1.0, 1.0), EPSILON));
+    }
+
+    #[test]
+    fn attached_shapes_sit_at_the_node() {
+        let (mut graph, [_, left, _, _]) = family();
+        let shape = Shape::circle(&Headless, 0.5, 8);
+        graph.attach_shape(left, shape, &Headless)[0].color = [0.0, 1.0, 0.0, 1.0];
+
+        let attachments = &graph.nodes[left.0].attachments;
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0][0].transform, Transform::IDENTITY);
+        assert_eq!(attachments[0][0].color, [0.0, 1.0, 0.0, 1.0]);
+    }
+}
@@ -1,7 +1,10 @@
-use glium::index::PrimitiveType;
-use glium::{Display, VertexBuffer};
+use drawable::mesh::Mesh;
+use glium::index::{IndicesSource, NoIndices, PrimitiveType};
+use glium::{DrawParameters, VertexBuffer};
+use shader::ProgramHandle;
 use std::f32::consts::PI;
-use util::bufferable::{BufferObject, Bufferable};
+use util::bufferable::{Backend, BufferObject, Bufferable};
+use util::index_buffer::{IndexObject, Indices};
 use util::vertex::F32vec3;
 
 const TWO_PI: f32 = PI * 2.0;
@@ -10,40 +13,45 @@ const TWO_PI: f32 = PI * 2.0;
 pub struct Shape {
     pub vertices: BufferObject<F32vec3>,
     pub index_type: PrimitiveType,
-    pub id: usize,
+    // Vertices drawn in order when there aren't any
+    pub indices: Option<IndexObject>,
+    // Program the shape is drawn with
+    pub program: ProgramHandle,
 }
 
 impl Shape {
     // Default shape
-    pub fn triangle(display: &Display) -> Shape {
-        let vertex1 = F32vec3::from([1.0, -1.0, 0.0]) * 0.1;
-        let vertex2 = F32vec3::from([-1.0, -1.0, 0.0]) * 0.1;
-        let vertex3 = F32vec3::from([0.0, 1.0, 0.0]) * 0.1;
-        let vertices = F32vec3::new_vbo(display, &vec![vertex1, vertex2, vertex3]);
+    pub fn triangle(display: &dyn Backend) -> Shape {
+        let vertex1 = F32vec3::from([0.1, -0.1, 0.0]);
+        let vertex2 = F32vec3::from([-0.1, -0.1, 0.0]);
+        let vertex3 = F32vec3::from([0.0, 0.1, 0.0]);
+        let vertices = F32vec3::new_vbo(display, &[vertex1, vertex2, vertex3]);
         Shape {
             vertices,
             index_type: PrimitiveType::TrianglesList,
-            id: 0,
+            indices: None,
+            program: ProgramHandle::BASE,
         }
     }
 
-    pub fn quad(display: &Display, scl: f32) -> Shape {
+    pub fn quad(display: &dyn Backend, scl: f32) -> Shape {
         let mut vertices = vec![
             F32vec3::from([-1.0, 1.0, 0.0]),
             F32vec3::from([1.0, 1.0, 0.0]),
             F32vec3::from([-1.0, -1.0, 0.0]),
             F32vec3::from([1.0, -1.0, 0.0]),
         ];
-        vertices.iter_mut().for_each(|p| *p *= scl);
+        vertices.iter_mut().for_each(|p| p.set_pos(p.pos() * scl));
         let vertices = F32vec3::new_vbo(display, &vertices);
         Shape {
             vertices,
             index_type: PrimitiveType::TriangleStrip,
-            id: 0,
+            indices: None,
+            program: ProgramHandle::BASE,
         }
     }
 
-    pub fn circle(display: &Display, radius: f32, num: u32) -> Shape {
+    pub fn circle(display: &dyn Backend, radius: f32, num: u32) -> Shape {
         let mut vertices = vec![];
 
         let inc = TWO_PI / num as f32;
@@ -59,21 +67,90 @@ impl Shape {
         Shape {
             vertices,
             index_type: PrimitiveType::LineStrip,
-            id: 0,
+            indices: None,
+            program: ProgramHandle::BASE,
         }
     }
 
     pub fn from_vertices(
-        vertices: &Vec<F32vec3>,
+        vertices: &[F32vec3],
         index_type: PrimitiveType,
-        display: &Display,
+        display: &dyn Backend,
     ) -> Shape {
         Shape {
-            vertices: F32vec3::new_vbo(display, &vertices),
+            vertices: F32vec3::new_vbo(display, vertices),
             index_type,
-            id: 0,
+            indices: None,
+            program: ProgramHandle::BASE,
         }
     }
+
+    // Vertices drawn through an index buffer, the primitive comes from the indices
+    pub fn indexed(vertices: &[F32vec3], indices: IndexObject, display: &dyn Backend) -> Shape {
+        Shape {
+            vertices: F32vec3::new_vbo(display, vertices),
+            index_type: indices.primitive(),
+            indices: Some(indices),
+            program: ProgramHandle::BASE,
+        }
+    }
+
+    // Triangles from a mesh, indexed with 16 bits when there are few enough vertices
+    pub fn from_mesh(mesh: &Mesh, display: &dyn Backend) -> Shape {
+        let indices = Indices::compact(&mesh.indices);
+        let indices = IndexObject::new(display, PrimitiveType::TrianglesList, indices);
+        Shape::indexed(&mesh.vertices, indices, display)
+    }
+
+    pub fn cube(display: &dyn Backend, size: f32, segments: u32) -> Shape {
+        Shape::from_mesh(&Mesh::cube(size, segments), display)
+    }
+
+    pub fn plane(display: &dyn Backend, width: f32, depth: f32, cols: u32, rows: u32) -> Shape {
+        Shape::from_mesh(&Mesh::plane(width, depth, cols, rows), display)
+    }
+
+    pub fn uv_sphere(display: &dyn Backend, radius: f32, segments: u32, rings: u32) -> Shape {
+        Shape::from_mesh(&Mesh::uv_sphere(radius, segments, rings), display)
+    }
+
+    pub fn icosphere(display: &dyn Backend, radius: f32, subdivisions: u32) -> Shape {
+        Shape::from_mesh(&Mesh::icosphere(radius, subdivisions), display)
+    }
+
+    pub fn cylinder(
+        display: &dyn Backend,
+        radius: f32,
+        height: f32,
+        segments: u32,
+        stacks: u32,
+    ) -> Shape {
+        Shape::from_mesh(&Mesh::cylinder(radius, height, segments, stacks), display)
+    }
+
+    pub fn cone(
+        display: &dyn Backend,
+        radius: f32,
+        height: f32,
+        segments: u32,
+        stacks: u32,
+    ) -> Shape {
+        Shape::from_mesh(&Mesh::cone(radius, height, segments, stacks), display)
+    }
+
+    pub fn torus(
+        display: &dyn Backend,
+        major: f32,
+        minor: f32,
+        segments: u32,
+        sides: u32,
+    ) -> Shape {
+        Shape::from_mesh(&Mesh::torus(major, minor, segments, sides), display)
+    }
+
+    pub fn set_program(&mut self, program: ProgramHandle) {
+        self.program = program;
+    }
 }
 
 // Trait for making sure any other shape made has the same types and handles drawing
@@ -83,16 +160,36 @@ pub trait HasShape {
     fn mut_vertices(&mut self) -> &mut [F32vec3];
     fn ref_vbo(&self) -> &VertexBuffer<F32vec3>;
     fn ref_index(&self) -> &PrimitiveType;
-    fn get_id(&self) -> usize;
+    fn program(&self) -> ProgramHandle;
+    fn ref_indices(&self) -> Option<&IndexObject> {
+        None
+    }
     fn update_vbo(&self) {
         self.ref_vbo().write(self.ref_vertices())
     }
+
+    // What to hand `draw`, the index buffer if there is one
+    fn indices(&self) -> IndicesSource<'_> {
+        match self.ref_indices() {
+            Some(indices) => indices.source(),
+            None => NoIndices(*self.ref_index()).into(),
+        }
+    }
+
+    // Turns primitive restart on for shapes whose indices use it
+    fn draw_parameters<'a>(&self, params: &DrawParameters<'a>) -> DrawParameters<'a> {
+        let restart = self.ref_indices().is_some_and(|i| i.restart());
+        DrawParameters {
+            primitive_restart_index: restart || params.primitive_restart_index,
+            ..params.clone()
+        }
+    }
 }
 
 impl HasShape for Shape {
     type RefType = Shape;
     fn ref_vertices(&self) -> &[F32vec3] {
-        &self.vertices.ref_data()
+        self.vertices.ref_data()
     }
     fn mut_vertices(&mut self) -> &mut [F32vec3] {
         self.vertices.mut_data()
@@ -103,8 +200,19 @@ impl HasShape for Shape {
     fn ref_index(&self) -> &PrimitiveType {
         &self.index_type
     }
-    fn get_id(&self) -> usize {
-        self.id
+    fn program(&self) -> ProgramHandle {
+        self.program
+    }
+    fn ref_indices(&self) -> Option<&IndexObject> {
+        self.indices.as_ref()
+    }
+    // Goes through the buffer objects so headless shapes don't need GPU buffers,
+    // and uploads the indices as well since they can be changed too
+    fn update_vbo(&self) {
+        self.vertices.update_buffer();
+        if let Some(indices) = &self.indices {
+            indices.update_buffer();
+        }
     }
 }
 
@@ -1,25 +1,25 @@
 use drawable::shape::HasShape;
 use drawable::{DrawUniforms, Drawable};
-use glium::index::NoIndices;
-use glium::{uniform, DrawParameters, Frame, Program, Surface};
+use glium::{DrawParameters, Frame, Surface};
+use math::Vec3;
+use shader::ProgramLibrary;
 use std::iter::zip;
 use std::slice::{Iter, IterMut};
-use util::attribute::Attr;
-use util::bufferable::BufferObject;
+use util::attribute::Instance;
+use util::bufferable::InstanceBuffer;
 use util::Manipulate;
 
 ///
 /// Shape groups: Main class
 /// Can hold multiple shapes and multiple attribute buffers for
-/// better instancing control. Add shapes and BufferObjects before use
+/// better instancing control. Add shapes and InstanceBuffers before use
 ///
-
 pub struct ShapeGroup<T>
 where
     T: HasShape + Send,
 {
     pub shapes: Vec<Box<T>>,
-    pub transforms: Vec<Box<BufferObject<Attr>>>,
+    pub transforms: Vec<InstanceBuffer>,
 }
 
 impl<T: HasShape + Send> Default for ShapeGroup<T> {
@@ -32,25 +32,21 @@ impl<T: HasShape + Send> Default for ShapeGroup<T> {
 }
 
 impl<T: HasShape + Send> ShapeGroup<T> {
-    pub fn push(&mut self, obj: (T, BufferObject<Attr>)) {
+    pub fn push(&mut self, obj: (T, InstanceBuffer)) {
         self.shapes.push(Box::from(obj.0));
-        self.transforms.push(Box::from(obj.1));
-    }
-
-    pub fn iter_shapes(&mut self) -> Iter<'_, Box<T>> {
-        self.shapes.iter()
+        self.transforms.push(obj.1);
     }
 
     pub fn iter_mut_shapes(&mut self) -> IterMut<'_, Box<T>> {
         self.shapes.iter_mut()
     }
 
-    pub fn iter_transforms(&self, index: usize) -> Iter<'_, Attr> {
+    pub fn iter_transforms(&self, index: usize) -> Iter<'_, Instance> {
         assert!(index < self.shapes.len());
         self.transforms[index].iter()
     }
 
-    pub fn iter_mut_transforms(&mut self, index: usize) -> IterMut<'_, Attr> {
+    pub fn iter_mut_transforms(&mut self, index: usize) -> IterMut<'_, Instance> {
         assert!(index < self.shapes.len());
         self.transforms[index].iter_mut()
     }
@@ -58,40 +54,51 @@ impl<T: HasShape + Send> ShapeGroup<T> {
     pub fn update_buffers(&self) {
         self.transforms.iter().for_each(|p| p.update_buffer());
     }
-}
 
-impl<T> Drawable for ShapeGroup<T>
-where
-    T: HasShape + Send,
-{
-    fn draw(
+    // Draws whatever is in the instance buffers now, without uploading the transforms first
+    pub fn draw_buffers(
         &self,
         target: &mut Frame,
-        program: &Program,
+        programs: &ProgramLibrary,
         params: &DrawParameters,
         uniforms: DrawUniforms,
     ) {
-        self.update_buffers();
         let shapes = self.shapes.as_slice();
         let transforms = self.transforms.as_slice();
         for (shape, transform) in zip(shapes, transforms) {
             target
                 .draw(
                     (shape.ref_vbo(), transform.per_instance()),
-                    &NoIndices(*shape.ref_index()),
-                    &program,
+                    shape.indices(),
+                    programs.get(shape.program()),
                     &uniforms,
-                    &params,
+                    &shape.draw_parameters(params),
                 )
                 .unwrap();
         }
     }
 }
 
+impl<T> Drawable for ShapeGroup<T>
+where
+    T: HasShape + Send,
+{
+    fn draw(
+        &self,
+        target: &mut Frame,
+        programs: &ProgramLibrary,
+        params: &DrawParameters,
+        uniforms: DrawUniforms,
+    ) {
+        self.update_buffers();
+        self.draw_buffers(target, programs, params, uniforms);
+    }
+}
+
 impl<T: HasShape + Send> Manipulate for ShapeGroup<T> {
-    fn rotate_axis(&mut self, axis: usize, ang: f32) {
+    fn rotate(&mut self, axis: Vec3, angle: f32) {
         self.transforms
             .iter_mut()
-            .for_each(|p| p.rotate_axis(axis, ang))
+            .for_each(|p| p.rotate(axis, angle))
     }
 }
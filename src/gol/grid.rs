@@ -0,0 +1,90 @@
+use super::rule::Rule;
+use super::topology::Topology;
+use super::Universe;
+use rayon::prelude::*;
+
+///
+/// A fixed size, double buffered grid of cells.
+/// The topology decides what lies past the edges when counting neighbours.
+///
+pub struct Grid {
+    width: usize,
+    height: usize,
+    cells: Vec<i32>,
+    next: Vec<i32>,
+    topology: Topology,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize, topology: Topology) -> Self {
+        assert!(width > 0 && height > 0, "grid must be at least 1x1");
+        Grid {
+            width,
+            height,
+            cells: vec![0; width * height],
+            next: vec![0; width * height],
+            topology,
+        }
+    }
+
+    // Counts the live cells around (x, y)
+    fn neighbours(&self, x: usize, y: usize) -> i32 {
+        let (x, y) = (x as i64, y as i64);
+        let inside = x > 0 && y > 0 && x + 1 < self.width as i64 && y + 1 < self.height as i64;
+        let mut count = 0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                let index = if inside {
+                    Some(((y + dy) as usize, (x + dx) as usize))
+                } else {
+                    self.topology
+                        .wrap(x + dx, y + dy, self.width, self.height)
+                        .map(|(nx, ny)| (ny, nx))
+                };
+                if let Some((ny, nx)) = index {
+                    count += (self.cells[ny * self.width + nx] == 1) as i32;
+                }
+            }
+        }
+        count
+    }
+}
+
+impl Universe for Grid {
+    // Computes the next generation into the back buffer in parallel over rows and swaps it in
+    fn step(&mut self, rule: &Rule) {
+        let mut next = std::mem::take(&mut self.next);
+        let width = self.width;
+        next.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            for (x, cell) in row.iter_mut().enumerate() {
+                *cell = rule.next(self.cells[y * width + x], self.neighbours(x, y));
+            }
+        });
+        self.next = std::mem::replace(&mut self.cells, next);
+    }
+
+    fn get(&self, x: i64, y: i64) -> i32 {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return 0;
+        }
+        self.cells[y as usize * self.width + x as usize]
+    }
+
+    // Coordinates off the grid are wrapped onto it, or dropped for a bounded grid
+    fn set(&mut self, x: i64, y: i64, state: i32) {
+        if let Some((x, y)) = self.topology.wrap(x, y, self.width, self.height) {
+            self.cells[y * self.width + x] = state;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.cells.par_iter_mut().for_each(|cell| *cell = 0);
+    }
+
+    fn bounds(&self) -> Option<(i64, i64, usize, usize)> {
+        Some((0, 0, self.width, self.height))
+    }
+}
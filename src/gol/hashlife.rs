@@ -0,0 +1,407 @@
+use super::rule::Rule;
+use super::Universe;
+use std::collections::HashMap;
+
+type NodeId = u32;
+
+const DEAD: NodeId = 0;
+const ALIVE: NodeId = 1;
+
+// Past this many nodes the caches are thrown away and only the live tree is kept
+const NODE_LIMIT: usize = 1 << 22;
+// Largest step as a power of two. The root is padded to 2^(exponent + 3) cells across,
+// and this leaves room inside an i64 for the pattern to spread out past that
+const MAX_STEP_EXPONENT: u32 = 48;
+
+// A square of 2^level cells, split into nw, ne, sw and se quadrants
+#[derive(Copy, Clone)]
+struct Node {
+    level: u32,
+    children: [NodeId; 4],
+    population: u64,
+}
+
+///
+/// HashLife: the universe is a quadtree where identical squares are shared,
+/// and the result of advancing each square is memoised, so repetitive patterns
+/// can be run for very many generations at once.
+/// Only two state rules without B0 are supported.
+/// The root is centred on the origin, with y growing downwards.
+///
+pub struct HashLife {
+    nodes: Vec<Node>,
+    lookup: HashMap<[NodeId; 4], NodeId>,
+    // Memoised successors, keyed by node and log2 of the generations advanced
+    results: HashMap<(NodeId, u32), NodeId>,
+    empty: Vec<NodeId>,
+    root: NodeId,
+    rule: Rule,
+}
+
+impl Default for HashLife {
+    fn default() -> Self {
+        let leaf = |population| Node {
+            level: 0,
+            children: [DEAD; 4],
+            population,
+        };
+        let mut hashlife = HashLife {
+            nodes: vec![leaf(0), leaf(1)],
+            lookup: HashMap::new(),
+            results: HashMap::new(),
+            empty: vec![DEAD],
+            root: DEAD,
+            rule: Rule::default(),
+        };
+        hashlife.root = hashlife.empty(3);
+        hashlife
+    }
+}
+
+impl HashLife {
+    fn level(&self, id: NodeId) -> u32 {
+        self.nodes[id as usize].level
+    }
+
+    fn population(&self, id: NodeId) -> u64 {
+        self.nodes[id as usize].population
+    }
+
+    fn child(&self, id: NodeId, quadrant: usize) -> NodeId {
+        self.nodes[id as usize].children[quadrant]
+    }
+
+    // Finds or creates the node with the given quadrants
+    fn join(&mut self, nw: NodeId, ne: NodeId, sw: NodeId, se: NodeId) -> NodeId {
+        let children = [nw, ne, sw, se];
+        if let Some(&id) = self.lookup.get(&children) {
+            return id;
+        }
+        let id = self.nodes.len() as NodeId;
+        let population = children.iter().map(|&c| self.population(c)).sum();
+        self.nodes.push(Node {
+            level: self.level(nw) + 1,
+            children,
+            population,
+        });
+        self.lookup.insert(children, id);
+        id
+    }
+
+    fn empty(&mut self, level: u32) -> NodeId {
+        while self.empty.len() <= level as usize {
+            let e = *self.empty.last().unwrap();
+            let next = self.join(e, e, e, e);
+            self.empty.push(next);
+        }
+        self.empty[level as usize]
+    }
+
+    // The middle half of a node, one level down
+    fn centre(&mut self, id: NodeId) -> NodeId {
+        let [nw, ne, sw, se] = self.nodes[id as usize].children;
+        let (a, b) = (self.child(nw, 3), self.child(ne, 2));
+        let (c, d) = (self.child(sw, 1), self.child(se, 0));
+        self.join(a, b, c, d)
+    }
+
+    // A node one level up with this node in the middle and empty space around it
+    fn expand(&mut self, id: NodeId) -> NodeId {
+        let level = self.level(id);
+        let e = self.empty(level - 1);
+        let [nw, ne, sw, se] = self.nodes[id as usize].children;
+        let nw = self.join(e, e, e, nw);
+        let ne = self.join(e, e, ne, e);
+        let sw = self.join(e, sw, e, e);
+        let se = self.join(se, e, e, e);
+        self.join(nw, ne, sw, se)
+    }
+
+    // Cell of a level 2 node, from its top left
+    fn cell_4x4(&self, id: NodeId, x: usize, y: usize) -> bool {
+        let quadrant = self.child(id, (y / 2) * 2 + x / 2);
+        self.child(quadrant, (y % 2) * 2 + x % 2) == ALIVE
+    }
+
+    // Advances the middle 2x2 of a 4x4 node by one generation
+    fn step_4x4(&mut self, id: NodeId) -> NodeId {
+        let mut next = [DEAD; 4];
+        for (i, cell) in next.iter_mut().enumerate() {
+            let (x, y) = (i % 2 + 1, i / 2 + 1);
+            let mut neighbours = 0;
+            for ny in y - 1..=y + 1 {
+                for nx in x - 1..=x + 1 {
+                    if (nx, ny) != (x, y) && self.cell_4x4(id, nx, ny) {
+                        neighbours += 1;
+                    }
+                }
+            }
+            let state = self.cell_4x4(id, x, y) as i32;
+            *cell = self.rule.next(state, neighbours) as NodeId;
+        }
+        self.join(next[0], next[1], next[2], next[3])
+    }
+
+    ///
+    /// The middle half of a node advanced by 2^step generations,
+    /// where step is at most level - 2.
+    /// The node is split into nine overlapping squares one level down, which are
+    /// either advanced or just trimmed, joined into four and advanced again.
+    ///
+    fn successor(&mut self, id: NodeId, step: u32) -> NodeId {
+        let level = self.level(id);
+        let step = step.min(level - 2);
+        if let Some(&result) = self.results.get(&(id, step)) {
+            return result;
+        }
+        if self.population(id) == 0 {
+            return self.empty(level - 1);
+        }
+
+        let result = if level == 2 {
+            self.step_4x4(id)
+        } else {
+            let [a, b, c, d] = self.nodes[id as usize].children;
+            let [_, a_ne, a_sw, a_se] = self.nodes[a as usize].children;
+            let [b_nw, _, b_sw, b_se] = self.nodes[b as usize].children;
+            let [c_nw, c_ne, _, c_se] = self.nodes[c as usize].children;
+            let [d_nw, d_ne, d_sw, _] = self.nodes[d as usize].children;
+            let squares = [
+                a,
+                self.join(a_ne, b_nw, a_se, b_sw),
+                b,
+                self.join(a_sw, a_se, c_nw, c_ne),
+                self.join(a_se, b_sw, c_ne, d_nw),
+                self.join(b_sw, b_se, d_nw, d_ne),
+                c,
+                self.join(c_ne, d_nw, c_se, d_sw),
+                d,
+            ];
+            let mut inner = [DEAD; 9];
+            for (i, &square) in squares.iter().enumerate() {
+                inner[i] = if step == level - 2 {
+                    self.successor(square, step)
+                } else {
+                    self.centre(square)
+                };
+            }
+            let nw = self.join(inner[0], inner[1], inner[3], inner[4]);
+            let ne = self.join(inner[1], inner[2], inner[4], inner[5]);
+            let sw = self.join(inner[3], inner[4], inner[6], inner[7]);
+            let se = self.join(inner[4], inner[5], inner[7], inner[8]);
+            let nw = self.successor(nw, step);
+            let ne = self.successor(ne, step);
+            let sw = self.successor(sw, step);
+            let se = self.successor(se, step);
+            self.join(nw, ne, sw, se)
+        };
+        self.results.insert((id, step), result);
+        result
+    }
+
+    // Half the width of the root, which spans -half..half on both axes
+    fn half(&self) -> i64 {
+        1 << (self.level(self.root) - 1)
+    }
+
+    fn contains(&self, x: i64, y: i64) -> bool {
+        let half = self.half();
+        (-half..half).contains(&x) && (-half..half).contains(&y)
+    }
+
+    fn get_in(&self, id: NodeId, x: i64, y: i64) -> i32 {
+        let node = &self.nodes[id as usize];
+        if node.population == 0 {
+            return 0;
+        }
+        if node.level == 0 {
+            return (id == ALIVE) as i32;
+        }
+        let half = 1 << (node.level - 1);
+        let quadrant = (y >= half) as usize * 2 + (x >= half) as usize;
+        self.get_in(node.children[quadrant], x % half, y % half)
+    }
+
+    fn set_in(&mut self, id: NodeId, x: i64, y: i64, alive: bool) -> NodeId {
+        let level = self.level(id);
+        if level == 0 {
+            return if alive { ALIVE } else { DEAD };
+        }
+        let half = 1 << (level - 1);
+        let quadrant = (y >= half) as usize * 2 + (x >= half) as usize;
+        let mut children = self.nodes[id as usize].children;
+        children[quadrant] = self.set_in(children[quadrant], x % half, y % half, alive);
+        self.join(children[0], children[1], children[2], children[3])
+    }
+
+    // Grows (min x, min y, max x, max y) to cover the live cells of a node with its top left at (x, y)
+    fn bounds_in(&self, id: NodeId, x: i64, y: i64, bounds: &mut Option<(i64, i64, i64, i64)>) {
+        let node = &self.nodes[id as usize];
+        if node.population == 0 {
+            return;
+        }
+        if node.level == 0 {
+            *bounds = Some(match *bounds {
+                Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                None => (x, y, x, y),
+            });
+            return;
+        }
+        let half = 1 << (node.level - 1);
+        for (quadrant, &child) in node.children.iter().enumerate() {
+            let (qx, qy) = ((quadrant % 2) as i64 * half, (quadrant / 2) as i64 * half);
+            self.bounds_in(child, x + qx, y + qy, bounds);
+        }
+    }
+
+    // Copies the tree reachable from the root into fresh storage, dropping every cache
+    fn collect_garbage(&mut self) {
+        let mut fresh = HashLife::default();
+        let mut copied = HashMap::new();
+        fresh.root = fresh.copy_from(self, self.root, &mut copied);
+        fresh.rule = self.rule;
+        *self = fresh;
+    }
+
+    fn copy_from(
+        &mut self,
+        other: &HashLife,
+        id: NodeId,
+        copied: &mut HashMap<NodeId, NodeId>,
+    ) -> NodeId {
+        if id == DEAD || id == ALIVE {
+            return id;
+        }
+        if let Some(&new) = copied.get(&id) {
+            return new;
+        }
+        let [nw, ne, sw, se] = other.nodes[id as usize].children;
+        let nw = self.copy_from(other, nw, copied);
+        let ne = self.copy_from(other, ne, copied);
+        let sw = self.copy_from(other, sw, copied);
+        let se = self.copy_from(other, se, copied);
+        let new = self.join(nw, ne, sw, se);
+        copied.insert(id, new);
+        new
+    }
+}
+
+impl Universe for HashLife {
+    fn step(&mut self, rule: &Rule) {
+        self.step_pow2(rule, 0);
+    }
+
+    fn max_step_exponent(&self) -> u32 {
+        MAX_STEP_EXPONENT
+    }
+
+    fn step_pow2(&mut self, rule: &Rule, exponent: u32) {
+        assert_eq!(rule.states(), 2, "HashLife only runs two state rules");
+        assert!(!rule.births(0), "HashLife can't run rules with B0");
+        if *rule != self.rule {
+            self.results.clear();
+            self.rule = *rule;
+        }
+
+        // Pad the root until the pattern sits in its middle quarter,
+        // so nothing can escape the result in 2^exponent generations
+        loop {
+            let inner = self.centre(self.root);
+            let inner = self.centre(inner);
+            let padded = self.population(inner) == self.population(self.root);
+            if padded && self.level(self.root) >= exponent + 3 {
+                break;
+            }
+            self.root = self.expand(self.root);
+        }
+        self.root = self.successor(self.root, exponent);
+
+        if self.nodes.len() > NODE_LIMIT {
+            self.collect_garbage();
+        }
+    }
+
+    fn get(&self, x: i64, y: i64) -> i32 {
+        if !self.contains(x, y) {
+            return 0;
+        }
+        let half = self.half();
+        self.get_in(self.root, x + half, y + half)
+    }
+
+    fn set(&mut self, x: i64, y: i64, state: i32) {
+        while !self.contains(x, y) {
+            self.root = self.expand(self.root);
+        }
+        let half = self.half();
+        self.root = self.set_in(self.root, x + half, y + half, state == 1);
+    }
+
+    fn clear(&mut self) {
+        self.root = self.empty(3);
+    }
+
+    fn bounds(&self) -> Option<(i64, i64, usize, usize)> {
+        let half = self.half();
+        let mut bounds = None;
+        self.bounds_in(self.root, -half, -half, &mut bounds);
+        bounds.map(|(x0, y0, x1, y1)| (x0, y0, (x1 - x0 + 1) as usize, (y1 - y0 + 1) as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sparse::SparseGrid;
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn live_cells(universe: &dyn Universe) -> Vec<(i64, i64)> {
+        let (x, y, width, height) = match universe.bounds() {
+            Some(bounds) => bounds,
+            None => return vec![],
+        };
+        let mut cells = vec![];
+        for cy in y..y + height as i64 {
+            for cx in x..x + width as i64 {
+                if universe.get(cx, cy) == 1 {
+                    cells.push((cx, cy));
+                }
+            }
+        }
+        cells
+    }
+
+    // A random soup run generation by generation and skipped ahead in powers of two
+    #[test]
+    fn skipping_ahead_matches_stepping() {
+        let rule = Rule::default();
+        let mut rng = StdRng::seed_from_u64(3);
+        let (mut hashlife, mut sparse) = (HashLife::default(), SparseGrid::default());
+        for y in -12..12 {
+            for x in -12..12 {
+                let state = rng.gen_bool(0.4) as i32;
+                hashlife.set(x, y, state);
+                sparse.set(x, y, state);
+            }
+        }
+        for exponent in [0, 3, 1, 5, 2] {
+            hashlife.step_pow2(&rule, exponent);
+            (0..1 << exponent).for_each(|_| sparse.step(&rule));
+            assert_eq!(live_cells(&hashlife), live_cells(&sparse));
+        }
+    }
+
+    #[test]
+    fn glider_moves_a_cell_every_four_generations() {
+        let mut hashlife = HashLife::default();
+        for &(x, y) in &[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            hashlife.set(x, y, 1);
+        }
+        hashlife.step_pow2(&Rule::default(), 10);
+        assert_eq!(hashlife.bounds(), Some((256, 256, 3, 3)));
+        hashlife.step_pow2(&Rule::default(), MAX_STEP_EXPONENT);
+        let shift = 256 + (1 << (MAX_STEP_EXPONENT - 2));
+        assert_eq!(hashlife.bounds(), Some((shift, shift, 3, 3)));
+    }
+}
@@ -0,0 +1,366 @@
+pub(crate) mod grid;
+pub(crate) mod hashlife;
+pub(crate) mod pattern;
+pub(crate) mod rule;
+pub(crate) mod sparse;
+pub(crate) mod topology;
+
+use self::grid::Grid;
+use self::hashlife::HashLife;
+use self::pattern::Pattern;
+use self::rule::Rule;
+use self::sparse::SparseGrid;
+use self::topology::Topology;
+use camera::pan_zoom::PanZoomCamera;
+use camera::Camera;
+use drawable::shape::Shape;
+use drawable::shape_group::ShapeGroup;
+use drawable::{DrawUniforms, Drawable};
+use glium::{Display, DrawParameters, Frame};
+use math::Vec3;
+use rand::Rng;
+use rayon::prelude::*;
+use runnable::scene::Scene;
+use shader::ProgramLibrary;
+use std::io::{self, Write};
+use util::attribute::Instance;
+use util::bufferable::{Backend, InstanceBuffer};
+use util::random;
+use util::Manipulate;
+use winit::event::VirtualKeyCode;
+
+pub(crate) const WIDTH: usize = 200;
+pub(crate) const HEIGHT: usize = 200;
+
+// Cells of space kept around the pattern when an unbounded grid grows
+const VIEW_PADDING: i64 = 16;
+
+// Storage and stepping for the cells of a Game of Life
+pub trait Universe: Sync {
+    fn step(&mut self, rule: &Rule);
+    // Advances 2^exponent generations, backends that can skip ahead override this
+    fn step_pow2(&mut self, rule: &Rule, exponent: u32) {
+        for _ in 0..1u64 << exponent {
+            self.step(rule);
+        }
+    }
+    // Most generations one step_pow2 call can advance, as a power of two.
+    // Backends that step one generation at a time keep this small
+    fn max_step_exponent(&self) -> u32 {
+        6
+    }
+    fn get(&self, x: i64, y: i64) -> i32;
+    fn set(&mut self, x: i64, y: i64, state: i32);
+    fn clear(&mut self);
+    // Rectangle (x, y, width, height) that holds every cell that isn't dead
+    fn bounds(&self) -> Option<(i64, i64, usize, usize)>;
+}
+
+// Region of the universe (x, y, width, height) that has an instance for each cell
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct View(i64, i64, usize, usize);
+
+pub struct GameOfLife {
+    shapegroup: ShapeGroup<Shape>,
+    universe: Box<dyn Universe>,
+    rule: Rule,
+    view: View,
+    // Whether the view grows to keep up with the pattern
+    follow: bool,
+    // Each update advances 2^step_exponent generations
+    step_exponent: u32,
+    generation: u64,
+    // Kept to rebuild the instances when the view changes, None when headless
+    display: Option<Display>,
+    // Framebuffer height over width
+    aspect: f32,
+}
+
+#[inline]
+fn map(value: f32, start1: f32, stop1: f32, start2: f32, stop2: f32) -> f32 {
+    start2 + (stop2 - start2) * ((value - start1) / (stop1 - start1))
+}
+
+impl GameOfLife {
+    // An empty grid, unbounded grids start out at width x height and grow from there
+    pub fn new(
+        display: &dyn Backend,
+        width: usize,
+        height: usize,
+        topology: Topology,
+        rule: Rule,
+    ) -> Self {
+        let universe: Box<dyn Universe> = match topology {
+            Topology::Unbounded => Box::<SparseGrid>::default(),
+            _ => Box::new(Grid::new(width, height, topology)),
+        };
+        let view = View(0, 0, width, height);
+        GameOfLife {
+            shapegroup: Self::build_instances(display, view, 1.0),
+            universe,
+            rule,
+            view,
+            follow: topology == Topology::Unbounded,
+            step_exponent: 0,
+            generation: 0,
+            display: display.display().cloned(),
+            aspect: 1.0,
+        }
+    }
+
+    ///
+    /// An unbounded universe run with HashLife, for very long runs of two state rules.
+    /// The view is fixed to width x height cells around the origin.
+    ///
+    pub fn hashlife(display: &dyn Backend, width: usize, height: usize, rule: Rule) -> Self {
+        assert_eq!(rule.states(), 2, "HashLife only runs two state rules");
+        let view = View(-(width as i64) / 2, -(height as i64) / 2, width, height);
+        GameOfLife {
+            shapegroup: Self::build_instances(display, view, 1.0),
+            universe: Box::<HashLife>::default(),
+            rule,
+            view,
+            follow: false,
+            step_exponent: 0,
+            generation: 0,
+            display: display.display().cloned(),
+            aspect: 1.0,
+        }
+    }
+
+    ///
+    /// Lays out a quad for every cell in the view on the z = -1 plane, as large as
+    /// fits in what the default camera sees: 2 units tall and 2 / `aspect` wide,
+    /// where `aspect` is the framebuffer's height over its width.
+    ///
+    fn build_instances(display: &dyn Backend, view: View, aspect: f32) -> ShapeGroup<Shape> {
+        let View(_, _, width, height) = view;
+        let (cols, rows) = (width as f32, height as f32);
+        // Width of a cell
+        let pitch = (2.0 / (cols * aspect)).min(2.0 / rows);
+        // Half the width of a cell, with a small gap between cells
+        let quad = Shape::quad(display, pitch * 0.45);
+        let half_width = cols * pitch / 2.0;
+        let half_height = rows * pitch / 2.0;
+
+        let attributes: Vec<Instance> = (0..width * height)
+            .map(|i| {
+                let x = (i % width) as f32 + 0.5;
+                let y = (i / width) as f32 + 0.5;
+                // Row 0 is the top of the screen so patterns aren't drawn upside down
+                let x = map(x, 0.0, cols, -half_width, half_width);
+                let y = map(y, 0.0, rows, half_height, -half_height);
+                let mut attr = Instance::from([x, y, -1.0]);
+                attr.color = [0.0, 0.0, 0.0, 1.0];
+                attr
+            })
+            .collect();
+
+        let mut shapegroup = ShapeGroup::default();
+        shapegroup.push((quad, InstanceBuffer::new(display, &attributes)));
+        shapegroup
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // Makes each update advance 2^exponent generations, as far as the backend can skip
+    pub fn set_step_exponent(&mut self, exponent: u32) {
+        self.step_exponent = exponent.min(self.universe.max_step_exponent());
+    }
+
+    // Moves the view so (x, y) is its top left cell
+    pub fn move_view(&mut self, x: i64, y: i64) {
+        self.view.0 = x;
+        self.view.1 = y;
+        self.update_colors();
+    }
+
+    // Fills the view with live cells at the given density
+    pub fn randomize(&mut self, density: f64) {
+        let View(x, y, width, height) = self.view;
+        let universe = &mut self.universe;
+        random::with_rng(|rng| {
+            for cy in y..y + height as i64 {
+                for cx in x..x + width as i64 {
+                    universe.set(cx, cy, rng.gen_bool(density) as i32);
+                }
+            }
+        });
+        self.update_colors();
+    }
+
+    // Writes a pattern into the grid with its top left corner at (x, y), wrapping around the edges
+    pub fn place(&mut self, pattern: &Pattern, x: i64, y: i64) {
+        for py in 0..pattern.height {
+            for px in 0..pattern.width {
+                let state = pattern.get(px, py).min(self.rule.states() - 1);
+                self.universe.set(x + px as i64, y + py as i64, state);
+            }
+        }
+        self.fit_view();
+        self.update_colors();
+    }
+
+    // Clears the grid, takes on the pattern's rule if it has one and places it at (x, y)
+    pub fn load_pattern(&mut self, pattern: &Pattern, x: i64, y: i64) {
+        self.universe.clear();
+        if let Some(rule) = pattern.rule {
+            self.rule = rule;
+        }
+        self.place(pattern, x, y);
+    }
+
+    // Loads a pattern into the middle of the view
+    pub fn load_pattern_centred(&mut self, pattern: &Pattern) {
+        let View(x, y, width, height) = self.view;
+        let x = x + (width as i64 - pattern.width as i64) / 2;
+        let y = y + (height as i64 - pattern.height as i64) / 2;
+        self.load_pattern(pattern, x, y);
+    }
+
+    // Copies the live part of the view out as a pattern
+    pub fn to_pattern(&self) -> Pattern {
+        let View(vx, vy, vw, vh) = self.view;
+        let (x, y, width, height) = match self.universe.bounds() {
+            Some((x, y, w, h)) => {
+                let (left, top) = (x.max(vx), y.max(vy));
+                let right = (x + w as i64).min(vx + vw as i64);
+                let bottom = (y + h as i64).min(vy + vh as i64);
+                if left >= right || top >= bottom {
+                    return Pattern::new(0, 0);
+                }
+                (left, top, (right - left) as usize, (bottom - top) as usize)
+            }
+            None => return Pattern::new(0, 0),
+        };
+        let mut pattern = Pattern::new(width, height);
+        pattern.rule = Some(self.rule);
+        for py in 0..height {
+            for px in 0..width {
+                let state = self.universe.get(x + px as i64, y + py as i64);
+                pattern.set(px, py, state);
+            }
+        }
+        pattern.trimmed()
+    }
+
+    pub fn step(&mut self) {
+        self.universe.step_pow2(&self.rule, self.step_exponent);
+        let generations = 1u64.checked_shl(self.step_exponent).unwrap_or(u64::MAX);
+        self.generation = self.generation.saturating_add(generations);
+        self.fit_view();
+    }
+
+    // Grows the view of an unbounded grid when the pattern reaches its edge,
+    // rebuilding the instance buffer to match
+    fn fit_view(&mut self) {
+        if !self.follow {
+            return;
+        }
+        let (x, y, width, height) = match self.universe.bounds() {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let View(vx, vy, vw, vh) = self.view;
+        let (right, bottom) = (x + width as i64, y + height as i64);
+        if x >= vx && y >= vy && right <= vx + vw as i64 && bottom <= vy + vh as i64 {
+            return;
+        }
+
+        let left = x.min(vx) - VIEW_PADDING;
+        let top = y.min(vy) - VIEW_PADDING;
+        let right = right.max(vx + vw as i64) + VIEW_PADDING;
+        let bottom = bottom.max(vy + vh as i64) + VIEW_PADDING;
+        self.view = View(left, top, (right - left) as usize, (bottom - top) as usize);
+        self.shapegroup = Self::build_instances(&self.display, self.view, self.aspect);
+    }
+
+    // Pushes the state of each cell in the view into the colour of its instance
+    fn update_colors(&mut self) {
+        let universe = &self.universe;
+        let rule = self.rule;
+        let View(x, y, width, _) = self.view;
+        self.shapegroup.transforms[0]
+            .mut_data()
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(i, attr)| {
+                let state = universe.get(x + (i % width) as i64, y + (i / width) as i64);
+                attr.color = rule.color(state);
+            });
+    }
+}
+
+impl Drawable for GameOfLife {
+    fn draw(
+        &self,
+        target: &mut Frame,
+        programs: &ProgramLibrary,
+        params: &DrawParameters,
+        uniforms: DrawUniforms,
+    ) {
+        self.shapegroup.draw(target, programs, params, uniforms);
+    }
+
+    fn update(&mut self) {
+        self.step();
+        self.update_colors();
+    }
+
+    // Lays the cells out again so they still fill the screen
+    fn resize(&mut self, width: u32, height: u32) {
+        self.aspect = height as f32 / width as f32;
+        self.shapegroup = Self::build_instances(&self.display, self.view, self.aspect);
+        self.update_colors();
+    }
+}
+
+impl Manipulate for GameOfLife {
+    fn rotate(&mut self, axis: Vec3, angle: f32) {
+        self.shapegroup.rotate(axis, angle);
+    }
+}
+
+// The live cells as RLE, with the generation in a comment
+impl Scene for GameOfLife {
+    fn dump(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "#C Generation {}", self.generation)?;
+        out.write_all(self.to_pattern().to_rle().as_bytes())
+    }
+
+    // Flat, so it's panned and zoomed rather than flown around
+    fn camera(&self) -> Box<dyn Camera> {
+        Box::new(PanZoomCamera::default())
+    }
+
+    // '-' and '=' halve and double the generations each update advances,
+    // the arrow keys move the view a quarter of its size
+    fn handle_key(&mut self, key: VirtualKeyCode) {
+        let View(x, y, width, height) = self.view;
+        let (dx, dy) = ((width as i64 / 4).max(1), (height as i64 / 4).max(1));
+        match key {
+            VirtualKeyCode::Minus | VirtualKeyCode::Equals => {
+                let exponent = match key {
+                    VirtualKeyCode::Minus => self.step_exponent.saturating_sub(1),
+                    _ => self.step_exponent + 1,
+                };
+                self.set_step_exponent(exponent);
+                println!("Generations per update: 2^{}", self.step_exponent);
+            }
+            VirtualKeyCode::Left => self.move_view(x - dx, y),
+            VirtualKeyCode::Right => self.move_view(x + dx, y),
+            VirtualKeyCode::Up => self.move_view(x, y - dy),
+            VirtualKeyCode::Down => self.move_view(x, y + dy),
+            VirtualKeyCode::P => {
+                let path = format!("gol-{}.rle", self.generation());
+                match self.to_pattern().save(&path) {
+                    Ok(()) => println!("Saved the cells in view to {}", path),
+                    Err(err) => eprintln!("Couldn't save {}: {}", path, err),
+                }
+            }
+            _ => (),
+        }
+    }
+}
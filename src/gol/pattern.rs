@@ -0,0 +1,557 @@
+use super::rule::{Rule, RuleError, MAX_STATES};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Write};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// RLE writers keep lines under this length
+const RLE_LINE_LENGTH: usize = 70;
+
+#[derive(Debug)]
+pub enum PatternError {
+    Io(io::Error),
+    Rule(usize, RuleError),
+    Header(usize, String),
+    Syntax(usize, String),
+    UnknownFormat(String),
+}
+
+impl Display for PatternError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatternError::Io(e) => write!(f, "could not read pattern: {}", e),
+            PatternError::Rule(line, e) => write!(f, "line {}: {}", line, e),
+            PatternError::Header(line, msg) => write!(f, "line {}: bad header, {}", line, msg),
+            PatternError::Syntax(line, msg) => write!(f, "line {}: {}", line, msg),
+            PatternError::UnknownFormat(ext) => write!(
+                f,
+                "unknown pattern format '{}', expected .rle, .lif, .life or .cells",
+                ext
+            ),
+        }
+    }
+}
+
+impl Error for PatternError {}
+
+impl From<io::Error> for PatternError {
+    fn from(value: io::Error) -> Self {
+        PatternError::Io(value)
+    }
+}
+
+///
+/// A rectangular block of cells that can be placed into a grid.
+/// Cells are stored row-major from the top left, 0 is dead, 1 is alive
+/// and higher values are the dying states of a Generations rule.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<i32>,
+    pub name: Option<String>,
+    pub rule: Option<Rule>,
+}
+
+impl Pattern {
+    pub fn new(width: usize, height: usize) -> Self {
+        Pattern {
+            width,
+            height,
+            cells: vec![0; width * height],
+            name: None,
+            rule: None,
+        }
+    }
+
+    // Builds the smallest pattern that holds every (x, y) pair, shifting them so the top left is at 0, 0
+    pub fn from_coords(coords: &[(i64, i64)]) -> Self {
+        if coords.is_empty() {
+            return Pattern::new(0, 0);
+        }
+        let min_x = coords.iter().map(|c| c.0).min().unwrap();
+        let min_y = coords.iter().map(|c| c.1).min().unwrap();
+        let max_x = coords.iter().map(|c| c.0).max().unwrap();
+        let max_y = coords.iter().map(|c| c.1).max().unwrap();
+        let mut pattern = Pattern::new((max_x - min_x + 1) as usize, (max_y - min_y + 1) as usize);
+        for &(x, y) in coords {
+            pattern.set((x - min_x) as usize, (y - min_y) as usize, 1);
+        }
+        pattern
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> i32 {
+        self.cells[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, state: i32) {
+        self.cells[y * self.width + x] = state;
+    }
+
+    // Shrinks the pattern down to the bounding box of its non-dead cells
+    pub fn trimmed(&self) -> Self {
+        let occupied = |x: usize, y: usize| self.get(x, y) != 0;
+        let rows: Vec<usize> = (0..self.height)
+            .filter(|&y| (0..self.width).any(|x| occupied(x, y)))
+            .collect();
+        let cols: Vec<usize> = (0..self.width)
+            .filter(|&x| (0..self.height).any(|y| occupied(x, y)))
+            .collect();
+        let (top, left) = match (rows.first(), cols.first()) {
+            (Some(&top), Some(&left)) => (top, left),
+            _ => {
+                return Pattern {
+                    name: self.name.clone(),
+                    rule: self.rule,
+                    ..Pattern::new(0, 0)
+                }
+            }
+        };
+        let (bottom, right) = (*rows.last().unwrap(), *cols.last().unwrap());
+
+        let mut pattern = Pattern::new(right - left + 1, bottom - top + 1);
+        for y in 0..pattern.height {
+            for x in 0..pattern.width {
+                pattern.set(x, y, self.get(x + left, y + top));
+            }
+        }
+        pattern.name = self.name.clone();
+        pattern.rule = self.rule;
+        pattern
+    }
+
+    // Picks a reader by file extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PatternError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        match Self::extension(path).as_str() {
+            "rle" => Self::from_rle(&text),
+            "lif" | "life" => Self::from_life106(&text),
+            "cells" => Self::from_cells(&text),
+            ext => Err(PatternError::UnknownFormat(ext.to_string())),
+        }
+    }
+
+    // Picks a writer by file extension
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PatternError> {
+        let path = path.as_ref();
+        let text = match Self::extension(path).as_str() {
+            "rle" => self.to_rle(),
+            "lif" | "life" => self.to_life106(),
+            "cells" => self.to_cells(),
+            ext => return Err(PatternError::UnknownFormat(ext.to_string())),
+        };
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    fn extension(path: &Path) -> String {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_lowercase()
+    }
+
+    fn is_multistate(&self) -> bool {
+        self.rule.is_some_and(|rule| rule.states() > 2) || self.cells.iter().any(|&c| c > 1)
+    }
+
+    ///
+    /// Reads a run length encoded pattern:
+    /// #N name
+    /// x = 3, y = 3, rule = B3/S23
+    /// bo$2bo$3o!
+    /// Multi-state patterns use `.` for dead, `A`-`X` for states 1-24 and two letters
+    /// for the rest, `pA`-`pX` for 25-48 and so on up to `yO` for 255.
+    ///
+    pub fn from_rle(text: &str) -> Result<Self, PatternError> {
+        let mut lines = text.lines().enumerate().map(|(i, l)| (i + 1, l.trim()));
+        let mut name = None;
+
+        let (header_line, header) = loop {
+            match lines.next() {
+                Some((_, "")) => continue,
+                Some((_, line)) if line.starts_with('#') => {
+                    if let Some(n) = line.strip_prefix("#N") {
+                        name = Some(n.trim().to_string());
+                    }
+                }
+                Some(header) => break header,
+                None => return Err(PatternError::Header(1, "missing 'x = , y = ' line".into())),
+            }
+        };
+
+        let (mut width, mut height, mut rule) = (None, None, None);
+        for field in header.split(',') {
+            let mut kv = field.splitn(2, '=').map(str::trim);
+            let (key, value) = match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) => (k, v),
+                _ => {
+                    let msg = format!("expected 'key = value', found '{}'", field.trim());
+                    return Err(PatternError::Header(header_line, msg));
+                }
+            };
+            let size = || {
+                value.parse::<usize>().map_err(|_| {
+                    let msg = format!("'{}' is not a valid {} size", value, key);
+                    PatternError::Header(header_line, msg)
+                })
+            };
+            match key {
+                "x" => width = Some(size()?),
+                "y" => height = Some(size()?),
+                "rule" => {
+                    let parsed = value
+                        .parse::<Rule>()
+                        .map_err(|e| PatternError::Rule(header_line, e))?;
+                    rule = Some(parsed);
+                }
+                _ => {
+                    let msg = format!("unknown key '{}'", key);
+                    return Err(PatternError::Header(header_line, msg));
+                }
+            }
+        }
+        let width = width.ok_or_else(|| PatternError::Header(header_line, "missing x".into()))?;
+        let height = height.ok_or_else(|| PatternError::Header(header_line, "missing y".into()))?;
+
+        let mut pattern = Pattern::new(width, height);
+        pattern.name = name;
+        pattern.rule = rule;
+
+        let (mut x, mut y, mut count) = (0, 0, None::<usize>);
+        // First letter of a two letter state
+        let mut prefix = None::<char>;
+        'body: for (line_no, line) in lines {
+            if line.starts_with('#') {
+                continue;
+            }
+            for c in line.chars() {
+                if let (Some(p), false) = (prefix, c.is_ascii_uppercase()) {
+                    let msg = format!("expected a state letter after '{}', found '{}'", p, c);
+                    return Err(PatternError::Syntax(line_no, msg));
+                }
+                let state = match c {
+                    '0'..='9' => {
+                        let digit = c.to_digit(10).unwrap() as usize;
+                        count = Some(count.unwrap_or(0) * 10 + digit);
+                        continue;
+                    }
+                    '!' => break 'body,
+                    '$' => {
+                        y += count.take().unwrap_or(1);
+                        x = 0;
+                        continue;
+                    }
+                    'b' | '.' => 0,
+                    'o' => 1,
+                    'p'..='y' => {
+                        prefix = Some(c);
+                        continue;
+                    }
+                    'A'..='X' => {
+                        let high = prefix.take().map_or(0, |p| p as i32 - 'p' as i32 + 1);
+                        let state = high * 24 + c as i32 - 'A' as i32 + 1;
+                        if state >= MAX_STATES {
+                            let msg =
+                                format!("state {} is past the last one, {}", state, MAX_STATES - 1);
+                            return Err(PatternError::Syntax(line_no, msg));
+                        }
+                        state
+                    }
+                    c if c.is_whitespace() => continue,
+                    _ => {
+                        let msg = format!("unexpected '{}' in RLE body", c);
+                        return Err(PatternError::Syntax(line_no, msg));
+                    }
+                };
+                let run = count.take().unwrap_or(1);
+                if x + run > width || y >= height {
+                    let msg = format!(
+                        "cells run past the {}x{} bounds in the header",
+                        width, height
+                    );
+                    return Err(PatternError::Syntax(line_no, msg));
+                }
+                for _ in 0..run {
+                    pattern.set(x, y, state);
+                    x += 1;
+                }
+            }
+        }
+        Ok(pattern)
+    }
+
+    pub fn to_rle(&self) -> String {
+        let multistate = self.is_multistate();
+        let tag = |state: i32| match (multistate, state) {
+            (false, 0) => "b".to_string(),
+            (false, _) => "o".to_string(),
+            (true, s) => state_tag(s),
+        };
+
+        // Build up the runs, dropping dead cells at the end of rows and merging blank rows
+        let mut runs: Vec<(usize, String)> = vec![];
+        let push = |runs: &mut Vec<(usize, String)>, n: usize, tag: String| match runs.last_mut() {
+            Some((count, last)) if *last == tag => *count += n,
+            _ => runs.push((n, tag)),
+        };
+        for y in 0..self.height {
+            let row = &self.cells[y * self.width..(y + 1) * self.width];
+            let end = row.iter().rposition(|&c| c != 0).map_or(0, |i| i + 1);
+            for &cell in &row[..end] {
+                push(&mut runs, 1, tag(cell));
+            }
+            if y + 1 < self.height {
+                push(&mut runs, 1, "$".to_string());
+            }
+        }
+        while runs.last().is_some_and(|(_, tag)| tag == "$") {
+            runs.pop();
+        }
+
+        let mut text = String::new();
+        if let Some(name) = &self.name {
+            writeln!(text, "#N {}", name).unwrap();
+        }
+        let rule = self.rule.unwrap_or_default();
+        writeln!(
+            text,
+            "x = {}, y = {}, rule = {}",
+            self.width, self.height, rule
+        )
+        .unwrap();
+
+        let mut line = String::new();
+        for (count, tag) in runs.into_iter().chain(Some((1, "!".to_string()))) {
+            let item = match count {
+                1 => tag,
+                _ => format!("{}{}", count, tag),
+            };
+            if line.len() + item.len() > RLE_LINE_LENGTH {
+                text.push_str(&line);
+                text.push('\n');
+                line.clear();
+            }
+            line.push_str(&item);
+        }
+        text.push_str(&line);
+        text.push('\n');
+        text
+    }
+
+    ///
+    /// Reads a Life 1.06 pattern, a `#Life 1.06` header followed by
+    /// one `x y` coordinate per live cell
+    ///
+    pub fn from_life106(text: &str) -> Result<Self, PatternError> {
+        let mut coords = vec![];
+        let mut seen_header = false;
+        for (i, line) in text.lines().enumerate() {
+            let (line_no, line) = (i + 1, line.trim());
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('#') {
+                if line.starts_with("#Life") && line != "#Life 1.06" {
+                    let msg = format!("expected '#Life 1.06', found '{}'", line);
+                    return Err(PatternError::Header(line_no, msg));
+                }
+                seen_header |= line == "#Life 1.06";
+                continue;
+            }
+            if !seen_header {
+                return Err(PatternError::Header(line_no, "missing '#Life 1.06'".into()));
+            }
+            let coord: Vec<i64> = line
+                .split_whitespace()
+                .map(|n| n.parse::<i64>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| {
+                    let msg = format!("'{}' is not a pair of integer coordinates", line);
+                    PatternError::Syntax(line_no, msg)
+                })?;
+            match coord.as_slice() {
+                [x, y] => coords.push((*x, *y)),
+                _ => {
+                    let msg = format!("expected 'x y', found '{}'", line);
+                    return Err(PatternError::Syntax(line_no, msg));
+                }
+            }
+        }
+        Ok(Pattern::from_coords(&coords))
+    }
+
+    // Only live cells are written, dying Generations states are lost
+    pub fn to_life106(&self) -> String {
+        let mut text = String::from("#Life 1.06\n");
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.get(x, y) == 1 {
+                    writeln!(text, "{} {}", x, y).unwrap();
+                }
+            }
+        }
+        text
+    }
+
+    ///
+    /// Reads a plaintext pattern, `!` comment lines followed by rows of
+    /// `.` for dead and `O` for live cells
+    ///
+    pub fn from_cells(text: &str) -> Result<Self, PatternError> {
+        let mut name = None;
+        let mut rows: Vec<Vec<i32>> = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_end();
+            if let Some(comment) = line.strip_prefix('!') {
+                if let Some(n) = comment.strip_prefix("Name:") {
+                    name = Some(n.trim().to_string());
+                }
+                continue;
+            }
+            let row = line
+                .chars()
+                .map(|c| match c {
+                    '.' => Ok(0),
+                    'O' | 'o' | '*' => Ok(1),
+                    _ => Err(PatternError::Syntax(
+                        i + 1,
+                        format!("unexpected '{}' in row", c),
+                    )),
+                })
+                .collect::<Result<_, _>>()?;
+            rows.push(row);
+        }
+
+        let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        let mut pattern = Pattern::new(width, rows.len());
+        pattern.name = name;
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &cell) in row.iter().enumerate() {
+                pattern.set(x, y, cell);
+            }
+        }
+        Ok(pattern)
+    }
+
+    // Only live cells are written, dying Generations states are lost
+    pub fn to_cells(&self) -> String {
+        let mut text = String::new();
+        if let Some(name) = &self.name {
+            writeln!(text, "!Name: {}", name).unwrap();
+        }
+        for y in 0..self.height {
+            let row = &self.cells[y * self.width..(y + 1) * self.width];
+            let end = row.iter().rposition(|&c| c == 1).map_or(0, |i| i + 1);
+            text.extend(row[..end].iter().map(|&c| if c == 1 { 'O' } else { '.' }));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+// How multi-state RLE writes a state, the inverse of what `Pattern::from_rle` reads
+fn state_tag(state: i32) -> String {
+    debug_assert!(
+        (0..MAX_STATES).contains(&state),
+        "no RLE for state {}",
+        state
+    );
+    let letter = |n: i32| (b'A' + n as u8) as char;
+    match state {
+        0 => ".".to_string(),
+        1..=24 => letter(state - 1).to_string(),
+        _ => {
+            let high = (b'p' + ((state - 25) / 24) as u8) as char;
+            format!("{}{}", high, letter((state - 25) % 24))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLIDER_RLE: &str = "#N Glider\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+
+    fn glider() -> Pattern {
+        Pattern::from_coords(&[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)])
+    }
+
+    #[test]
+    fn rle_round_trip() {
+        let pattern = Pattern::from_rle(GLIDER_RLE).unwrap();
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.cells, glider().cells);
+        assert_eq!(pattern.name.as_deref(), Some("Glider"));
+        assert_eq!(pattern.rule, Some(Rule::default()));
+        assert_eq!(pattern.to_rle(), GLIDER_RLE);
+        assert_eq!(Pattern::from_rle(&pattern.to_rle()).unwrap(), pattern);
+    }
+
+    #[test]
+    fn rle_keeps_blank_rows_and_long_runs() {
+        let mut pattern = Pattern::new(100, 5);
+        pattern.rule = Some("B36/S23".parse().unwrap());
+        (0..100).for_each(|x| pattern.set(x, 0, 1));
+        pattern.set(99, 4, 1);
+        let rle = pattern.to_rle();
+        assert!(rle.starts_with("x = 100, y = 5, rule = B36/S23\n100o4$99bo!"));
+        assert_eq!(Pattern::from_rle(&rle).unwrap(), pattern);
+    }
+
+    #[test]
+    fn rle_round_trips_every_generations_state() {
+        let mut pattern = Pattern::new(MAX_STATES as usize, 1);
+        pattern.rule = Some(Rule::new(&[2], &[], MAX_STATES));
+        (0..MAX_STATES).for_each(|state| pattern.set(state as usize, 0, state));
+        let rle = pattern.to_rle();
+        for tag in ["A", "X", "pA", "pX", "qA", "yO"] {
+            assert!(rle.contains(tag), "{} missing from {}", tag, rle);
+        }
+        assert!(rle.lines().all(|line| line.len() <= RLE_LINE_LENGTH));
+        assert_eq!(Pattern::from_rle(&rle).unwrap(), pattern);
+    }
+
+    #[test]
+    fn rle_rejects_states_past_the_last() {
+        let header = "x = 1, y = 1, rule = B2/S/C256\n";
+        assert!(Pattern::from_rle(&format!("{}yO!", header)).is_ok());
+        assert!(Pattern::from_rle(&format!("{}yP!", header)).is_err());
+        assert!(Pattern::from_rle(&format!("{}pb!", header)).is_err());
+        assert!("B2/S/C257".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn rle_header_errors() {
+        assert!(Pattern::from_rle("bo$2bo$3o!").is_err());
+        assert!(Pattern::from_rle("x = 3\nbo$2bo$3o!").is_err());
+        assert!(Pattern::from_rle("x = 3, y = 3, rule = B9/S23\n3o!").is_err());
+        assert!(Pattern::from_rle("x = 2, y = 1\n3o!").is_err());
+    }
+
+    #[test]
+    fn life106_round_trip() {
+        let text = "#Life 1.06\n1 0\n2 1\n0 2\n1 2\n2 2\n";
+        assert_eq!(glider().to_life106(), text);
+        assert_eq!(Pattern::from_life106(text).unwrap(), glider());
+        // Coordinates are shifted so the top left cell is at 0, 0
+        let shifted = "#Life 1.06\n-4 -9\n-3 -8\n-5 -7\n-4 -7\n-3 -7\n";
+        assert_eq!(Pattern::from_life106(shifted).unwrap(), glider());
+        assert!(Pattern::from_life106("1 0\n").is_err());
+        assert!(Pattern::from_life106("#Life 1.06\n1 x\n").is_err());
+    }
+
+    #[test]
+    fn cells_round_trip() {
+        let text = "!Name: Glider\n.O\n..O\nOOO\n";
+        let pattern = Pattern::from_cells(text).unwrap();
+        assert_eq!(pattern.cells, glider().cells);
+        assert_eq!(pattern.name.as_deref(), Some("Glider"));
+        assert_eq!(pattern.to_cells(), text);
+        assert!(Pattern::from_cells(".O\nOx\n").is_err());
+    }
+}
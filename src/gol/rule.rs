@@ -0,0 +1,402 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+const ALIVE: [f32; 4] = [1.0; 4];
+const DEAD: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+// Colours that dying cells in a Generations rule fade through, from just died to almost dead
+const RAMP_START: [f32; 4] = [1.0, 0.6, 0.1, 1.0];
+const RAMP_END: [f32; 4] = [0.25, 0.0, 0.4, 1.0];
+
+// Most states a Generations rule can have, as many as RLE files can write
+pub const MAX_STATES: i32 = 256;
+
+// Named rules that can be picked without writing out the rule string
+pub const PRESETS: &[(&str, &str)] = &[
+    ("life", "B3/S23"),
+    ("highlife", "B36/S23"),
+    ("daynight", "B3678/S34678"),
+    ("seeds", "B2/S"),
+    ("briansbrain", "B2/S/C3"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleError {
+    Empty,
+    MissingBirth(String),
+    MissingSurvival(String),
+    InvalidCount(char),
+    DuplicateCount(char),
+    InvalidStates(String),
+    // A section that isn't B, S or C, or one past the third in the untagged form
+    UnknownSection(String),
+    // Nothing between two slashes, or after the last one
+    EmptySection(String),
+    DuplicateSection(char),
+    UnknownPreset(String),
+}
+
+impl Display for RuleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleError::Empty => write!(f, "rule string is empty"),
+            RuleError::MissingBirth(s) => write!(f, "rule '{}' has no birth (B) section", s),
+            RuleError::MissingSurvival(s) => {
+                write!(f, "rule '{}' has no survival (S) section", s)
+            }
+            RuleError::InvalidCount(c) => {
+                write!(f, "'{}' is not a neighbour count, expected a digit 0-8", c)
+            }
+            RuleError::DuplicateCount(c) => write!(f, "neighbour count {} is listed twice", c),
+            RuleError::InvalidStates(s) => {
+                write!(
+                    f,
+                    "'{}' is not a valid state count, expected a number from 2 to {}",
+                    s, MAX_STATES
+                )
+            }
+            RuleError::UnknownSection(s) => write!(
+                f,
+                "'{}' is not a rule section, expected B, S and C sections or survival/birth/states",
+                s
+            ),
+            RuleError::EmptySection(s) => write!(f, "rule '{}' has an empty section", s),
+            RuleError::DuplicateSection(c) => write!(f, "the {} section is given twice", c),
+            RuleError::UnknownPreset(s) => write!(
+                f,
+                "unknown rule preset '{}', expected one of: {}",
+                s,
+                PRESETS
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl Error for RuleError {}
+
+///
+/// A Life-like or Generations rule.
+/// Birth and survival are bitmasks over the number of live neighbours (0-8),
+/// and `states` is the total number of cell states including dead and alive.
+/// Life-like rules have 2 states, Generations rules have more, where a cell that
+/// fails to survive decays through the extra states before dying.
+///
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    birth: u16,
+    survival: u16,
+    states: i32,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::new(&[3], &[2, 3], 2)
+    }
+}
+
+impl Rule {
+    pub fn new(birth: &[u8], survival: &[u8], states: i32) -> Self {
+        assert!(
+            (2..=MAX_STATES).contains(&states),
+            "a rule needs 2 to {} states",
+            MAX_STATES
+        );
+        let mask = |counts: &[u8]| {
+            counts.iter().fold(0u16, |acc, &n| {
+                assert!(n <= 8, "neighbour count {} out of range", n);
+                acc | 1 << n
+            })
+        };
+        Rule {
+            birth: mask(birth),
+            survival: mask(survival),
+            states,
+        }
+    }
+
+    pub fn preset(name: &str) -> Result<Self, RuleError> {
+        let name = name.to_lowercase().replace([' ', '\'', '&', '-', '_'], "");
+        PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, rule)| rule.parse().unwrap())
+            .ok_or(RuleError::UnknownPreset(name))
+    }
+
+    pub fn states(&self) -> i32 {
+        self.states
+    }
+
+    pub fn births(&self, neighbours: i32) -> bool {
+        self.birth & (1 << neighbours) != 0
+    }
+
+    pub fn survives(&self, neighbours: i32) -> bool {
+        self.survival & (1 << neighbours) != 0
+    }
+
+    // The state a cell moves to given its current state and number of live neighbours
+    #[inline]
+    pub fn next(&self, state: i32, neighbours: i32) -> i32 {
+        match state {
+            0 if self.births(neighbours) => 1,
+            0 => 0,
+            1 if self.survives(neighbours) => 1,
+            _ => (state + 1) % self.states,
+        }
+    }
+
+    // Colour of a cell in the given state, dying states are spread along the colour ramp
+    pub fn color(&self, state: i32) -> [f32; 4] {
+        match state {
+            0 => DEAD,
+            1 => ALIVE,
+            _ => {
+                let t = if self.states > 3 {
+                    (state - 2) as f32 / (self.states - 3) as f32
+                } else {
+                    0.0
+                };
+                let mut color = [0.0; 4];
+                for (i, c) in color.iter_mut().enumerate() {
+                    *c = RAMP_START[i] + (RAMP_END[i] - RAMP_START[i]) * t;
+                }
+                color
+            }
+        }
+    }
+
+    fn parse_counts(counts: &str) -> Result<u16, RuleError> {
+        let mut mask = 0u16;
+        for c in counts.chars() {
+            let n = match c.to_digit(10) {
+                Some(n) if n <= 8 => n,
+                _ => return Err(RuleError::InvalidCount(c)),
+            };
+            if mask & (1 << n) != 0 {
+                return Err(RuleError::DuplicateCount(c));
+            }
+            mask |= 1 << n;
+        }
+        Ok(mask)
+    }
+
+    fn parse_states(states: &str) -> Result<i32, RuleError> {
+        match states.parse::<i32>() {
+            Ok(n) if (2..=MAX_STATES).contains(&n) => Ok(n),
+            _ => Err(RuleError::InvalidStates(states.to_string())),
+        }
+    }
+}
+
+// Parses `B3/S23` style rules, `B2/S/C3` Generations rules and the older
+// `23/3` (survival/birth) and `/2/3` (survival/birth/states) forms
+impl FromStr for Rule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(RuleError::Empty);
+        }
+        let parts: Vec<&str> = s.split('/').map(str::trim).collect();
+
+        // Untagged sections are all digits, where an empty one is an empty set of counts
+        let tagged = parts
+            .iter()
+            .any(|p| p.starts_with(|c: char| c.is_alphabetic()));
+        if !tagged {
+            return match parts.as_slice() {
+                [survival, birth] => Ok(Rule {
+                    birth: Self::parse_counts(birth)?,
+                    survival: Self::parse_counts(survival)?,
+                    states: 2,
+                }),
+                [survival, birth, states] => Ok(Rule {
+                    birth: Self::parse_counts(birth)?,
+                    survival: Self::parse_counts(survival)?,
+                    states: Self::parse_states(states)?,
+                }),
+                [_] => Err(RuleError::MissingBirth(s.to_string())),
+                _ => Err(RuleError::UnknownSection(parts[3].to_string())),
+            };
+        }
+
+        let (mut birth, mut survival, mut states) = (None, None, None);
+        for part in parts {
+            let mut chars = part.chars();
+            let tag = chars.next().map(|c| c.to_ascii_uppercase());
+            let rest = chars.as_str();
+            // A bare number is the state count, G is another name for C
+            match tag {
+                Some('B') => set_once(&mut birth, Self::parse_counts(rest)?, 'B')?,
+                Some('S') => set_once(&mut survival, Self::parse_counts(rest)?, 'S')?,
+                Some('C') | Some('G') => set_once(&mut states, Self::parse_states(rest)?, 'C')?,
+                Some(c) if c.is_ascii_digit() => {
+                    set_once(&mut states, Self::parse_states(part)?, 'C')?
+                }
+                Some(_) => return Err(RuleError::UnknownSection(part.to_string())),
+                None => return Err(RuleError::EmptySection(s.to_string())),
+            }
+        }
+
+        Ok(Rule {
+            birth: birth.ok_or_else(|| RuleError::MissingBirth(s.to_string()))?,
+            survival: survival.ok_or_else(|| RuleError::MissingSurvival(s.to_string()))?,
+            states: states.unwrap_or(2),
+        })
+    }
+}
+
+// Fills in a section, unless an earlier part of the rule already did
+fn set_once<T>(section: &mut Option<T>, value: T, tag: char) -> Result<(), RuleError> {
+    match section.replace(value) {
+        Some(_) => Err(RuleError::DuplicateSection(tag)),
+        None => Ok(()),
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let counts = |mask: u16| {
+            (0..=8)
+                .filter(|n| mask & (1 << n) != 0)
+                .map(|n| n.to_string())
+                .collect::<String>()
+        };
+        write!(f, "B{}/S{}", counts(self.birth), counts(self.survival))?;
+        if self.states > 2 {
+            write!(f, "/C{}", self.states)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(rule: &str) -> RuleError {
+        rule.parse::<Rule>().unwrap_err()
+    }
+
+    #[test]
+    fn tagged_rules() {
+        assert_eq!("B3/S23".parse(), Ok(Rule::default()));
+        assert_eq!("s23/b3".parse(), Ok(Rule::default()));
+        assert_eq!(" B36 / S23 ".parse(), Ok(Rule::new(&[3, 6], &[2, 3], 2)));
+        assert_eq!("B2/S".parse(), Ok(Rule::new(&[2], &[], 2)));
+        assert_eq!("B2/S/C3".parse(), Ok(Rule::new(&[2], &[], 3)));
+        assert_eq!("B2/S/G4".parse(), Ok(Rule::new(&[2], &[], 4)));
+        assert_eq!("B2/S/5".parse(), Ok(Rule::new(&[2], &[], 5)));
+    }
+
+    #[test]
+    fn untagged_rules_are_survival_then_birth() {
+        assert_eq!("23/3".parse(), Ok(Rule::default()));
+        assert_eq!("/2/3".parse(), Ok(Rule::new(&[2], &[], 3)));
+        assert_eq!("345/2/4".parse(), Ok(Rule::new(&[2], &[3, 4, 5], 4)));
+    }
+
+    #[test]
+    fn bad_rules_say_what_is_wrong() {
+        assert_eq!(error("  "), RuleError::Empty);
+        assert_eq!(error("S23"), RuleError::MissingBirth("S23".to_string()));
+        assert_eq!(error("23"), RuleError::MissingBirth("23".to_string()));
+        assert_eq!(error("B3"), RuleError::MissingSurvival("B3".to_string()));
+        assert_eq!(error("B39/S23"), RuleError::InvalidCount('9'));
+        assert_eq!(error("B3/S2x"), RuleError::InvalidCount('x'));
+        assert_eq!(error("B33/S23"), RuleError::DuplicateCount('3'));
+        assert_eq!(
+            error("B3/S23/C1"),
+            RuleError::InvalidStates("1".to_string())
+        );
+        assert_eq!(
+            error("23/3/257"),
+            RuleError::InvalidStates("257".to_string())
+        );
+    }
+
+    #[test]
+    fn bad_sections_are_named() {
+        assert_eq!(error("X3/S23"), RuleError::UnknownSection("X3".to_string()));
+        assert_eq!(error("B3/Y"), RuleError::UnknownSection("Y".to_string()));
+        assert_eq!(error("1/2/3/4"), RuleError::UnknownSection("4".to_string()));
+        assert_eq!(
+            error("B3//S23"),
+            RuleError::EmptySection("B3//S23".to_string())
+        );
+        assert_eq!(
+            error("B3/S23/"),
+            RuleError::EmptySection("B3/S23/".to_string())
+        );
+        assert_eq!(error("B3/B36/S23"), RuleError::DuplicateSection('B'));
+        assert_eq!(error("B3/S23/s2"), RuleError::DuplicateSection('S'));
+        assert_eq!(error("B2/S/C3/4"), RuleError::DuplicateSection('C'));
+        assert!(error("X3/S23")
+            .to_string()
+            .contains("'X3' is not a rule section"));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for rule in &[
+            "B3/S23",
+            "B/S",
+            "B012345678/S012345678",
+            "B2/S/C3",
+            "B34/S45/C256",
+        ] {
+            let parsed: Rule = rule.parse().unwrap();
+            assert_eq!(parsed.to_string(), *rule);
+        }
+        // Other spellings come out in the usual form
+        assert_eq!("23/3".parse::<Rule>().unwrap().to_string(), "B3/S23");
+        assert_eq!("b2/s/g3".parse::<Rule>().unwrap().to_string(), "B2/S/C3");
+    }
+
+    #[test]
+    fn every_preset_parses() {
+        for (name, rule) in PRESETS {
+            let preset = Rule::preset(name).unwrap();
+            assert_eq!(preset.to_string(), *rule);
+        }
+        assert_eq!(Rule::preset("Brian's Brain"), Rule::preset("briansbrain"));
+        assert_eq!(
+            Rule::preset("nope"),
+            Err(RuleError::UnknownPreset("nope".to_string()))
+        );
+    }
+
+    #[test]
+    fn life_like_rules() {
+        let life = Rule::default();
+        assert_eq!(life.next(0, 3), 1);
+        assert_eq!(life.next(0, 2), 0);
+        assert_eq!(life.next(1, 2), 1);
+        assert_eq!(life.next(1, 3), 1);
+        assert_eq!(life.next(1, 4), 0);
+        assert_eq!(life.next(1, 1), 0);
+    }
+
+    #[test]
+    fn generations_cells_decay_whatever_their_neighbours() {
+        let rule: Rule = "B2/S3/C4".parse().unwrap();
+        assert_eq!(rule.next(0, 2), 1);
+        assert_eq!(rule.next(1, 3), 1);
+        // Failing to survive starts the decay, which runs to dead and can't be stopped
+        assert_eq!(rule.next(1, 2), 2);
+        assert_eq!(rule.next(2, 3), 3);
+        assert_eq!(rule.next(3, 2), 0);
+        assert_eq!(rule.next(2, 0), 3);
+
+        let brain = Rule::preset("briansbrain").unwrap();
+        assert_eq!(brain.next(1, 2), 2);
+        assert_eq!(brain.next(2, 2), 0);
+    }
+}
@@ -0,0 +1,141 @@
+use super::rule::Rule;
+use super::Universe;
+use std::collections::HashMap;
+
+///
+/// An unbounded grid that only stores cells which aren't dead.
+/// Rules with B0 can't be run on it, since every empty cell would be born.
+///
+#[derive(Default)]
+pub struct SparseGrid {
+    cells: HashMap<(i64, i64), i32>,
+}
+
+impl Universe for SparseGrid {
+    fn step(&mut self, rule: &Rule) {
+        // Count live neighbours for every cell next to a live cell
+        let mut counts: HashMap<(i64, i64), i32> = HashMap::with_capacity(self.cells.len() * 4);
+        for (&(x, y), _) in self.cells.iter().filter(|(_, &state)| state == 1) {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if dx != 0 || dy != 0 {
+                        *counts.entry((x + dx, y + dy)).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+
+        let mut next = HashMap::with_capacity(self.cells.len());
+        // Cells with no live neighbours can still be decaying
+        for (&pos, &state) in &self.cells {
+            if !counts.contains_key(&pos) {
+                let state = rule.next(state, 0);
+                if state != 0 {
+                    next.insert(pos, state);
+                }
+            }
+        }
+        for (pos, count) in counts {
+            let state = rule.next(self.get(pos.0, pos.1), count);
+            if state != 0 {
+                next.insert(pos, state);
+            }
+        }
+        self.cells = next;
+    }
+
+    fn get(&self, x: i64, y: i64) -> i32 {
+        self.cells.get(&(x, y)).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, x: i64, y: i64, state: i32) {
+        if state == 0 {
+            self.cells.remove(&(x, y));
+        } else {
+            self.cells.insert((x, y), state);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    fn bounds(&self) -> Option<(i64, i64, usize, usize)> {
+        let mut keys = self.cells.keys();
+        let &(x, y) = keys.next()?;
+        let (min_x, min_y, max_x, max_y) = keys.fold((x, y, x, y), |b, &(x, y)| {
+            (b.0.min(x), b.1.min(y), b.2.max(x), b.3.max(y))
+        });
+        Some((
+            min_x,
+            min_y,
+            (max_x - min_x + 1) as usize,
+            (max_y - min_y + 1) as usize,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glider() -> SparseGrid {
+        let mut grid = SparseGrid::default();
+        for &(x, y) in &[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            grid.set(x, y, 1);
+        }
+        grid
+    }
+
+    #[test]
+    fn a_glider_carries_the_grid_with_it() {
+        let (rule, mut grid) = (Rule::default(), glider());
+        assert_eq!(grid.bounds(), Some((0, 0, 3, 3)));
+        for _ in 0..400 {
+            grid.step(&rule);
+        }
+        // A glider moves one cell diagonally every four generations
+        assert_eq!(grid.bounds(), Some((100, 100, 3, 3)));
+        assert_eq!(
+            grid.cells,
+            glider()
+                .cells
+                .into_iter()
+                .map(|((x, y), s)| ((x + 100, y + 100), s))
+                .collect()
+        );
+    }
+
+    #[test]
+    fn the_grid_grows_the_other_way_too() {
+        let (rule, mut grid) = (Rule::default(), SparseGrid::default());
+        // Mirrored, the glider heads up and left into negative coordinates
+        for (&(x, y), &state) in &glider().cells {
+            grid.set(-x, -y, state);
+        }
+        for _ in 0..40 {
+            grid.step(&rule);
+        }
+        assert_eq!(grid.bounds(), Some((-12, -12, 3, 3)));
+    }
+
+    #[test]
+    fn lone_cells_still_decay() {
+        // Nothing survives and each cell fades through one dying state
+        let rule = Rule::new(&[3], &[], 3);
+        let mut grid = SparseGrid::default();
+        grid.set(0, 0, 1);
+        grid.step(&rule);
+        assert_eq!(grid.get(0, 0), 2);
+        grid.step(&rule);
+        assert_eq!(grid.bounds(), None);
+    }
+
+    #[test]
+    fn clearing_empties_the_grid() {
+        let mut grid = glider();
+        grid.clear();
+        assert_eq!(grid.bounds(), None);
+        assert_eq!(grid.get(1, 0), 0);
+    }
+}
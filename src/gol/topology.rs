@@ -0,0 +1,146 @@
+///
+/// How the edges of a cellular grid behave.
+/// The wrapped topologies join opposite edges, with Klein bottles and
+/// cross-surfaces flipping the cells that cross them.
+///
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Topology {
+    // Cells past the edge are always dead
+    Bounded,
+    // Left joins right and top joins bottom
+    #[default]
+    Torus,
+    // Left joins right, top joins bottom mirrored left to right
+    KleinBottle,
+    // Both pairs of edges are joined mirrored, a real projective plane
+    CrossSurface,
+    // No edges, the grid grows to fit the pattern
+    Unbounded,
+}
+
+pub const TOPOLOGIES: &[(&str, Topology)] = &[
+    ("bounded", Topology::Bounded),
+    ("torus", Topology::Torus),
+    ("klein", Topology::KleinBottle),
+    ("cross", Topology::CrossSurface),
+    ("unbounded", Topology::Unbounded),
+];
+
+impl Topology {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        TOPOLOGIES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, topology)| *topology)
+    }
+
+    pub fn name(&self) -> &'static str {
+        TOPOLOGIES
+            .iter()
+            .find(|(_, topology)| topology == self)
+            .map(|(name, _)| *name)
+            .unwrap()
+    }
+
+    // Maps a coordinate that may lie off a width x height grid back onto it,
+    // or None if it falls off a bounded edge
+    #[inline]
+    pub fn wrap(&self, x: i64, y: i64, width: usize, height: usize) -> Option<(usize, usize)> {
+        let (w, h) = (width as i64, height as i64);
+        // Number of times the point has crossed the vertical and horizontal edges
+        let (cross_x, cross_y) = (x.div_euclid(w), y.div_euclid(h));
+        let (x, y) = match self {
+            Topology::Bounded | Topology::Unbounded => {
+                if cross_x != 0 || cross_y != 0 {
+                    return None;
+                }
+                (x, y)
+            }
+            Topology::Torus => (x, y),
+            Topology::KleinBottle if cross_y % 2 != 0 => (w - 1 - x, y),
+            Topology::KleinBottle => (x, y),
+            Topology::CrossSurface => {
+                let x = if cross_y % 2 != 0 { w - 1 - x } else { x };
+                let y = if cross_x % 2 != 0 { h - 1 - y } else { y };
+                (x, y)
+            }
+        };
+        Some((x.rem_euclid(w) as usize, y.rem_euclid(h) as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: usize = 4;
+    const H: usize = 3;
+
+    #[test]
+    fn names_round_trip() {
+        for &(name, topology) in TOPOLOGIES {
+            assert_eq!(Topology::from_name(name), Some(topology));
+            assert_eq!(topology.name(), name);
+        }
+        assert_eq!(Topology::from_name("Klein"), Some(Topology::KleinBottle));
+        assert_eq!(Topology::from_name("sphere"), None);
+    }
+
+    #[test]
+    fn cells_on_the_grid_stay_put() {
+        for &(_, topology) in TOPOLOGIES {
+            for y in 0..H {
+                for x in 0..W {
+                    assert_eq!(topology.wrap(x as i64, y as i64, W, H), Some((x, y)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grids_with_edges_drop_cells_past_them() {
+        for topology in [Topology::Bounded, Topology::Unbounded].iter() {
+            assert_eq!(topology.wrap(-1, 0, W, H), None);
+            assert_eq!(topology.wrap(W as i64, 0, W, H), None);
+            assert_eq!(topology.wrap(0, -1, W, H), None);
+            assert_eq!(topology.wrap(0, H as i64, W, H), None);
+        }
+    }
+
+    #[test]
+    fn a_torus_joins_opposite_edges() {
+        let torus = Topology::Torus;
+        assert_eq!(torus.wrap(-1, 1, W, H), Some((3, 1)));
+        assert_eq!(torus.wrap(4, 1, W, H), Some((0, 1)));
+        assert_eq!(torus.wrap(1, -1, W, H), Some((1, 2)));
+        assert_eq!(torus.wrap(1, 3, W, H), Some((1, 0)));
+        assert_eq!(torus.wrap(-1, -1, W, H), Some((3, 2)));
+        assert_eq!(torus.wrap(9, 7, W, H), Some((1, 1)));
+    }
+
+    #[test]
+    fn a_klein_bottle_mirrors_cells_crossing_the_top_and_bottom() {
+        let klein = Topology::KleinBottle;
+        // Left and right join straight
+        assert_eq!(klein.wrap(-1, 1, W, H), Some((3, 1)));
+        assert_eq!(klein.wrap(4, 0, W, H), Some((0, 0)));
+        // Top and bottom join mirrored
+        assert_eq!(klein.wrap(0, -1, W, H), Some((3, 2)));
+        assert_eq!(klein.wrap(1, 3, W, H), Some((2, 0)));
+        // Going round twice undoes the mirroring
+        assert_eq!(klein.wrap(1, 6, W, H), Some((1, 0)));
+    }
+
+    #[test]
+    fn a_cross_surface_mirrors_cells_crossing_either_edge() {
+        let cross = Topology::CrossSurface;
+        assert_eq!(cross.wrap(-1, 0, W, H), Some((3, 2)));
+        assert_eq!(cross.wrap(4, 2, W, H), Some((0, 0)));
+        assert_eq!(cross.wrap(0, -1, W, H), Some((3, 2)));
+        assert_eq!(cross.wrap(1, 3, W, H), Some((2, 0)));
+        // Past a corner both get mirrored
+        assert_eq!(cross.wrap(-1, -1, W, H), Some((0, 0)));
+        assert_eq!(cross.wrap(8, 6, W, H), Some((0, 0)));
+    }
+}
@@ -1,54 +1,79 @@
-use drawable::shape::{HasShape, Shape};
+use camera::fly::FlyCamera;
+use camera::Camera;
+use drawable::mesh::Mesh;
+use drawable::shape::Shape;
 use drawable::shape_group::ShapeGroup;
 use drawable::{DrawUniforms, Drawable};
 use glium::index::PrimitiveType;
-use glium::{Display, DrawParameters, Frame, Program};
+use glium::{DrawParameters, Frame};
+use math::Vec3;
+use model;
 use noise::{NoiseFn, Perlin};
-use rand::{thread_rng, RngCore};
-use rayon::prelude::*;
-use util::attribute::Attr;
-use util::bufferable::Bufferable;
+use rand::RngCore;
+use runnable::scene::Scene;
+use shader::ProgramLibrary;
+use std::f32::consts::FRAC_PI_4;
+use std::io::{self, Write};
+use util::attribute::Instance;
+use util::bufferable::{Backend, InstanceBuffer};
+use util::index_buffer::IndexObject;
+use util::random;
 use util::vertex::F32vec3;
 use util::Manipulate;
 
+// Samples across and the distance between them
 #[derive(Copy, Clone)]
-struct Dims(i32, i32, f64, f64);
+struct Dims(i32, f64);
 
 pub struct Landscape {
     shapes: ShapeGroup<Shape>,
     noise: Perlin,
     time: f64,
     dims: Dims,
+    // Height of each sample, row by row, without the last row of vertices
+    heights: Vec<f32>,
 }
 
 impl Landscape {
-    pub fn default(display: &Display) -> Self {
+    pub fn default(display: &dyn Backend) -> Self {
         let (cols, rows, res, nres) = (100, 100, 0.01, 0.5);
         let height = 1.0;
-        let noise = Perlin::new(thread_rng().next_u32());
+        let noise = Perlin::new(random::with_rng(|rng| rng.next_u32()));
         let time = 0.0;
 
-        let mut shapes = ShapeGroup::default();
-        for i in 0..rows {
+        // One more row of vertices than strips, each strip zigzags between two of them
+        let mut vertices = vec![];
+        let mut heights = vec![];
+        for i in 0..=rows {
             let y = (i as f64 - (rows as f64 / 2.0)) * res;
-            let mut vertices = vec![];
             for j in 0..cols {
                 let x = (j as f64 - (cols as f64 / 2.0)) * res;
                 let z = noise.get([x * nres, y * nres, time]) * height;
-                let z2 = noise.get([x * nres, (y + res) * nres, time]) * height;
-                vertices.push(F32vec3::from([(x + res) as f32, y as f32, z as f32]));
-                vertices.push(F32vec3::from([(x + res) as f32, y as f32, z2 as f32]));
+                // Heights go up the y axis, rows run back along z
+                vertices.push(F32vec3::from([(x + res) as f32, z as f32, y as f32]));
+                if i < rows {
+                    heights.push(z as f32);
+                }
             }
-            let shape = Shape::from_vertices(&vertices, PrimitiveType::LineStrip, display);
-            let attr = Attr::new_vbo(display, &[Attr::from([0.0, (y * res) as f32, 0.0])]);
-            shapes.push((shape, attr));
         }
+        let strips: Vec<Vec<u32>> = (0..rows as u32)
+            .map(|i| {
+                let (row, next) = (i * cols as u32, (i + 1) * cols as u32);
+                (0..cols as u32).flat_map(|j| [row + j, next + j]).collect()
+            })
+            .collect();
+        let indices = IndexObject::strips(display, PrimitiveType::LineStrip, &strips);
+
+        let mut shapes = ShapeGroup::default();
+        let shape = Shape::indexed(&vertices, indices, display);
+        shapes.push((shape, InstanceBuffer::new(display, &[Instance::default()])));
 
         Landscape {
             shapes,
             noise,
             time,
-            dims: Dims(cols, rows, res, nres),
+            dims: Dims(cols, res),
+            heights,
         }
     }
 }
@@ -57,34 +82,65 @@ impl Drawable for Landscape {
     fn draw(
         &self,
         target: &mut Frame,
-        program: &Program,
+        programs: &ProgramLibrary,
         params: &DrawParameters,
         uniforms: DrawUniforms,
     ) {
-        self.shapes.draw(target, program, params, uniforms);
+        self.shapes.draw(target, programs, params, uniforms);
     }
 
     fn update(&mut self) {
-        let dims = self.dims.clone();
+        let dims = self.dims;
         let time = self.time;
         let noise = self.noise;
-        let shapes = self.shapes.iter_mut_shapes().enumerate();
+        let heights = &mut self.heights;
+        let cols = dims.0 as usize;
 
-        shapes.for_each(|(p, shape)| {
-            for (idx, verts) in shape.vertices.mut_data().chunks_mut(2).enumerate() {
-                let x = idx as f64 * dims.2;
-                let y = p as f64 * dims.2;
-                verts[0].position[1] = noise.get([x, y, time]) as f32;
-                verts[1].position[1] = noise.get([x, y + dims.2 * dims.3, time]) as f32;
+        for shape in self.shapes.iter_mut_shapes() {
+            for (idx, vertex) in shape.vertices.mut_data().iter_mut().enumerate() {
+                let (row, col) = (idx / cols, idx % cols);
+                let x = col as f64 * dims.1;
+                let y = row as f64 * dims.1;
+                let height = noise.get([x, y, time]) as f32;
+                vertex.position[1] = height;
+                if let Some(h) = heights.get_mut(idx) {
+                    *h = height;
+                }
             }
             shape.vertices.update_buffer();
-        });
+        }
         self.time += 0.01;
     }
 }
 
 impl Manipulate for Landscape {
-    fn rotate_axis(&mut self, axis: usize, ang: f32) {
-        self.shapes.rotate_axis(axis, ang);
+    fn rotate(&mut self, axis: Vec3, angle: f32) {
+        self.shapes.rotate(axis, angle);
+    }
+}
+
+// The heightmap as comma separated rows
+impl Scene for Landscape {
+    fn dump(&self, out: &mut dyn Write) -> io::Result<()> {
+        for row in self.heights.chunks(self.dims.0 as usize) {
+            let row: Vec<String> = row.iter().map(|h| h.to_string()).collect();
+            writeln!(out, "{}", row.join(","))?;
+        }
+        Ok(())
+    }
+
+    // The lines zigzag between rows like a triangle strip does, so they bake to a surface
+    fn mesh(&self) -> Option<Mesh> {
+        let shape = self.shapes.shapes.first()?;
+        let transforms = self.shapes.transforms.first()?.ref_data();
+        let primitive = PrimitiveType::TriangleStrip;
+        let mut mesh = model::bake_as(shape.as_ref(), transforms, primitive).ok()?;
+        mesh.smooth_normals();
+        Some(mesh)
+    }
+
+    // Starts above the near edge looking down over the terrain
+    fn camera(&self) -> Box<dyn Camera> {
+        Box::new(FlyCamera::new(Vec3::new(0.0, 1.0, 1.0), 0.0, -FRAC_PI_4))
     }
 }
@@ -6,17 +6,78 @@ extern crate rayon;
 extern crate winit;
 
 mod boids;
+mod camera;
 mod drawable;
 mod landscape;
+mod math;
+mod model;
+mod planets;
 mod runnable;
+mod shader;
 mod util;
+mod viewer;
 mod gol;
 
 use runnable::app::App;
-use runnable::engine::{Engine, Updatable};
+use runnable::cli::{self, Command};
+use runnable::engine::Engine;
+use runnable::scene::SceneRegistry;
+use std::{env, process};
+use util::random;
 
 fn main() {
+    let mut args = env::args();
+    let program = args.next().unwrap_or_else(|| "opengl-template".to_string());
+    let scenes = SceneRegistry::default();
+    let options = match cli::parse(args, &scenes) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", cli::usage(&program, &scenes));
+            return;
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            eprintln!("Run '{} --help' to see the options", program);
+            process::exit(2);
+        }
+    };
+    if let Some(seed) = options.seed {
+        random::seed(seed);
+    }
+    // Compares the compute shader boids against the CPU ones and exits
+    if options.check_gpu_boids {
+        process::exit(boids::gpu::run_check(options.params.boids, 5));
+    }
+
+    if let Some(ticks) = options.headless {
+        let path = options
+            .output
+            .clone()
+            .unwrap_or_else(|| format!("{}-{}.txt", options.scene, ticks).into());
+        let export = options.export.as_deref();
+        let run = App::run_headless(
+            &scenes,
+            &options.scene,
+            &options.params,
+            ticks,
+            &path,
+            export,
+            options.ascii,
+        );
+        if let Err(err) = run {
+            eprintln!("error: headless run failed: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
     let mut app = App::default_app();
-    let engine: Engine = Engine::init(&app.event_loop_ref());
+    let engine = match Engine::with_options(&app.event_loop_ref(), &options, scenes) {
+        Ok(engine) => engine,
+        Err(err) => {
+            eprintln!("error: couldn't start: {}", err);
+            process::exit(1);
+        }
+    };
     app.run(engine);
 }
@@ -0,0 +1,554 @@
+use super::quaternion::Quaternion;
+use super::vector::{Vec3, Vec4};
+use std::ops;
+
+///
+/// 3x3 matrix stored column by column, `cols[column][row]`.
+/// Used for rotations and for transforming normals.
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat3 {
+    pub cols: [[f32; 3]; 3],
+}
+
+///
+/// 4x4 matrix stored column by column, `cols[column][row]`, the layout glium
+/// sends to shaders, so `cols` can be used as a `mat4` uniform as it is.
+/// Builders follow OpenGL's conventions: right handed, looking down -z,
+/// with clip space depth from -1 to 1.
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4 {
+    pub cols: [[f32; 4]; 4],
+}
+
+impl Default for Mat3 {
+    fn default() -> Self {
+        Mat3::IDENTITY
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Mat4::IDENTITY
+    }
+}
+
+impl Mat3 {
+    pub const IDENTITY: Mat3 = Mat3 {
+        cols: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+    };
+
+    pub fn from_cols(x: Vec3, y: Vec3, z: Vec3) -> Self {
+        Mat3 {
+            cols: [x.into(), y.into(), z.into()],
+        }
+    }
+
+    pub fn from_scale(scale: Vec3) -> Self {
+        Mat3::from_cols(Vec3::X * scale.x, Vec3::Y * scale.y, Vec3::Z * scale.z)
+    }
+
+    pub fn from_quat(rotation: Quaternion) -> Self {
+        let Quaternion { x, y, z, w } = rotation;
+        let (x2, y2, z2) = (x + x, y + y, z + z);
+        let (xx, xy, xz) = (x * x2, x * y2, x * z2);
+        let (yy, yz, zz) = (y * y2, y * z2, z * z2);
+        let (wx, wy, wz) = (w * x2, w * y2, w * z2);
+        Mat3 {
+            cols: [
+                [1.0 - (yy + zz), xy + wz, xz - wy],
+                [xy - wz, 1.0 - (xx + zz), yz + wx],
+                [xz + wy, yz - wx, 1.0 - (xx + yy)],
+            ],
+        }
+    }
+
+    pub fn col(&self, index: usize) -> Vec3 {
+        Vec3::from(self.cols[index])
+    }
+
+    pub fn row(&self, index: usize) -> Vec3 {
+        Vec3::new(
+            self.cols[0][index],
+            self.cols[1][index],
+            self.cols[2][index],
+        )
+    }
+
+    pub fn transpose(&self) -> Self {
+        Mat3::from_cols(self.row(0), self.row(1), self.row(2))
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let a = &self.cols;
+        a[0][0] * (a[1][1] * a[2][2] - a[1][2] * a[2][1])
+            - a[0][1] * (a[1][0] * a[2][2] - a[1][2] * a[2][0])
+            + a[0][2] * (a[1][0] * a[2][1] - a[1][1] * a[2][0])
+    }
+
+    // None if the matrix squashes space flat and can't be undone
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let a = &self.cols;
+        let cols = [
+            [
+                a[1][1] * a[2][2] - a[1][2] * a[2][1],
+                a[0][2] * a[2][1] - a[0][1] * a[2][2],
+                a[0][1] * a[1][2] - a[0][2] * a[1][1],
+            ],
+            [
+                a[1][2] * a[2][0] - a[1][0] * a[2][2],
+                a[0][0] * a[2][2] - a[0][2] * a[2][0],
+                a[0][2] * a[1][0] - a[0][0] * a[1][2],
+            ],
+            [
+                a[1][0] * a[2][1] - a[1][1] * a[2][0],
+                a[0][1] * a[2][0] - a[0][0] * a[2][1],
+                a[0][0] * a[1][1] - a[0][1] * a[1][0],
+            ],
+        ];
+        Some(Mat3 { cols } * (1.0 / det))
+    }
+
+    // Transforms normals the same way the matrix transforms surfaces, None if it can't be inverted
+    pub fn normal_matrix(&self) -> Option<Self> {
+        self.inverse().map(|inverse| inverse.transpose())
+    }
+
+    pub fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        (0..3).all(|i| self.col(i).approx_eq(other.col(i), epsilon))
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        cols: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    pub fn from_cols(x: Vec4, y: Vec4, z: Vec4, w: Vec4) -> Self {
+        Mat4 {
+            cols: [x.into(), y.into(), z.into(), w.into()],
+        }
+    }
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        let mut matrix = Mat4::IDENTITY;
+        matrix.cols[3] = translation.extend(1.0).into();
+        matrix
+    }
+
+    pub fn from_scale(scale: Vec3) -> Self {
+        Mat4::from(Mat3::from_scale(scale))
+    }
+
+    pub fn from_quat(rotation: Quaternion) -> Self {
+        Mat4::from(Mat3::from_quat(rotation))
+    }
+
+    // Scales, then rotates, then translates
+    pub fn from_scale_rotation_translation(
+        scale: Vec3,
+        rotation: Quaternion,
+        translation: Vec3,
+    ) -> Self {
+        let rotation = Mat3::from_quat(rotation);
+        Mat4::from_cols(
+            (rotation.col(0) * scale.x).extend(0.0),
+            (rotation.col(1) * scale.y).extend(0.0),
+            (rotation.col(2) * scale.z).extend(0.0),
+            translation.extend(1.0),
+        )
+    }
+
+    // View matrix for an eye at `eye` looking towards `target`, `up` doesn't have to be exact
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        let f = (target - eye).normalize();
+        let s = f.cross(up).normalize();
+        let u = s.cross(f);
+        Mat4 {
+            cols: [
+                [s.x, u.x, -f.x, 0.0],
+                [s.y, u.y, -f.y, 0.0],
+                [s.z, u.z, -f.z, 0.0],
+                [-s.dot(eye), -u.dot(eye), f.dot(eye), 1.0],
+            ],
+        }
+    }
+
+    // `fov` is the vertical field of view in radians, `aspect` is the width over the height
+    pub fn perspective(fov: f32, aspect: f32, znear: f32, zfar: f32) -> Self {
+        let f = 1.0 / (fov / 2.0).tan();
+        Mat4 {
+            cols: [
+                [f / aspect, 0.0, 0.0, 0.0],
+                [0.0, f, 0.0, 0.0],
+                [0.0, 0.0, (zfar + znear) / (znear - zfar), -1.0],
+                [0.0, 0.0, (2.0 * zfar * znear) / (znear - zfar), 0.0],
+            ],
+        }
+    }
+
+    pub fn ortho(left: f32, right: f32, bottom: f32, top: f32, znear: f32, zfar: f32) -> Self {
+        Mat4 {
+            cols: [
+                [2.0 / (right - left), 0.0, 0.0, 0.0],
+                [0.0, 2.0 / (top - bottom), 0.0, 0.0],
+                [0.0, 0.0, -2.0 / (zfar - znear), 0.0],
+                [
+                    -(right + left) / (right - left),
+                    -(top + bottom) / (top - bottom),
+                    -(zfar + znear) / (zfar - znear),
+                    1.0,
+                ],
+            ],
+        }
+    }
+
+    pub fn col(&self, index: usize) -> Vec4 {
+        Vec4::from(self.cols[index])
+    }
+
+    pub fn row(&self, index: usize) -> Vec4 {
+        Vec4::new(
+            self.cols[0][index],
+            self.cols[1][index],
+            self.cols[2][index],
+            self.cols[3][index],
+        )
+    }
+
+    pub fn transpose(&self) -> Self {
+        Mat4::from_cols(self.row(0), self.row(1), self.row(2), self.row(3))
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let (s, c) = self.minors();
+        s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+    }
+
+    // 2x2 determinants of the first two columns and of the last two, shared by the determinant and inverse
+    fn minors(&self) -> ([f32; 6], [f32; 6]) {
+        let a = &self.cols;
+        let s = [
+            a[0][0] * a[1][1] - a[1][0] * a[0][1],
+            a[0][0] * a[1][2] - a[1][0] * a[0][2],
+            a[0][0] * a[1][3] - a[1][0] * a[0][3],
+            a[0][1] * a[1][2] - a[1][1] * a[0][2],
+            a[0][1] * a[1][3] - a[1][1] * a[0][3],
+            a[0][2] * a[1][3] - a[1][2] * a[0][3],
+        ];
+        let c = [
+            a[2][0] * a[3][1] - a[3][0] * a[2][1],
+            a[2][0] * a[3][2] - a[3][0] * a[2][2],
+            a[2][0] * a[3][3] - a[3][0] * a[2][3],
+            a[2][1] * a[3][2] - a[3][1] * a[2][2],
+            a[2][1] * a[3][3] - a[3][1] * a[2][3],
+            a[2][2] * a[3][3] - a[3][2] * a[2][3],
+        ];
+        (s, c)
+    }
+
+    // None if the matrix squashes space flat and can't be undone
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let (s, c) = self.minors();
+        let a = &self.cols;
+        let cols = [
+            [
+                a[1][1] * c[5] - a[1][2] * c[4] + a[1][3] * c[3],
+                -a[0][1] * c[5] + a[0][2] * c[4] - a[0][3] * c[3],
+                a[3][1] * s[5] - a[3][2] * s[4] + a[3][3] * s[3],
+                -a[2][1] * s[5] + a[2][2] * s[4] - a[2][3] * s[3],
+            ],
+            [
+                -a[1][0] * c[5] + a[1][2] * c[2] - a[1][3] * c[1],
+                a[0][0] * c[5] - a[0][2] * c[2] + a[0][3] * c[1],
+                -a[3][0] * s[5] + a[3][2] * s[2] - a[3][3] * s[1],
+                a[2][0] * s[5] - a[2][2] * s[2] + a[2][3] * s[1],
+            ],
+            [
+                a[1][0] * c[4] - a[1][1] * c[2] + a[1][3] * c[0],
+                -a[0][0] * c[4] + a[0][1] * c[2] - a[0][3] * c[0],
+                a[3][0] * s[4] - a[3][1] * s[2] + a[3][3] * s[0],
+                -a[2][0] * s[4] + a[2][1] * s[2] - a[2][3] * s[0],
+            ],
+            [
+                -a[1][0] * c[3] + a[1][1] * c[1] - a[1][2] * c[0],
+                a[0][0] * c[3] - a[0][1] * c[1] + a[0][2] * c[0],
+                -a[3][0] * s[3] + a[3][1] * s[1] - a[3][2] * s[0],
+                a[2][0] * s[3] - a[2][1] * s[1] + a[2][2] * s[0],
+            ],
+        ];
+        Some(Mat4 { cols } * (1.0 / det))
+    }
+
+    // Applies the matrix to a point, translation included
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        (*self * point.extend(1.0)).truncate()
+    }
+
+    // Applies the matrix to a direction, which translation doesn't affect
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        (*self * vector.extend(0.0)).truncate()
+    }
+
+    pub fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        (0..4).all(|i| self.col(i).approx_eq(other.col(i), epsilon))
+    }
+}
+
+// The upper left 3x3, which holds the rotation and scale
+impl From<Mat4> for Mat3 {
+    fn from(value: Mat4) -> Self {
+        Mat3::from_cols(
+            value.col(0).truncate(),
+            value.col(1).truncate(),
+            value.col(2).truncate(),
+        )
+    }
+}
+
+impl From<Mat3> for Mat4 {
+    fn from(value: Mat3) -> Self {
+        Mat4::from_cols(
+            value.col(0).extend(0.0),
+            value.col(1).extend(0.0),
+            value.col(2).extend(0.0),
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+}
+
+impl From<[[f32; 4]; 4]> for Mat4 {
+    fn from(cols: [[f32; 4]; 4]) -> Self {
+        Mat4 { cols }
+    }
+}
+
+impl From<Mat4> for [[f32; 4]; 4] {
+    fn from(value: Mat4) -> Self {
+        value.cols
+    }
+}
+
+impl ops::Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+    fn mul(self, rhs: Vec3) -> Self::Output {
+        self.col(0) * rhs.x + self.col(1) * rhs.y + self.col(2) * rhs.z
+    }
+}
+
+impl ops::Mul for Mat3 {
+    type Output = Mat3;
+    fn mul(self, rhs: Self) -> Self::Output {
+        Mat3::from_cols(self * rhs.col(0), self * rhs.col(1), self * rhs.col(2))
+    }
+}
+
+impl ops::Mul<f32> for Mat3 {
+    type Output = Mat3;
+    fn mul(self, rhs: f32) -> Self::Output {
+        Mat3::from_cols(self.col(0) * rhs, self.col(1) * rhs, self.col(2) * rhs)
+    }
+}
+
+impl ops::Mul<Vec4> for Mat4 {
+    type Output = Vec4;
+    fn mul(self, rhs: Vec4) -> Self::Output {
+        self.col(0) * rhs.x + self.col(1) * rhs.y + self.col(2) * rhs.z + self.col(3) * rhs.w
+    }
+}
+
+impl ops::Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, rhs: Self) -> Self::Output {
+        Mat4::from_cols(
+            self * rhs.col(0),
+            self * rhs.col(1),
+            self * rhs.col(2),
+            self * rhs.col(3),
+        )
+    }
+}
+
+impl ops::Mul<f32> for Mat4 {
+    type Output = Mat4;
+    fn mul(self, rhs: f32) -> Self::Output {
+        Mat4::from_cols(
+            self.col(0) * rhs,
+            self.col(1) * rhs,
+            self.col(2) * rhs,
+            self.col(3) * rhs,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::f32::consts::FRAC_PI_2;
+
+    const EPSILON: f32 = 1e-4;
+    const CASES: usize = 500;
+
+    fn random_vec3(rng: &mut StdRng, range: f32) -> Vec3 {
+        Vec3::new(
+            rng.gen_range(-range..range),
+            rng.gen_range(-range..range),
+            rng.gen_range(-range..range),
+        )
+    }
+
+    fn random_rotation(rng: &mut StdRng) -> Quaternion {
+        let axis = random_vec3(rng, 1.0).try_normalize().unwrap_or(Vec3::Y);
+        Quaternion::from_axis_angle(axis, rng.gen_range(-10.0..10.0))
+    }
+
+    // Scales kept away from zero so the matrices are always invertible
+    fn random_mat4(rng: &mut StdRng) -> Mat4 {
+        let scale = Vec3::new(
+            rng.gen_range(0.2..3.0),
+            rng.gen_range(0.2..3.0),
+            rng.gen_range(0.2..3.0),
+        );
+        let matrix = Mat4::from_scale_rotation_translation(
+            scale,
+            random_rotation(rng),
+            random_vec3(rng, 10.0),
+        );
+        // Shear it too, by another rotation after a scale
+        matrix * Mat4::from_quat(random_rotation(rng)) * Mat4::from_scale(scale)
+    }
+
+    fn random_mat3(rng: &mut StdRng) -> Mat3 {
+        Mat3::from(random_mat4(rng))
+    }
+
+    // The point after the perspective divide
+    fn project(matrix: &Mat4, point: Vec3) -> Vec3 {
+        let clip = *matrix * point.extend(1.0);
+        clip.truncate() / clip.w
+    }
+
+    #[test]
+    fn inverse_times_matrix_is_identity() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..CASES {
+            let m = random_mat4(&mut rng);
+            let inverse = m.inverse().unwrap();
+            assert!((inverse * m).approx_eq(&Mat4::IDENTITY, EPSILON), "{:?}", m);
+            assert!((m * inverse).approx_eq(&Mat4::IDENTITY, EPSILON), "{:?}", m);
+
+            let m = random_mat3(&mut rng);
+            let inverse = m.inverse().unwrap();
+            assert!((inverse * m).approx_eq(&Mat3::IDENTITY, EPSILON), "{:?}", m);
+        }
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        let flat = Vec3::new(1.0, 0.0, 1.0);
+        assert!(Mat4::from_scale(flat).inverse().is_none());
+        assert!(Mat3::from_scale(flat).inverse().is_none());
+        assert!(Mat3::from_scale(flat).normal_matrix().is_none());
+    }
+
+    #[test]
+    fn transposing_twice_gives_the_same_matrix() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..CASES {
+            let m = random_mat4(&mut rng);
+            assert_eq!(m.transpose().transpose(), m);
+            assert_eq!(m.transpose().row(1), m.col(1));
+            let m = random_mat3(&mut rng);
+            assert_eq!(m.transpose().transpose(), m);
+        }
+    }
+
+    #[test]
+    fn determinant_of_a_product_is_the_product_of_determinants() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..CASES {
+            let (a, b) = (random_mat4(&mut rng), random_mat4(&mut rng));
+            let (product, expected) = ((a * b).determinant(), a.determinant() * b.determinant());
+            assert!((product - expected).abs() <= expected.abs() * EPSILON);
+        }
+    }
+
+    #[test]
+    fn normal_matrix_keeps_normals_perpendicular() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for _ in 0..CASES {
+            let m = random_mat3(&mut rng);
+            let (tangent, other) = (random_vec3(&mut rng, 1.0), random_vec3(&mut rng, 1.0));
+            let normal = tangent.cross(other);
+            let moved = (m * normal).dot(m * tangent);
+            let kept = (m.normal_matrix().unwrap() * normal).dot(m * tangent);
+            assert!(kept.abs() <= EPSILON * (1.0 + moved.abs()), "{}", kept);
+        }
+    }
+
+    #[test]
+    fn look_at_puts_the_target_down_negative_z() {
+        let view = Mat4::look_at(Vec3::new(0.0, 0.0, 5.0), Vec3::ZERO, Vec3::Y);
+        let expected = Mat4::from_translation(Vec3::new(0.0, 0.0, -5.0));
+        assert!(view.approx_eq(&expected, EPSILON));
+
+        // Looking down -x, world -z is off to the right
+        let view = Mat4::look_at(Vec3::new(5.0, 0.0, 0.0), Vec3::ZERO, Vec3::Y);
+        let cases = [
+            (Vec3::new(5.0, 0.0, 0.0), Vec3::ZERO),
+            (Vec3::ZERO, Vec3::new(0.0, 0.0, -5.0)),
+            (Vec3::new(0.0, 0.0, -1.0), Vec3::new(1.0, 0.0, -5.0)),
+            (Vec3::new(0.0, 2.0, 0.0), Vec3::new(0.0, 2.0, -5.0)),
+        ];
+        for (world, eye) in cases {
+            assert!(view.transform_point(world).approx_eq(eye, EPSILON));
+        }
+        // Views only turn and move, so they keep lengths
+        assert!((view.determinant() - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn perspective_maps_the_frustum_to_clip_space() {
+        let projection = Mat4::perspective(FRAC_PI_2, 2.0, 1.0, 10.0);
+        let cases = [
+            (Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, -1.0)),
+            (Vec3::new(0.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 1.0)),
+            (Vec3::new(2.0, 1.0, -1.0), Vec3::new(1.0, 1.0, -1.0)),
+            (Vec3::new(-20.0, -10.0, -10.0), Vec3::new(-1.0, -1.0, 1.0)),
+        ];
+        for (eye, ndc) in cases {
+            let projected = project(&projection, eye);
+            assert!(projected.approx_eq(ndc, EPSILON), "{:?}", projected);
+        }
+    }
+
+    #[test]
+    fn ortho_maps_the_box_to_clip_space() {
+        let projection = Mat4::ortho(-2.0, 2.0, -1.0, 3.0, 0.1, 10.0);
+        let cases = [
+            (Vec3::new(2.0, 3.0, -0.1), Vec3::new(1.0, 1.0, -1.0)),
+            (Vec3::new(-2.0, -1.0, -10.0), Vec3::new(-1.0, -1.0, 1.0)),
+            (Vec3::new(0.0, 1.0, -5.05), Vec3::ZERO),
+        ];
+        for (eye, ndc) in cases {
+            let projected = project(&projection, eye);
+            assert!(projected.approx_eq(ndc, EPSILON), "{:?}", projected);
+        }
+    }
+}
@@ -0,0 +1,11 @@
+pub(crate) mod matrix;
+pub(crate) mod quaternion;
+pub(crate) mod transform;
+pub(crate) mod vector;
+
+// Vectors, matrices and rotations for the simulations and cameras,
+// kept apart from the vertex types that get uploaded to the GPU
+pub use self::matrix::{Mat3, Mat4};
+pub use self::quaternion::Quaternion;
+pub use self::transform::Transform;
+pub use self::vector::{Vec2, Vec3, Vec4};
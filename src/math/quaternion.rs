@@ -0,0 +1,303 @@
+use super::matrix::{Mat3, Mat4};
+use super::vector::Vec3;
+use std::ops;
+
+///
+/// Rotation stored as a unit quaternion, `xyz` the vector part and `w` the scalar.
+/// `a * b` rotates by `b` first, then by `a`, the same order as matrices.
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quaternion {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Quaternion::IDENTITY
+    }
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+        w: 1.0,
+    };
+
+    pub const fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Quaternion { x, y, z, w }
+    }
+
+    // Turns `angle` radians anticlockwise around `axis`, which doesn't need to be normalised
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let axis = axis.normalize() * (angle / 2.0).sin();
+        Quaternion::new(axis.x, axis.y, axis.z, (angle / 2.0).cos())
+    }
+
+    // The shortest rotation that turns the direction `from` into the direction `to`
+    pub fn from_rotation_arc(from: Vec3, to: Vec3) -> Self {
+        let (from, to) = (from.normalize(), to.normalize());
+        let dot = from.dot(to);
+        if dot < -1.0 + 1e-6 {
+            // Opposite directions, any axis at right angles will do
+            return Quaternion::from_axis_angle(from.any_orthogonal(), std::f32::consts::PI);
+        }
+        let axis = from.cross(to);
+        Quaternion::new(axis.x, axis.y, axis.z, 1.0 + dot).normalize()
+    }
+
+    // Rotation whose columns are the given orthonormal basis
+    pub fn from_mat3(matrix: &Mat3) -> Self {
+        let m = &matrix.cols;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quaternion::new(
+                (m[1][2] - m[2][1]) / s,
+                (m[2][0] - m[0][2]) / s,
+                (m[0][1] - m[1][0]) / s,
+                s / 4.0,
+            )
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            Quaternion::new(
+                s / 4.0,
+                (m[1][0] + m[0][1]) / s,
+                (m[2][0] + m[0][2]) / s,
+                (m[1][2] - m[2][1]) / s,
+            )
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            Quaternion::new(
+                (m[1][0] + m[0][1]) / s,
+                s / 4.0,
+                (m[2][1] + m[1][2]) / s,
+                (m[2][0] - m[0][2]) / s,
+            )
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            Quaternion::new(
+                (m[2][0] + m[0][2]) / s,
+                (m[2][1] + m[1][2]) / s,
+                s / 4.0,
+                (m[0][1] - m[1][0]) / s,
+            )
+        };
+        q.normalize()
+    }
+
+    pub fn vector(self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+
+    pub fn dot(self, other: Self) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    // Back to unit length, which rounding errors drift away from over many products
+    pub fn normalize(self) -> Self {
+        let length = self.length();
+        Quaternion::new(
+            self.x / length,
+            self.y / length,
+            self.z / length,
+            self.w / length,
+        )
+    }
+
+    pub fn conjugate(self) -> Self {
+        Quaternion::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    // The opposite rotation
+    pub fn inverse(self) -> Self {
+        let length_sq = self.dot(self);
+        let c = self.conjugate();
+        Quaternion::new(
+            c.x / length_sq,
+            c.y / length_sq,
+            c.z / length_sq,
+            c.w / length_sq,
+        )
+    }
+
+    // The axis turned around and by how many radians
+    pub fn to_axis_angle(self) -> (Vec3, f32) {
+        let q = self.normalize();
+        let angle = 2.0 * q.w.clamp(-1.0, 1.0).acos();
+        let axis = q.vector().try_normalize().unwrap_or(Vec3::X);
+        (axis, angle)
+    }
+
+    pub fn to_mat3(self) -> Mat3 {
+        Mat3::from_quat(self)
+    }
+
+    pub fn to_mat4(self) -> Mat4 {
+        Mat4::from_quat(self)
+    }
+
+    // Turns at a constant rate from `self` to `other`, the short way round
+    pub fn slerp(self, other: Self, t: f32) -> Self {
+        let mut dot = self.dot(other);
+        let other = if dot < 0.0 {
+            dot = -dot;
+            Quaternion::new(-other.x, -other.y, -other.z, -other.w)
+        } else {
+            other
+        };
+        // Too close together for the angle to be accurate, a straight line is as good
+        let (a, b) = if dot > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = dot.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Quaternion::new(
+            self.x * a + other.x * b,
+            self.y * a + other.y * b,
+            self.z * a + other.z * b,
+            self.w * a + other.w * b,
+        )
+        .normalize()
+    }
+
+    // Both describe the same rotation to within `epsilon`, q and -q included
+    pub fn approx_eq(self, other: Self, epsilon: f32) -> bool {
+        1.0 - self.dot(other).abs() <= epsilon
+    }
+}
+
+impl ops::Mul for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, rhs: Self) -> Self::Output {
+        Quaternion::new(
+            self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+            self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+        )
+    }
+}
+
+impl ops::MulAssign for Quaternion {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+// Rotates a vector
+impl ops::Mul<Vec3> for Quaternion {
+    type Output = Vec3;
+    fn mul(self, rhs: Vec3) -> Self::Output {
+        let q = self.vector();
+        let t = q.cross(rhs) * 2.0;
+        rhs + t * self.w + q.cross(t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::f32::consts::PI;
+
+    const EPSILON: f32 = 1e-4;
+    const CASES: usize = 500;
+
+    fn random_vec3(rng: &mut StdRng) -> Vec3 {
+        Vec3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        )
+    }
+
+    fn random_rotation(rng: &mut StdRng) -> Quaternion {
+        let axis = random_vec3(rng).try_normalize().unwrap_or(Vec3::Y);
+        Quaternion::from_axis_angle(axis, rng.gen_range(-PI..PI))
+    }
+
+    #[test]
+    fn matrices_turn_vectors_the_same_way() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..CASES {
+            let (q, v) = (random_rotation(&mut rng), random_vec3(&mut rng));
+            let turned = q * v;
+            assert!((q.to_mat3() * v).approx_eq(turned, EPSILON));
+            assert!(q.to_mat4().transform_vector(v).approx_eq(turned, EPSILON));
+            assert!((turned.length() - v.length()).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn products_match_matrix_products() {
+        let mut rng = StdRng::seed_from_u64(6);
+        for _ in 0..CASES {
+            let (a, b) = (random_rotation(&mut rng), random_rotation(&mut rng));
+            let product = (a * b).to_mat3();
+            assert!(product.approx_eq(&(a.to_mat3() * b.to_mat3()), EPSILON));
+            // Rotation matrices are orthonormal
+            let m = a.to_mat3();
+            assert!((m * m.transpose()).approx_eq(&Mat3::IDENTITY, EPSILON));
+            assert!((m.determinant() - 1.0).abs() < EPSILON);
+            assert!(a.inverse().to_mat3().approx_eq(&m.transpose(), EPSILON));
+        }
+    }
+
+    #[test]
+    fn round_trips_through_a_matrix() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..CASES {
+            let q = random_rotation(&mut rng);
+            assert!(Quaternion::from_mat3(&q.to_mat3()).approx_eq(q, EPSILON));
+        }
+        // Half turns, where the trace is at its most negative
+        for axis in [
+            Vec3::X,
+            Vec3::Y,
+            Vec3::Z,
+            Vec3::new(1.0, 1.0, 0.0).normalize(),
+        ] {
+            let q = Quaternion::from_axis_angle(axis, PI);
+            assert!(Quaternion::from_mat3(&q.to_mat3()).approx_eq(q, EPSILON));
+        }
+    }
+
+    #[test]
+    fn axis_angle_round_trips() {
+        let mut rng = StdRng::seed_from_u64(8);
+        for _ in 0..CASES {
+            let axis = random_vec3(&mut rng).normalize();
+            let angle = rng.gen_range(0.01..PI);
+            let (back_axis, back_angle) = Quaternion::from_axis_angle(axis, angle).to_axis_angle();
+            assert!(back_axis.approx_eq(axis, EPSILON));
+            assert!((back_angle - angle).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn slerp_turns_at_a_constant_rate() {
+        let mut rng = StdRng::seed_from_u64(9);
+        for _ in 0..CASES {
+            let (a, b) = (random_rotation(&mut rng), random_rotation(&mut rng));
+            assert!(a.slerp(b, 0.0).approx_eq(a, EPSILON));
+            assert!(a.slerp(b, 1.0).approx_eq(b, EPSILON));
+            let total = (a.inverse() * b).to_axis_angle().1;
+            let total = total.min(2.0 * PI - total);
+            let half = (a.inverse() * a.slerp(b, 0.5)).to_axis_angle().1;
+            let half = half.min(2.0 * PI - half);
+            assert!((half - total / 2.0).abs() < 1e-3, "{} {}", half, total);
+        }
+    }
+}
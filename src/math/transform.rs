@@ -0,0 +1,74 @@
+use super::matrix::Mat4;
+use super::quaternion::Quaternion;
+use super::vector::Vec3;
+
+///
+/// Where an object sits, how it's turned and how it's stretched.
+/// Scale and rotation happen around `pivot`, a point in the object's own space,
+/// before the whole thing is moved by `translation`.
+/// The rotation is kept as a unit quaternion and the matrix rebuilt from the parts,
+/// so turning an object over and over doesn't skew or shrink it.
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Vec3,
+    pub pivot: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        translation: Vec3::ZERO,
+        rotation: Quaternion::IDENTITY,
+        scale: Vec3::new(1.0, 1.0, 1.0),
+        pivot: Vec3::ZERO,
+    };
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Transform {
+            translation,
+            ..Transform::IDENTITY
+        }
+    }
+
+    // Turns `angle` radians anticlockwise around `axis` through the pivot, the axis given in the parent's space
+    pub fn rotate(&mut self, axis: Vec3, angle: f32) {
+        self.rotate_by(Quaternion::from_axis_angle(axis, angle));
+    }
+
+    pub fn rotate_by(&mut self, rotation: Quaternion) {
+        self.rotation = (rotation * self.rotation).normalize();
+    }
+
+    // Swings the whole object around `point` in the parent's space, moving it as well as turning it
+    pub fn rotate_about(&mut self, point: Vec3, axis: Vec3, angle: f32) {
+        let rotation = Quaternion::from_axis_angle(axis, angle);
+        let pivot = self.translation + self.pivot;
+        self.translation = point + rotation * (pivot - point) - self.pivot;
+        self.rotate_by(rotation);
+    }
+
+    pub fn translate(&mut self, offset: Vec3) {
+        self.translation += offset;
+    }
+
+    // Model matrix, taking points from the object's space to the parent's
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(
+            self.scale,
+            self.rotation,
+            self.transform_point(Vec3::ZERO),
+        )
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.translation + self.pivot + self.rotation * ((point - self.pivot) * self.scale)
+    }
+}
@@ -0,0 +1,256 @@
+use std::fmt::{Display, Formatter};
+use std::iter::Sum;
+use std::ops;
+
+// Everything the vector types have in common, component by component
+macro_rules! vector {
+    ($name:ident, $n:expr, $($field:ident),+) => {
+        #[derive(Copy, Clone, Debug, Default, PartialEq)]
+        pub struct $name {
+            $(pub $field: f32),+
+        }
+
+        impl $name {
+            pub const ZERO: $name = $name { $($field: 0.0),+ };
+
+            pub const fn new($($field: f32),+) -> Self {
+                $name { $($field),+ }
+            }
+
+            // Every component set to `value`
+            pub const fn splat(value: f32) -> Self {
+                $name { $($field: value),+ }
+            }
+
+            pub fn dot(self, other: Self) -> f32 {
+                0.0 $(+ self.$field * other.$field)+
+            }
+
+            pub fn length_sq(self) -> f32 {
+                self.dot(self)
+            }
+
+            pub fn length(self) -> f32 {
+                self.length_sq().sqrt()
+            }
+
+            pub fn distance(self, other: Self) -> f32 {
+                (other - self).length()
+            }
+
+            // Same direction with a length of 1, only meaningful for non zero vectors
+            pub fn normalize(self) -> Self {
+                self / self.length()
+            }
+
+            // Same direction with a length of 1, or None if there's no direction to keep
+            pub fn try_normalize(self) -> Option<Self> {
+                let length = self.length();
+                if length > 0.0 && length.is_finite() {
+                    Some(self / length)
+                } else {
+                    None
+                }
+            }
+
+            // Shortened to `max` if it's any longer
+            pub fn clamp_length(self, max: f32) -> Self {
+                let length_sq = self.length_sq();
+                if length_sq <= max * max {
+                    return self;
+                }
+                self / length_sq.sqrt() * max
+            }
+
+            // The point `t` of the way from `self` to `other`
+            pub fn lerp(self, other: Self, t: f32) -> Self {
+                self + (other - self) * t
+            }
+
+            pub fn min(self, other: Self) -> Self {
+                $name { $($field: self.$field.min(other.$field)),+ }
+            }
+
+            pub fn max(self, other: Self) -> Self {
+                $name { $($field: self.$field.max(other.$field)),+ }
+            }
+
+            pub fn abs(self) -> Self {
+                $name { $($field: self.$field.abs()),+ }
+            }
+
+            pub fn is_finite(self) -> bool {
+                true $(&& self.$field.is_finite())+
+            }
+
+            // Whether every component is within `epsilon` of the other's
+            pub fn approx_eq(self, other: Self, epsilon: f32) -> bool {
+                true $(&& (self.$field - other.$field).abs() <= epsilon)+
+            }
+        }
+
+        impl ops::Add for $name {
+            type Output = $name;
+            fn add(self, other: Self) -> Self::Output {
+                $name { $($field: self.$field + other.$field),+ }
+            }
+        }
+
+        impl ops::AddAssign for $name {
+            fn add_assign(&mut self, other: Self) {
+                $(self.$field += other.$field;)+
+            }
+        }
+
+        impl ops::Sub for $name {
+            type Output = $name;
+            fn sub(self, other: Self) -> Self::Output {
+                $name { $($field: self.$field - other.$field),+ }
+            }
+        }
+
+        impl ops::SubAssign for $name {
+            fn sub_assign(&mut self, other: Self) {
+                $(self.$field -= other.$field;)+
+            }
+        }
+
+        // Component by component, for scaling each axis separately
+        impl ops::Mul for $name {
+            type Output = $name;
+            fn mul(self, other: Self) -> Self::Output {
+                $name { $($field: self.$field * other.$field),+ }
+            }
+        }
+
+        impl ops::Mul<f32> for $name {
+            type Output = $name;
+            fn mul(self, rhs: f32) -> Self::Output {
+                $name { $($field: self.$field * rhs),+ }
+            }
+        }
+
+        impl ops::Mul<$name> for f32 {
+            type Output = $name;
+            fn mul(self, rhs: $name) -> Self::Output {
+                rhs * self
+            }
+        }
+
+        impl ops::MulAssign<f32> for $name {
+            fn mul_assign(&mut self, rhs: f32) {
+                $(self.$field *= rhs;)+
+            }
+        }
+
+        impl ops::Div<f32> for $name {
+            type Output = $name;
+            fn div(self, rhs: f32) -> Self::Output {
+                $name { $($field: self.$field / rhs),+ }
+            }
+        }
+
+        impl ops::DivAssign<f32> for $name {
+            fn div_assign(&mut self, rhs: f32) {
+                $(self.$field /= rhs;)+
+            }
+        }
+
+        impl ops::Neg for $name {
+            type Output = $name;
+            fn neg(self) -> Self::Output {
+                $name { $($field: -self.$field),+ }
+            }
+        }
+
+        impl ops::Index<usize> for $name {
+            type Output = f32;
+            fn index(&self, index: usize) -> &f32 {
+                [$(&self.$field),+][index]
+            }
+        }
+
+        impl ops::IndexMut<usize> for $name {
+            fn index_mut(&mut self, index: usize) -> &mut f32 {
+                IntoIterator::into_iter([$(&mut self.$field),+])
+                    .nth(index)
+                    .unwrap_or_else(|| panic!("index {} out of range for {}", index, stringify!($name)))
+            }
+        }
+
+        impl Sum for $name {
+            fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+                iter.fold($name::ZERO, |a, b| a + b)
+            }
+        }
+
+        impl From<[f32; $n]> for $name {
+            fn from(value: [f32; $n]) -> Self {
+                let [$($field),+] = value;
+                $name { $($field),+ }
+            }
+        }
+
+        impl From<$name> for [f32; $n] {
+            fn from(value: $name) -> Self {
+                [$(value.$field),+]
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                let components: Vec<String> = vec![$(self.$field.to_string()),+];
+                write!(f, "({})", components.join(", "))
+            }
+        }
+    };
+}
+
+vector!(Vec2, 2, x, y);
+vector!(Vec3, 3, x, y, z);
+vector!(Vec4, 4, x, y, z, w);
+
+impl Vec2 {
+    // Turned a quarter turn anticlockwise
+    pub fn perp(self) -> Self {
+        Vec2::new(-self.y, self.x)
+    }
+
+    pub fn extend(self, z: f32) -> Vec3 {
+        Vec3::new(self.x, self.y, z)
+    }
+}
+
+impl Vec3 {
+    pub const X: Vec3 = Vec3::new(1.0, 0.0, 0.0);
+    pub const Y: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    pub const Z: Vec3 = Vec3::new(0.0, 0.0, 1.0);
+
+    pub fn cross(self, other: Self) -> Self {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    // Some unit vector at right angles to this one
+    pub fn any_orthogonal(self) -> Self {
+        let other = if self.x.abs() < 0.9 { Vec3::X } else { Vec3::Y };
+        self.cross(other).normalize()
+    }
+
+    pub fn extend(self, w: f32) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, w)
+    }
+
+    pub fn truncate(self) -> Vec2 {
+        Vec2::new(self.x, self.y)
+    }
+}
+
+impl Vec4 {
+    pub fn truncate(self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+}
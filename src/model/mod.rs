@@ -0,0 +1,299 @@
+pub(crate) mod obj;
+pub(crate) mod ply;
+pub(crate) mod stl;
+
+use drawable::mesh::Mesh;
+use drawable::shape::HasShape;
+use drawable::shape_group::ShapeGroup;
+use glium::index::PrimitiveType;
+use math::{Mat3, Vec2, Vec3};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::iter::zip;
+use std::path::{Path, PathBuf};
+use util::attribute::Instance;
+
+#[derive(Debug)]
+pub enum ModelError {
+    Io(PathBuf, io::Error),
+    Write(PathBuf, io::Error),
+    // Something in a text file that couldn't be understood, and the line it's on
+    Parse {
+        file: String,
+        line: usize,
+        message: String,
+    },
+    // Something wrong with a file that isn't down to one line, like a binary file being cut short
+    Invalid {
+        file: String,
+        message: String,
+    },
+    // A file, format or shape this can't handle
+    Unsupported(String),
+}
+
+impl ModelError {
+    pub fn parse(file: &str, line: usize, message: impl ToString) -> Self {
+        ModelError::Parse {
+            file: file.to_string(),
+            line,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn invalid(file: &str, message: impl ToString) -> Self {
+        ModelError::Invalid {
+            file: file.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for ModelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModelError::Io(path, e) => write!(f, "couldn't read '{}': {}", path.display(), e),
+            ModelError::Write(path, e) => write!(f, "couldn't write '{}': {}", path.display(), e),
+            ModelError::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", file, line, message),
+            ModelError::Invalid { file, message } => write!(f, "{}: {}", file, message),
+            ModelError::Unsupported(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for ModelError {}
+
+// Reads any of the formats there's a loader for, picked by the file's extension
+pub fn load(path: &Path) -> Result<Vec<Mesh>, ModelError> {
+    match extension(path).as_str() {
+        "obj" => Ok(obj::load(path)?
+            .meshes
+            .into_iter()
+            .map(|m| m.mesh)
+            .collect()),
+        "stl" => Ok(vec![stl::load(path)?]),
+        "ply" => Ok(vec![ply::load(path)?]),
+        _ => Err(unknown_format(path, ".obj, .stl or .ply")),
+    }
+}
+
+// Writes STL or PLY, picked by the file's extension, as text if `ascii` is set and binary otherwise
+pub fn save(path: &Path, mesh: &Mesh, ascii: bool) -> Result<(), ModelError> {
+    let write = |write: &dyn Fn(&mut dyn Write) -> io::Result<()>| {
+        let file = File::create(path).map_err(|e| ModelError::Write(path.to_path_buf(), e))?;
+        let mut out = BufWriter::new(file);
+        write(&mut out)
+            .and_then(|_| out.flush())
+            .map_err(|e| ModelError::Write(path.to_path_buf(), e))
+    };
+    let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("mesh");
+    let encoding = match ascii {
+        true => ply::Encoding::Ascii,
+        false => ply::Encoding::BinaryLittleEndian,
+    };
+    match extension(path).as_str() {
+        "stl" if ascii => write(&|out| stl::write_ascii(out, mesh, name)),
+        "stl" => write(&|out| stl::write_binary(out, mesh)),
+        "ply" => write(&|out| ply::write(out, mesh, encoding)),
+        _ => Err(unknown_format(path, ".stl or .ply")),
+    }
+}
+
+// Bakes every shape in a group with its instance transforms and saves them as one mesh
+pub fn export<S: HasShape + Send>(
+    path: &Path,
+    group: &ShapeGroup<S>,
+    ascii: bool,
+) -> Result<(), ModelError> {
+    save(path, &bake_group(group)?, ascii)
+}
+
+fn extension(path: &Path) -> String {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    extension.to_ascii_lowercase()
+}
+
+fn unknown_format(path: &Path, expected: &str) -> ModelError {
+    ModelError::Unsupported(format!(
+        "can't tell what format '{}' is, expected {}",
+        path.display(),
+        expected
+    ))
+}
+
+///
+/// A shape's triangles copied once for every instance, moved, turned and scaled the way
+/// each instance's transform draws them. Strips and fans are split into separate triangles,
+/// restarts included. Points and lines have no triangles, so they can't be baked.
+///
+pub fn bake<S: HasShape + ?Sized>(shape: &S, instances: &[Instance]) -> Result<Mesh, ModelError> {
+    bake_as(shape, instances, *shape.ref_index())
+}
+
+// Every shape in a group baked and put together in one mesh
+pub fn bake_group<S: HasShape + Send>(group: &ShapeGroup<S>) -> Result<Mesh, ModelError> {
+    let mut mesh = Mesh::default();
+    for (shape, instances) in zip(&group.shapes, &group.transforms) {
+        let part = bake(shape.as_ref(), instances.ref_data())?;
+        let start = mesh.vertices.len() as u32;
+        mesh.vertices.extend(part.vertices);
+        mesh.indices
+            .extend(part.indices.iter().map(|&index| start + index));
+    }
+    Ok(mesh)
+}
+
+// Bakes as if the shape was drawn as `primitive`, for shapes whose lines trace out a surface
+pub fn bake_as<S: HasShape + ?Sized>(
+    shape: &S,
+    instances: &[Instance],
+    primitive: PrimitiveType,
+) -> Result<Mesh, ModelError> {
+    let vertices = shape.ref_vertices();
+    let (indices, restart) = match shape.ref_indices() {
+        Some(indices) => {
+            let data = indices.ref_data();
+            let restart = Some(data.restart_index()).filter(|_| indices.restart());
+            (data.to_u32(), restart)
+        }
+        None => ((0..vertices.len() as u32).collect(), None),
+    };
+    let triangles = triangles(primitive, &indices, restart)?;
+
+    let mut mesh = Mesh::default();
+    for instance in instances {
+        let transform = &instance.transform;
+        let matrix = transform.matrix();
+        let rotation = Mat3::from_quat(transform.rotation);
+        let normals = (rotation * Mat3::from_scale(transform.scale))
+            .normal_matrix()
+            .unwrap_or(rotation);
+        let start = mesh.vertices.len() as u32;
+        mesh.vertices.extend(vertices.iter().map(|vertex| {
+            let mut baked = *vertex;
+            baked.set_pos(matrix.transform_point(vertex.pos()));
+            let normal = normals * Vec3::from(vertex.normal);
+            baked.normal = normal.try_normalize().unwrap_or(normal).into();
+            baked
+        }));
+        mesh.indices
+            .extend(triangles.iter().flatten().map(|&index| start + index));
+    }
+    Ok(mesh)
+}
+
+// Splits indices drawn as `primitive` into separate triangles, keeping their winding
+fn triangles(
+    primitive: PrimitiveType,
+    indices: &[u32],
+    restart: Option<u32>,
+) -> Result<Vec<[u32; 3]>, ModelError> {
+    let mut triangles = vec![];
+    for run in indices.split(|&index| Some(index) == restart) {
+        match primitive {
+            PrimitiveType::TrianglesList => {
+                triangles.extend(run.chunks_exact(3).map(|t| [t[0], t[1], t[2]]));
+            }
+            // Every other triangle in a strip is wound backwards
+            PrimitiveType::TriangleStrip => {
+                triangles.extend(run.windows(3).enumerate().map(|(i, t)| match i % 2 {
+                    0 => [t[0], t[1], t[2]],
+                    _ => [t[1], t[0], t[2]],
+                }));
+            }
+            PrimitiveType::TriangleFan => {
+                triangles.extend(run.windows(2).skip(1).map(|t| [run[0], t[0], t[1]]));
+            }
+            _ => {
+                return Err(ModelError::Unsupported(format!(
+                    "{:?} has no triangles to bake",
+                    primitive
+                )))
+            }
+        }
+    }
+    // Strips repeat indices to join up, which leaves triangles with no area
+    triangles.retain(|&[a, b, c]| a != b && b != c && c != a);
+    Ok(triangles)
+}
+
+///
+/// Splits a polygon into triangles by clipping ears, which copes with concave ones.
+/// The polygon is flattened onto the plane it mostly lies in first. Ones that twist
+/// or cross themselves so there's no ear left fall back to a fan.
+///
+pub(crate) fn triangulate(points: &[Vec3]) -> Vec<[usize; 3]> {
+    if points.len() == 3 {
+        return vec![[0, 1, 2]];
+    }
+    // Newell's method, which works for concave polygons too
+    let mut normal = Vec3::ZERO;
+    for (i, &a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        normal += Vec3::new(
+            (a.y - b.y) * (a.z + b.z),
+            (a.z - b.z) * (a.x + b.x),
+            (a.x - b.x) * (a.y + b.y),
+        );
+    }
+    let (abs_x, abs_y, abs_z) = (normal.x.abs(), normal.y.abs(), normal.z.abs());
+    // Drops the axis the normal is closest to, keeping the winding the same way round
+    let flat: Vec<Vec2> = if abs_x >= abs_y && abs_x >= abs_z {
+        let sign = normal.x.signum();
+        points.iter().map(|p| Vec2::new(p.y, p.z * sign)).collect()
+    } else if abs_y >= abs_z {
+        let sign = normal.y.signum();
+        points.iter().map(|p| Vec2::new(p.z, p.x * sign)).collect()
+    } else {
+        let sign = normal.z.signum();
+        points.iter().map(|p| Vec2::new(p.x, p.y * sign)).collect()
+    };
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = vec![];
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let (a, b, c) = (
+                remaining[(i + count - 1) % count],
+                remaining[i],
+                remaining[(i + 1) % count],
+            );
+            let (pa, pb, pc) = (flat[a], flat[b], flat[c]);
+            cross(pa, pb, pc) > 0.0
+                && remaining.iter().all(|&other| {
+                    let p = flat[other];
+                    [pa, pb, pc].contains(&p)
+                        || cross(pa, pb, p) < 0.0
+                        || cross(pb, pc, p) < 0.0
+                        || cross(pc, pa, p) < 0.0
+                })
+        });
+        match ear {
+            Some(i) => {
+                let (a, c) = (
+                    remaining[(i + count - 1) % count],
+                    remaining[(i + 1) % count],
+                );
+                triangles.push([a, remaining[i], c]);
+                remaining.remove(i);
+            }
+            None => break,
+        }
+    }
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+    triangles
+}
+
+// Positive when a, b, c turn anticlockwise
+fn cross(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
@@ -0,0 +1,640 @@
+use drawable::mesh::Mesh;
+use drawable::shape::Shape;
+use math::{Vec2, Vec3};
+use model::{triangulate, ModelError};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::{FromStr, SplitWhitespace};
+use util::bufferable::Backend;
+use util::vertex::F32vec3;
+
+///
+/// A material from an MTL file, just the parts that are widely used.
+/// Colours are linear rgb from 0 to 1.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    // 1 is solid, 0 is invisible
+    pub opacity: f32,
+    // Relative to the working directory, resolved from the MTL file's directory
+    pub diffuse_map: Option<PathBuf>,
+}
+
+impl Material {
+    fn new(name: &str) -> Self {
+        Material {
+            name: name.to_string(),
+            ambient: [0.0; 3],
+            diffuse: [0.8; 3],
+            specular: [0.0; 3],
+            shininess: 0.0,
+            opacity: 1.0,
+            diffuse_map: None,
+        }
+    }
+}
+
+// The triangles of one object or group drawn with one material
+#[derive(Clone, Debug)]
+pub struct ObjMesh {
+    // Empty when the faces came before any 'o'
+    pub object: String,
+    pub groups: Vec<String>,
+    pub material: Option<String>,
+    pub mesh: Mesh,
+}
+
+impl ObjMesh {
+    // The object and groups it's in, separated by spaces
+    pub fn name(&self) -> String {
+        let object = Some(self.object.as_str()).filter(|o| !o.is_empty());
+        let groups = self.groups.iter().map(String::as_str);
+        object
+            .into_iter()
+            .chain(groups)
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+}
+
+///
+/// Everything in an OBJ file that can be drawn. There's a mesh for every run of faces
+/// with the same object, groups and material, in the order they're in the file.
+///
+#[derive(Clone, Debug, Default)]
+pub struct Obj {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<Material>,
+}
+
+impl Obj {
+    pub fn material(&self, mesh: &ObjMesh) -> Option<&Material> {
+        let name = mesh.material.as_ref()?;
+        self.materials.iter().find(|m| &m.name == name)
+    }
+
+    pub fn shapes(&self, display: &dyn Backend) -> Vec<Shape> {
+        self.meshes
+            .iter()
+            .map(|obj_mesh| Shape::from_mesh(&obj_mesh.mesh, display))
+            .collect()
+    }
+}
+
+// Reads an OBJ file along with the MTL files it names
+pub fn load(path: &Path) -> Result<Obj, ModelError> {
+    let text = fs::read_to_string(path).map_err(|e| ModelError::Io(path.to_path_buf(), e))?;
+    parse(&text, &path.display().to_string(), path.parent())
+}
+
+///
+/// Parses the text of an OBJ file called `file`, loading its MTL files from `dir`.
+/// Polygons are split into triangles, and faces without normals get them worked out,
+/// smoothed across faces in the same smoothing group and flat otherwise.
+///
+pub fn parse(text: &str, file: &str, dir: Option<&Path>) -> Result<Obj, ModelError> {
+    let mut builder = Builder::default();
+    let mut materials = vec![];
+    for (line, statement) in statements(text) {
+        let error = |message: String| ModelError::parse(file, line, message);
+        let mut words = statement.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        match keyword {
+            // Some exporters put a colour after the position, which is skipped
+            "v" => {
+                let [x, y, z] = numbers(&mut words, 3, 7, keyword).map_err(error)?;
+                builder.positions.push(Vec3::new(x, y, z));
+            }
+            "vt" => {
+                let [u, v, _] = numbers(&mut words, 1, 3, keyword).map_err(error)?;
+                builder.uvs.push(Vec2::new(u, v));
+            }
+            "vn" => {
+                let [x, y, z] = numbers(&mut words, 3, 3, keyword).map_err(error)?;
+                builder.normals.push(Vec3::new(x, y, z));
+            }
+            "f" => {
+                let corners = words
+                    .map(|corner| builder.corner(corner))
+                    .collect::<Result<Vec<Corner>, String>>()
+                    .map_err(error)?;
+                if corners.len() < 3 {
+                    return Err(error("a face needs at least 3 corners".to_string()));
+                }
+                builder.face(&corners);
+            }
+            "o" => {
+                builder.finish_part();
+                builder.part.object = words.collect::<Vec<&str>>().join(" ");
+                builder.part.groups.clear();
+            }
+            "g" => {
+                builder.finish_part();
+                builder.part.groups = words.map(|group| group.to_string()).collect();
+            }
+            "usemtl" => {
+                builder.finish_part();
+                let name = rest(&statement, keyword);
+                if name.is_empty() {
+                    return Err(error("'usemtl' needs a material name".to_string()));
+                }
+                builder.part.material = Some(name.to_string());
+            }
+            "s" => {
+                builder.smoothing = match words.next() {
+                    Some("off") => 0,
+                    Some(group) => group
+                        .parse()
+                        .map_err(|_| error(format!("'{}' isn't a smoothing group", group)))?,
+                    None => return Err(error("'s' needs a group number or 'off'".to_string())),
+                };
+            }
+            "mtllib" => {
+                // Names can have spaces in them, but most files list several without any
+                let names = rest(&statement, keyword);
+                for name in names.split_whitespace() {
+                    let path = dir.unwrap_or_else(|| Path::new(".")).join(name);
+                    let text = fs::read_to_string(&path)
+                        .map_err(|e| error(format!("couldn't read '{}': {}", name, e)))?;
+                    let mtl = path.display().to_string();
+                    materials.extend(parse_mtl(&text, &mtl, path.parent())?);
+                }
+            }
+            // Lines, points, curves and the rest don't make triangles
+            _ => (),
+        }
+    }
+    builder.finish_part();
+    Ok(Obj {
+        meshes: builder.meshes,
+        materials,
+    })
+}
+
+// Parses the text of an MTL file called `file`, with texture paths relative to `dir`
+pub fn parse_mtl(text: &str, file: &str, dir: Option<&Path>) -> Result<Vec<Material>, ModelError> {
+    let mut materials: Vec<Material> = vec![];
+    for (line, statement) in statements(text) {
+        let error = |message: String| ModelError::parse(file, line, message);
+        let mut words = statement.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            let name = rest(&statement, keyword);
+            if name.is_empty() {
+                return Err(error("'newmtl' needs a name".to_string()));
+            }
+            materials.push(Material::new(name));
+            continue;
+        }
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => return Err(error(format!("'{}' comes before any 'newmtl'", keyword))),
+        };
+        match keyword {
+            "Ka" | "Kd" | "Ks" => {
+                let [r, g, b] = numbers(&mut words, 1, 3, keyword).map_err(error)?;
+                // Just r means a grey
+                let colour = if statement.split_whitespace().count() == 2 {
+                    [r, r, r]
+                } else {
+                    [r, g, b]
+                };
+                match keyword {
+                    "Ka" => material.ambient = colour,
+                    "Kd" => material.diffuse = colour,
+                    _ => material.specular = colour,
+                }
+            }
+            "Ns" => material.shininess = numbers(&mut words, 1, 1, keyword).map_err(error)?[0],
+            "d" => material.opacity = numbers(&mut words, 1, 1, keyword).map_err(error)?[0],
+            "Tr" => material.opacity = 1.0 - numbers(&mut words, 1, 1, keyword).map_err(error)?[0],
+            // Options like -bm come first, the file name is last
+            "map_Kd" => match words.last() {
+                Some(name) => {
+                    let path = dir.unwrap_or_else(|| Path::new(".")).join(name);
+                    material.diffuse_map = Some(path);
+                }
+                None => return Err(error("'map_Kd' needs a file name".to_string())),
+            },
+            _ => (),
+        }
+    }
+    Ok(materials)
+}
+
+///
+/// Lines with the comments taken off and continuations joined up,
+/// each with the number of the line it starts on.
+///
+fn statements(text: &str) -> Vec<(usize, String)> {
+    let mut statements = vec![];
+    let mut pending: Option<(usize, String)> = None;
+    for (index, line) in text.lines().enumerate() {
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let (start, mut statement) = pending.take().unwrap_or((index + 1, String::new()));
+        match line.trim_end().strip_suffix('\\') {
+            Some(continued) => {
+                statement.push_str(continued);
+                statement.push(' ');
+                pending = Some((start, statement));
+            }
+            None => {
+                statement.push_str(line);
+                statements.push((start, statement));
+            }
+        }
+    }
+    statements.extend(pending);
+    statements
+}
+
+// Everything after the keyword, for names that can have spaces in them
+fn rest<'a>(statement: &'a str, keyword: &str) -> &'a str {
+    statement.trim_start()[keyword.len()..].trim()
+}
+
+// Between `min` and `max` numbers, the missing ones left at zero
+fn numbers(
+    words: &mut SplitWhitespace,
+    min: usize,
+    max: usize,
+    keyword: &str,
+) -> Result<[f32; 3], String> {
+    let mut values = [0.0; 3];
+    let mut count = 0;
+    for word in words {
+        if count == max {
+            return Err(format!("'{}' takes at most {} numbers", keyword, max));
+        }
+        if count < 3 {
+            values[count] = parse_number(word)?;
+        }
+        count += 1;
+    }
+    if count < min {
+        return Err(format!(
+            "'{}' needs at least {} numbers, found {}",
+            keyword, min, count
+        ));
+    }
+    Ok(values)
+}
+
+fn parse_number<T: FromStr>(word: &str) -> Result<T, String> {
+    word.parse()
+        .map_err(|_| format!("'{}' isn't a number", word))
+}
+
+// One corner of a face, as indices counted from 0
+#[derive(Copy, Clone)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+// A triangle of a part and the smoothing group it was in
+struct Triangle {
+    corners: [Corner; 3],
+    smoothing: u32,
+}
+
+// What the faces being read now belong to
+#[derive(Default)]
+struct Part {
+    object: String,
+    groups: Vec<String>,
+    material: Option<String>,
+    triangles: Vec<Triangle>,
+}
+
+// How a vertex gets its normal, which decides which corners can share it
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum NormalSource {
+    Given(usize),
+    // Averaged over the faces around the position in a smoothing group
+    Smoothed(u32),
+    // The normal of the one triangle with this number
+    Flat(usize),
+}
+
+#[derive(Default)]
+struct Builder {
+    positions: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    normals: Vec<Vec3>,
+    smoothing: u32,
+    part: Part,
+    meshes: Vec<ObjMesh>,
+}
+
+impl Builder {
+    // Parses `v`, `v/vt`, `v//vn` or `v/vt/vn`
+    fn corner(&self, corner: &str) -> Result<Corner, String> {
+        let mut indices = corner.split('/');
+        let position = indices.next().unwrap_or("");
+        let uv = indices.next().filter(|uv| !uv.is_empty());
+        let normal = indices.next().filter(|normal| !normal.is_empty());
+        if indices.next().is_some() {
+            return Err(format!("'{}' has too many parts for a face corner", corner));
+        }
+        Ok(Corner {
+            position: resolve(position, self.positions.len(), "vertex")?,
+            uv: uv
+                .map(|uv| resolve(uv, self.uvs.len(), "texture coordinate"))
+                .transpose()?,
+            normal: normal
+                .map(|normal| resolve(normal, self.normals.len(), "normal"))
+                .transpose()?,
+        })
+    }
+
+    fn face(&mut self, corners: &[Corner]) {
+        let points: Vec<Vec3> = corners.iter().map(|c| self.positions[c.position]).collect();
+        for [a, b, c] in triangulate(&points) {
+            self.part.triangles.push(Triangle {
+                corners: [corners[a], corners[b], corners[c]],
+                smoothing: self.smoothing,
+            });
+        }
+    }
+
+    // Turns the faces read so far into a mesh, if there are any
+    fn finish_part(&mut self) {
+        let triangles = std::mem::take(&mut self.part.triangles);
+        if triangles.is_empty() {
+            return;
+        }
+
+        let face_normals: Vec<Vec3> = triangles
+            .iter()
+            .map(|triangle| {
+                let [a, b, c] = triangle
+                    .corners
+                    .map(|corner| self.positions[corner.position]);
+                // Left unnormalised so bigger faces count for more when smoothing
+                (b - a).cross(c - a)
+            })
+            .collect();
+        let mut smoothed: HashMap<(usize, u32), Vec3> = HashMap::new();
+        for (triangle, &normal) in triangles.iter().zip(face_normals.iter()) {
+            for corner in triangle.corners.iter().filter(|c| c.normal.is_none()) {
+                if triangle.smoothing != 0 {
+                    *smoothed
+                        .entry((corner.position, triangle.smoothing))
+                        .or_default() += normal;
+                }
+            }
+        }
+
+        let mut mesh = Mesh {
+            uvs: triangles
+                .iter()
+                .all(|t| t.corners.iter().all(|c| c.uv.is_some())),
+            ..Mesh::default()
+        };
+        let mut shared: HashMap<(usize, Option<usize>, NormalSource), u32> = HashMap::new();
+        for (index, triangle) in triangles.iter().enumerate() {
+            let face_normal = face_normals[index].try_normalize().unwrap_or(Vec3::Y);
+            for corner in triangle.corners.iter() {
+                let source = match (corner.normal, triangle.smoothing) {
+                    (Some(normal), _) => NormalSource::Given(normal),
+                    (None, 0) => NormalSource::Flat(index),
+                    (None, group) => NormalSource::Smoothed(group),
+                };
+                let key = (corner.position, corner.uv, source);
+                let vertex = *shared.entry(key).or_insert_with(|| {
+                    let normal = match source {
+                        NormalSource::Given(normal) => self.normals[normal].try_normalize(),
+                        NormalSource::Smoothed(group) => {
+                            smoothed[&(corner.position, group)].try_normalize()
+                        }
+                        NormalSource::Flat(_) => None,
+                    };
+                    let position = self.positions[corner.position];
+                    let uv = corner.uv.map_or(Vec2::ZERO, |uv| self.uvs[uv]);
+                    let vertex = F32vec3::new(position, normal.unwrap_or(face_normal));
+                    mesh.vertices.push(vertex.with_uv(uv));
+                    mesh.vertices.len() as u32 - 1
+                });
+                mesh.indices.push(vertex);
+            }
+        }
+
+        self.meshes.push(ObjMesh {
+            object: self.part.object.clone(),
+            groups: self.part.groups.clone(),
+            material: self.part.material.clone(),
+            mesh,
+        });
+    }
+}
+
+// An index counted from 1, or back from the last one defined when negative
+fn resolve(index: &str, count: usize, what: &str) -> Result<usize, String> {
+    let number: i64 = index
+        .parse()
+        .map_err(|_| format!("'{}' isn't a {} index", index, what))?;
+    let resolved = match number {
+        0 => return Err(format!("{} indices start at 1, not 0", what)),
+        n if n > 0 => n - 1,
+        n => count as i64 + n,
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "{} {} is out of range, {} defined so far",
+            what, number, count
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn read(text: &str) -> Obj {
+        parse(text, "test.obj", None).unwrap()
+    }
+
+    fn positions(mesh: &Mesh) -> Vec<[Vec3; 3]> {
+        mesh.triangles()
+            .map(|triangle| triangle.map(|vertex| vertex.pos()))
+            .collect()
+    }
+
+    fn area([a, b, c]: [Vec3; 3]) -> Vec3 {
+        (b - a).cross(c - a) * 0.5
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_vertex() {
+        let obj = read(&format!("{}f -4 -3 -2\nv 5 5 5\nf 1 -2 -1\n", SQUARE));
+        let triangles = positions(&obj.meshes[0].mesh);
+        assert_eq!(
+            triangles[0],
+            [Vec3::ZERO, Vec3::X, Vec3::new(1.0, 1.0, 0.0)]
+        );
+        assert_eq!(
+            triangles[1],
+            [Vec3::ZERO, Vec3::Y, Vec3::new(5.0, 5.0, 5.0)]
+        );
+    }
+
+    #[test]
+    fn corners_can_have_uvs_and_normals() {
+        let text = format!(
+            "{}vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 2\nf 1/1/1 2/2/1 3/3/1\nf 1//1 3//1 4//1\n",
+            SQUARE
+        );
+        let mesh = &read(&text).meshes[0].mesh;
+        assert_eq!(mesh.triangle_count(), 2);
+        // The uvs only count when every corner has one
+        assert!(!mesh.uvs);
+        assert_eq!(mesh.vertices[2].uv, [1.0, 1.0]);
+        for vertex in &mesh.vertices {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn concave_polygons_are_split_into_ears() {
+        // An L, starting where a fan would fold back over itself
+        let text = "v 2 1 0\nv 1 1 0\nv 1 2 0\nv 0 2 0\nv 0 0 0\nv 2 0 0\nf 1 2 3 4 5 6\n";
+        let mesh = &read(text).meshes[0].mesh;
+        assert_eq!(mesh.triangle_count(), 4);
+        let mut total = 0.0;
+        for triangle in positions(mesh) {
+            let area = area(triangle);
+            assert!(area.z > 0.0, "{:?} is wound the wrong way", triangle);
+            total += area.z;
+        }
+        assert!((total - 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn polygons_without_ears_fall_back_to_a_fan() {
+        let points: Vec<Vec3> = (0..5).map(|i| Vec3::new(i as f32, 0.0, 0.0)).collect();
+        assert_eq!(triangulate(&points), vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+        // Through a face too, which still makes triangles even though they're flat
+        let text = "v 0 0 0\nv 1 0 0\nv 2 0 0\nv 3 0 0\nf 1 2 3 4\n";
+        assert_eq!(read(text).meshes[0].mesh.triangle_count(), 2);
+    }
+
+    #[test]
+    fn objects_groups_and_materials_split_the_meshes() {
+        let text = format!(
+            "{}f 1 2 3\no box\nf 1 2 3\ng lid top\nf 1 3 4\nusemtl red\nf 1 3 4\no ball\nf 1 2 3\n",
+            SQUARE
+        );
+        let obj = read(&text);
+        let names: Vec<String> = obj.meshes.iter().map(ObjMesh::name).collect();
+        assert_eq!(names, ["", "box", "box lid top", "box lid top", "ball"]);
+        let materials: Vec<Option<&str>> = obj
+            .meshes
+            .iter()
+            .map(|mesh| mesh.material.as_deref())
+            .collect();
+        assert_eq!(materials, [None, None, None, Some("red"), Some("red")]);
+        assert!(obj
+            .meshes
+            .iter()
+            .all(|mesh| mesh.mesh.triangle_count() == 1));
+    }
+
+    #[test]
+    fn smoothing_groups_share_normals_across_faces() {
+        // Two faces folded along the y axis
+        let fold = "v 0 0 0\nv 0 1 0\nv 1 0 1\nv -1 0 1\nf 1 3 2\nf 1 2 4\n";
+        let smooth = &read(&format!("s 1\n{}", fold)).meshes[0].mesh;
+        assert_eq!(smooth.vertices.len(), 4);
+        // The two corners on the fold are shared and get the average of both faces
+        for vertex in smooth.vertices.iter().filter(|vertex| vertex.x() == 0.0) {
+            let normal = Vec3::from(vertex.normal);
+            assert!(normal.approx_eq(Vec3::Z, 1e-6), "{:?}", normal);
+        }
+
+        let flat = &read(&format!("s off\n{}", fold)).meshes[0].mesh;
+        assert_eq!(flat.vertices.len(), 6);
+        for triangle in flat.triangles() {
+            let normal = area(triangle.map(|vertex| vertex.pos())).normalize();
+            for vertex in &triangle {
+                assert!(Vec3::from(vertex.normal).approx_eq(normal, 1e-6));
+            }
+        }
+    }
+
+    #[test]
+    fn mtl_colours_and_maps() {
+        let text = "newmtl red\nKd 1 0 0\nmap_Kd -bm 0.5 bricks.png\nnewmtl grey\nKd 0.5\nd 0.25\n";
+        let materials = parse_mtl(text, "test.mtl", Some(Path::new("models"))).unwrap();
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].diffuse, [1.0, 0.0, 0.0]);
+        assert_eq!(
+            materials[0].diffuse_map,
+            Some(Path::new("models").join("bricks.png"))
+        );
+        assert_eq!(materials[1].diffuse, [0.5, 0.5, 0.5]);
+        assert_eq!(materials[1].opacity, 0.25);
+        assert_eq!(materials[1].diffuse_map, None);
+
+        match parse_mtl("Kd 1 1 1\n", "test.mtl", None) {
+            Err(ModelError::Parse { line, .. }) => assert_eq!(line, 1),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn mtl_files_are_found_next_to_the_obj() {
+        let dir = env::temp_dir().join(format!("obj-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("paint.mtl"),
+            "newmtl blue\nKd 0 0 1\nmap_Kd sky.png\n",
+        )
+        .unwrap();
+        let text = format!("mtllib paint.mtl\n{}usemtl blue\nf 1 2 3\n", SQUARE);
+        let obj = parse(&text, "test.obj", Some(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let obj = obj.unwrap();
+        let material = obj.material(&obj.meshes[0]).unwrap();
+        assert_eq!(material.diffuse, [0.0, 0.0, 1.0]);
+        assert_eq!(material.diffuse_map, Some(dir.join("sky.png")));
+    }
+
+    #[test]
+    fn errors_say_which_line_they_are_on() {
+        let error = |text: &str| match parse(text, "test.obj", None) {
+            Err(ModelError::Parse { line, message, .. }) => (line, message),
+            other => panic!("{:?}", other.map(|obj| obj.meshes.len())),
+        };
+        let (line, message) = error(&format!("{}# a comment\nf 1 2 9\n", SQUARE));
+        assert_eq!(line, 6);
+        assert_eq!(message, "vertex 9 is out of range, 4 defined so far");
+        // A statement carried on over several lines is reported on the first
+        let (line, _) = error("v 0 0 0\nf 1 \\\n 1 \\\n 0\n");
+        assert_eq!(line, 2);
+        assert_eq!(error("v 0 0\n").0, 1);
+        assert_eq!(error("v 0 0 0\nv 0 0 0\nv 0 0 0\nf 1 -4 2\n").0, 4);
+        assert_eq!(error("v 0 0 0\nf 1 1\n").0, 2);
+    }
+}
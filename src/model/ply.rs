@@ -0,0 +1,664 @@
+use drawable::mesh::Mesh;
+use math::{Vec2, Vec3};
+use model::{triangulate, ModelError};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str;
+use util::vertex::F32vec3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+// The number types a property can have, under their old and new names
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // `bytes` is exactly `size` long
+    fn read_le(self, bytes: &[u8]) -> f64 {
+        let mut b = [0u8; 8];
+        b[..bytes.len()].copy_from_slice(bytes);
+        match self {
+            Scalar::I8 => b[0] as i8 as f64,
+            Scalar::U8 => b[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(b),
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    // A count followed by that many items
+    List(String, Scalar, Scalar),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn property(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|property| names.contains(&property.name()))
+    }
+}
+
+// What one property of one element read as, lists are only used for faces
+enum Value {
+    Scalar(f64),
+    List(Vec<f64>),
+}
+
+impl Value {
+    fn scalar(&self) -> f64 {
+        match self {
+            Value::Scalar(value) => *value,
+            Value::List(values) => values.first().copied().unwrap_or(0.0),
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Result<Mesh, ModelError> {
+    let bytes = fs::read(path).map_err(|e| ModelError::Io(path.to_path_buf(), e))?;
+    parse(&bytes, &path.display().to_string())
+}
+
+///
+/// Parses ASCII or little endian binary PLY. Only the vertex and face elements are used:
+/// positions, normals if there are all three, and uvs under any of their usual names.
+/// Faces with more than three corners are split up, and normals are worked out
+/// from the faces when the file doesn't have any.
+///
+pub fn parse(bytes: &[u8], file: &str) -> Result<Mesh, ModelError> {
+    let Header {
+        encoding,
+        elements,
+        mut line,
+        body,
+    } = parse_header(bytes, file)?;
+    let mut body = Body {
+        bytes: body,
+        offset: 0,
+        lines: match encoding {
+            Encoding::Ascii => Some(str::from_utf8(body).map_err(|_| {
+                ModelError::invalid(
+                    file,
+                    "ASCII PLY with bytes that aren't text after the header",
+                )
+            })?),
+            Encoding::BinaryLittleEndian => None,
+        },
+    };
+
+    let mut vertices: Vec<F32vec3> = vec![];
+    let mut has_normals = false;
+    let mut has_uvs = false;
+    // Each face's corners and the line it was on, checked once every vertex is in
+    let mut faces: Vec<(Option<usize>, Vec<f64>)> = vec![];
+    for element in &elements {
+        let position = ["x", "y", "z"].map(|name| element.property(&[name]));
+        let normal = ["nx", "ny", "nz"].map(|name| element.property(&[name]));
+        let uv = [
+            element.property(&["u", "s", "texture_u", "texture_s"]),
+            element.property(&["v", "t", "texture_v", "texture_t"]),
+        ];
+        let corners = element.property(&["vertex_indices", "vertex_index"]);
+        if element.name == "vertex" && position.contains(&None) {
+            return Err(ModelError::invalid(
+                file,
+                "vertices need x, y and z properties",
+            ));
+        }
+        if element.name == "face" && corners.is_none() {
+            return Err(ModelError::invalid(
+                file,
+                "faces need a vertex_indices list",
+            ));
+        }
+
+        for index in 0..element.count {
+            let at = body.line(&mut line);
+            let values = body.element(element, index, file, at)?;
+            let get = |property: Option<usize>| property.map(|p| values[p].scalar() as f32);
+            match element.name.as_str() {
+                "vertex" => {
+                    let [x, y, z] = position.map(|p| get(p).unwrap_or(0.0));
+                    let mut vertex = F32vec3::from([x, y, z]);
+                    if let [Some(nx), Some(ny), Some(nz)] = normal.map(get) {
+                        vertex.normal = [nx, ny, nz];
+                        has_normals = true;
+                    }
+                    if let [Some(u), Some(v)] = uv.map(get) {
+                        vertex = vertex.with_uv(Vec2::new(u, v));
+                        has_uvs = true;
+                    }
+                    vertices.push(vertex);
+                }
+                "face" => match corners.map(|c| &values[c]) {
+                    Some(Value::List(corners)) => faces.push((at, corners.clone())),
+                    _ => return Err(ModelError::invalid(file, "vertex_indices isn't a list")),
+                },
+                _ => (),
+            }
+        }
+    }
+    if let Some(at) = body.leftover(line) {
+        return Err(located(file, at, "more data than the header says there is"));
+    }
+
+    let mut mesh = Mesh {
+        vertices,
+        indices: vec![],
+        uvs: has_uvs,
+    };
+    for (face, (at, corners)) in faces.iter().enumerate() {
+        let mut indices = vec![];
+        for &corner in corners {
+            if corner < 0.0 || corner >= mesh.vertices.len() as f64 || corner.fract() != 0.0 {
+                let message = format!(
+                    "face {} has a corner {}, there are {} vertices",
+                    face,
+                    corner,
+                    mesh.vertices.len()
+                );
+                return Err(located(file, *at, message));
+            }
+            indices.push(corner as u32);
+        }
+        if indices.len() < 3 {
+            continue;
+        }
+        let points: Vec<Vec3> = indices
+            .iter()
+            .map(|&i| mesh.vertices[i as usize].pos())
+            .collect();
+        for triangle in triangulate(&points) {
+            mesh.indices
+                .extend(triangle.iter().map(|&corner| indices[corner]));
+        }
+    }
+    if has_normals {
+        for vertex in mesh.vertices.iter_mut() {
+            let normal = Vec3::from(vertex.normal);
+            vertex.normal = normal.try_normalize().unwrap_or(Vec3::Y).into();
+        }
+    } else {
+        mesh.smooth_normals();
+    }
+    Ok(mesh)
+}
+
+struct Header<'a> {
+    encoding: Encoding,
+    elements: Vec<Element>,
+    // How many lines the header took
+    line: usize,
+    // Everything after it
+    body: &'a [u8],
+}
+
+fn parse_header<'a>(bytes: &'a [u8], file: &str) -> Result<Header<'a>, ModelError> {
+    let mut encoding = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut offset = 0;
+    let mut line = 0;
+    loop {
+        let end = match bytes[offset..].iter().position(|&b| b == b'\n') {
+            Some(end) => offset + end,
+            None => {
+                return Err(ModelError::invalid(
+                    file,
+                    "the header never gets to 'end_header'",
+                ))
+            }
+        };
+        let text = str::from_utf8(&bytes[offset..end])
+            .map_err(|_| ModelError::invalid(file, "the header isn't text"))?;
+        offset = end + 1;
+        line += 1;
+        let error = |message: String| ModelError::parse(file, line, message);
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if line == 1 {
+            if words != ["ply"] {
+                return Err(error("PLY files start with 'ply'".to_string()));
+            }
+            continue;
+        }
+        match words.as_slice() {
+            ["format", "ascii", _] => encoding = Some(Encoding::Ascii),
+            ["format", "binary_little_endian", _] => encoding = Some(Encoding::BinaryLittleEndian),
+            ["format", "binary_big_endian", _] => {
+                return Err(ModelError::Unsupported(format!(
+                    "{}: big endian PLY isn't supported",
+                    file
+                )))
+            }
+            ["format", ..] => return Err(error(format!("unknown format '{}'", text.trim()))),
+            ["element", name, count] => {
+                let count = count
+                    .parse()
+                    .map_err(|_| error(format!("'{}' isn't a count", count)))?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: vec![],
+                });
+            }
+            ["property", ..] => {
+                let scalar = |name: &str| {
+                    Scalar::from_name(name).ok_or_else(|| error(format!("unknown type '{}'", name)))
+                };
+                let property = match &words[1..] {
+                    ["list", count, item, name] => {
+                        Property::List(name.to_string(), scalar(count)?, scalar(item)?)
+                    }
+                    [ty, name] => Property::Scalar(name.to_string(), scalar(ty)?),
+                    _ => return Err(error(format!("can't read '{}'", text.trim()))),
+                };
+                match elements.last_mut() {
+                    Some(element) => element.properties.push(property),
+                    None => return Err(error("a property before any element".to_string())),
+                }
+            }
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] | [] => (),
+            _ => return Err(error(format!("can't read '{}'", text.trim()))),
+        }
+    }
+    let encoding = encoding
+        .ok_or_else(|| ModelError::invalid(file, "the header doesn't say what format it's in"))?;
+    Ok(Header {
+        encoding,
+        elements,
+        line,
+        body: &bytes[offset..],
+    })
+}
+
+// Errors in ASCII files say which line they're on
+fn located(file: &str, line: Option<usize>, message: impl ToString) -> ModelError {
+    match line {
+        Some(line) => ModelError::parse(file, line, message),
+        None => ModelError::invalid(file, message),
+    }
+}
+
+// The data after the header, read as text a line at a time or as bytes
+struct Body<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    lines: Option<&'a str>,
+}
+
+impl<'a> Body<'a> {
+    // Moves on to the next line with something on it in ASCII files, whose number is returned
+    fn line(&mut self, line: &mut usize) -> Option<usize> {
+        let text = self.lines.as_mut()?;
+        loop {
+            let (first, rest) = text.split_once('\n').unwrap_or((text, ""));
+            if first.trim().is_empty() && !rest.is_empty() {
+                *text = rest;
+                *line += 1;
+                continue;
+            }
+            *line += 1;
+            return Some(*line);
+        }
+    }
+
+    fn element(
+        &mut self,
+        element: &Element,
+        index: usize,
+        file: &str,
+        at: Option<usize>,
+    ) -> Result<Vec<Value>, ModelError> {
+        match (self.lines.as_mut(), at) {
+            (Some(text), Some(line)) => {
+                let (first, rest) = text.split_once('\n').unwrap_or((text, ""));
+                *text = rest;
+                read_text(first, element).map_err(|message| located(file, Some(line), message))
+            }
+            _ => {
+                let (values, read) =
+                    read_binary(&self.bytes[self.offset..], element).ok_or_else(|| {
+                        let message = format!("ends partway through {} {}", element.name, index);
+                        ModelError::invalid(file, message)
+                    })?;
+                self.offset += read;
+                Ok(values)
+            }
+        }
+    }
+
+    // Where anything left over after the last element is, if there is anything
+    fn leftover(&self, line: usize) -> Option<Option<usize>> {
+        match self.lines {
+            Some(text) => text
+                .lines()
+                .position(|rest| !rest.trim().is_empty())
+                .map(|blanks| Some(line + 1 + blanks)),
+            None if self.offset < self.bytes.len() => Some(None),
+            None => None,
+        }
+    }
+}
+
+fn read_text(line: &str, element: &Element) -> Result<Vec<Value>, String> {
+    let mut words = line.split_whitespace();
+    let mut number = || -> Result<f64, String> {
+        let word = words
+            .next()
+            .ok_or_else(|| format!("not enough values for a {}", element.name))?;
+        word.parse()
+            .map_err(|_| format!("'{}' isn't a number", word))
+    };
+    let mut values = vec![];
+    for property in &element.properties {
+        values.push(match property {
+            Property::Scalar(..) => Value::Scalar(number()?),
+            Property::List(..) => {
+                let count = number()?;
+                if count < 0.0 || count.fract() != 0.0 {
+                    return Err(format!("'{}' isn't a list length", count));
+                }
+                Value::List(
+                    (0..count as usize)
+                        .map(|_| number())
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+        });
+    }
+    match words.next() {
+        Some(word) => Err(format!("'{}' is more than a {} has", word, element.name)),
+        None => Ok(values),
+    }
+}
+
+// The values and how many bytes they took, None if the bytes run out
+fn read_binary(bytes: &[u8], element: &Element) -> Option<(Vec<Value>, usize)> {
+    let mut offset = 0;
+    let mut scalar = |ty: Scalar| {
+        let value = ty.read_le(bytes.get(offset..offset + ty.size())?);
+        offset += ty.size();
+        Some(value)
+    };
+    let mut values = vec![];
+    for property in &element.properties {
+        values.push(match *property {
+            Property::Scalar(_, ty) => Value::Scalar(scalar(ty)?),
+            Property::List(_, count, item) => {
+                let count = scalar(count)?.max(0.0) as usize;
+                Value::List((0..count).map(|_| scalar(item)).collect::<Option<_>>()?)
+            }
+        });
+    }
+    Some((values, offset))
+}
+
+///
+/// Writes positions, normals and uvs if the mesh has any, with the faces as triangles.
+/// Floats are written at full precision, so reading the file back gives the same mesh.
+///
+pub fn write(out: &mut dyn Write, mesh: &Mesh, encoding: Encoding) -> io::Result<()> {
+    let format = match encoding {
+        Encoding::Ascii => "ascii",
+        Encoding::BinaryLittleEndian => "binary_little_endian",
+    };
+    writeln!(out, "ply")?;
+    writeln!(out, "format {} 1.0", format)?;
+    writeln!(out, "element vertex {}", mesh.vertices.len())?;
+    let mut properties = vec!["x", "y", "z", "nx", "ny", "nz"];
+    if mesh.uvs {
+        properties.extend(["u", "v"]);
+    }
+    for property in &properties {
+        writeln!(out, "property float {}", property)?;
+    }
+    writeln!(out, "element face {}", mesh.triangle_count())?;
+    writeln!(out, "property list uchar uint vertex_indices")?;
+    writeln!(out, "end_header")?;
+
+    for vertex in &mesh.vertices {
+        let mut values = [vertex.position, vertex.normal].concat();
+        if mesh.uvs {
+            values.extend(vertex.uv);
+        }
+        match encoding {
+            Encoding::Ascii => {
+                let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                writeln!(out, "{}", values.join(" "))?;
+            }
+            Encoding::BinaryLittleEndian => {
+                for value in values {
+                    out.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+    }
+    for triangle in mesh.indices.chunks_exact(3) {
+        match encoding {
+            Encoding::Ascii => writeln!(out, "3 {} {} {}", triangle[0], triangle[1], triangle[2])?,
+            Encoding::BinaryLittleEndian => {
+                out.write_all(&[3])?;
+                for index in triangle {
+                    out.write_all(&index.to_le_bytes())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(mesh: &Mesh, encoding: Encoding) -> Vec<u8> {
+        let mut bytes = vec![];
+        write(&mut bytes, mesh, encoding).unwrap();
+        bytes
+    }
+
+    fn check_round_trip(mesh: &Mesh, encoding: Encoding) {
+        let read = parse(&written(mesh, encoding), "mesh.ply").unwrap();
+        assert_eq!(read.indices, mesh.indices);
+        assert_eq!(read.uvs, mesh.uvs);
+        assert_eq!(read.vertices.len(), mesh.vertices.len());
+        for (before, after) in mesh.vertices.iter().zip(&read.vertices) {
+            assert_eq!(after.position, before.position);
+            assert_eq!(after.uv, before.uv);
+            let normal = Vec3::from(before.normal);
+            assert!(Vec3::from(after.normal).approx_eq(normal, 1e-6));
+        }
+    }
+
+    // A cube without its uvs
+    fn plain_cube() -> Mesh {
+        let mut mesh = Mesh::cube(1.0, 2);
+        mesh.uvs = false;
+        for vertex in mesh.vertices.iter_mut() {
+            vertex.uv = [0.0, 0.0];
+        }
+        mesh
+    }
+
+    fn error_of(text: &str) -> ModelError {
+        match parse(text.as_bytes(), "mesh.ply") {
+            Ok(mesh) => panic!("read {} triangles", mesh.triangle_count()),
+            Err(error) => error,
+        }
+    }
+
+    const SQUARE: &str = "ply\n\
+                          format ascii 1.0\n\
+                          comment a unit square\n\
+                          element vertex 4\n\
+                          property float x\n\
+                          property float y\n\
+                          property float z\n\
+                          element face 1\n\
+                          property list uchar int vertex_indices\n\
+                          end_header\n\
+                          0 0 0\n\
+                          1 0 0\n\
+                          1 1 0\n\
+                          0 1 0\n\
+                          4 0 1 2 3\n";
+
+    #[test]
+    fn ascii_round_trip() {
+        check_round_trip(&Mesh::uv_sphere(1.0, 8, 6), Encoding::Ascii);
+        check_round_trip(&plain_cube(), Encoding::Ascii);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        check_round_trip(&Mesh::uv_sphere(1.0, 8, 6), Encoding::BinaryLittleEndian);
+        check_round_trip(&plain_cube(), Encoding::BinaryLittleEndian);
+    }
+
+    #[test]
+    fn faces_are_split_and_normals_worked_out() {
+        let mesh = parse(SQUARE.as_bytes(), "square.ply").unwrap();
+        assert_eq!(mesh.triangle_count(), 2);
+        assert!(!mesh.uvs);
+        for vertex in &mesh.vertices {
+            assert!(Vec3::from(vertex.normal).approx_eq(Vec3::Z, 1e-6));
+        }
+    }
+
+    #[test]
+    fn cut_short_binary_files_are_rejected() {
+        let bytes = written(&plain_cube(), Encoding::BinaryLittleEndian);
+        let header = bytes
+            .windows(11)
+            .position(|w| w == b"end_header\n")
+            .unwrap()
+            + 11;
+        // Halfway through the third vertex, then halfway through the last face
+        for (cut, element) in [
+            (header + 24 * 2 + 12, "vertex 2"),
+            (bytes.len() - 2, "face"),
+        ]
+        .iter()
+        {
+            match parse(&bytes[..*cut], "cube.ply") {
+                Err(ModelError::Invalid { message, .. }) => {
+                    assert!(message.contains("ends partway through"), "{}", message);
+                    assert!(message.contains(element), "{}", message);
+                }
+                other => panic!("{:?}", other.map(|mesh| mesh.triangle_count())),
+            }
+        }
+    }
+
+    #[test]
+    fn data_after_the_last_element_is_rejected() {
+        let mut bytes = written(&plain_cube(), Encoding::BinaryLittleEndian);
+        bytes.push(0);
+        assert!(matches!(
+            parse(&bytes, "cube.ply"),
+            Err(ModelError::Invalid { .. })
+        ));
+
+        match error_of(&format!("{}\n3 0 1 2\n", SQUARE)) {
+            ModelError::Parse { line, message, .. } => {
+                assert_eq!(line, 17);
+                assert!(message.contains("more data"), "{}", message);
+            }
+            error => panic!("{}", error),
+        }
+        match error_of(&SQUARE.replace("1 1 0", "1 1 0 1")) {
+            ModelError::Parse { line, .. } => assert_eq!(line, 13),
+            error => panic!("{}", error),
+        }
+    }
+
+    #[test]
+    fn corners_have_to_be_vertices() {
+        for corner in &["4", "-1", "1.5"] {
+            let text = SQUARE.replace("4 0 1 2 3", &format!("4 0 1 2 {}", corner));
+            match error_of(&text) {
+                ModelError::Parse { line, message, .. } => {
+                    assert_eq!(line, 15);
+                    assert!(message.contains("there are 4 vertices"), "{}", message);
+                }
+                error => panic!("{}", error),
+            }
+        }
+    }
+
+    #[test]
+    fn headers_are_checked() {
+        assert!(matches!(
+            error_of(&SQUARE.replace("ascii", "binary_big_endian")),
+            ModelError::Unsupported(_)
+        ));
+        match error_of(&SQUARE.replace("property float y", "property fixed y")) {
+            ModelError::Parse { line, .. } => assert_eq!(line, 6),
+            error => panic!("{}", error),
+        }
+        assert!(matches!(
+            error_of(&SQUARE.replace("property float z\n", "")),
+            ModelError::Invalid { .. }
+        ));
+        assert!(matches!(
+            error_of("ply\nformat ascii 1.0\nelement vertex 0\n"),
+            ModelError::Invalid { .. }
+        ));
+    }
+}
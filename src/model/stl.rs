@@ -0,0 +1,310 @@
+use drawable::mesh::Mesh;
+use math::Vec3;
+use model::ModelError;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str;
+use util::vertex::F32vec3;
+
+// Bytes before the triangle count in a binary file, which mean nothing
+const HEADER: usize = 80;
+// Normal, three corners and a two byte attribute nobody agrees on
+const TRIANGLE: usize = 50;
+
+pub fn load(path: &Path) -> Result<Mesh, ModelError> {
+    let bytes = fs::read(path).map_err(|e| ModelError::Io(path.to_path_buf(), e))?;
+    parse(&bytes, &path.display().to_string())
+}
+
+///
+/// Parses ASCII or binary STL. Binary files can start with "solid" too,
+/// so they're told apart by whether the size fits the triangle count.
+/// STL doesn't share corners, every triangle gets three vertices of its own.
+///
+pub fn parse(bytes: &[u8], file: &str) -> Result<Mesh, ModelError> {
+    if let Some(count) = binary_count(bytes) {
+        if bytes.len() == HEADER + 4 + count * TRIANGLE {
+            return Ok(parse_binary(&bytes[HEADER + 4..], count));
+        }
+    }
+    let text = str::from_utf8(bytes)
+        .ok()
+        .filter(|text| text.trim_start().starts_with("solid"));
+    match (text, binary_count(bytes)) {
+        (Some(text), _) => parse_ascii(text, file),
+        (None, Some(count)) => Err(ModelError::invalid(
+            file,
+            format!(
+                "binary STL with {} triangles should be {} bytes, not {}",
+                count,
+                HEADER + 4 + count * TRIANGLE,
+                bytes.len()
+            ),
+        )),
+        (None, None) => Err(ModelError::invalid(file, "too short to be an STL file")),
+    }
+}
+
+fn binary_count(bytes: &[u8]) -> Option<usize> {
+    let count = bytes.get(HEADER..HEADER + 4)?;
+    Some(u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize)
+}
+
+fn parse_binary(bytes: &[u8], count: usize) -> Mesh {
+    let mut mesh = Mesh::default();
+    for triangle in bytes.chunks_exact(TRIANGLE).take(count) {
+        let vector = |at: usize| {
+            let value = |i: usize| {
+                let b = &triangle[at + i * 4..at + i * 4 + 4];
+                f32::from_le_bytes([b[0], b[1], b[2], b[3]])
+            };
+            Vec3::new(value(0), value(1), value(2))
+        };
+        push_triangle(&mut mesh, vector(0), [vector(12), vector(24), vector(36)]);
+    }
+    mesh
+}
+
+fn parse_ascii(text: &str, file: &str) -> Result<Mesh, ModelError> {
+    let mut mesh = Mesh::default();
+    // The normal and corners of the facet being read
+    let mut facet: Option<(Vec3, Vec<Vec3>)> = None;
+    let mut last = 0;
+    for (index, line) in text.lines().enumerate() {
+        last = index + 1;
+        let error = |message: String| ModelError::parse(file, index + 1, message);
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        match (keyword, facet.as_mut()) {
+            ("solid", None) | ("endsolid", None) | ("outer", Some(_)) | ("endloop", Some(_)) => (),
+            ("facet", None) => {
+                if words.next() != Some("normal") {
+                    return Err(error("expected 'facet normal'".to_string()));
+                }
+                facet = Some((vector(words).map_err(error)?, vec![]));
+            }
+            ("vertex", Some((_, corners))) => corners.push(vector(words).map_err(error)?),
+            ("endfacet", Some(_)) => {
+                let (normal, corners) = facet.take().unwrap_or_default();
+                if corners.len() != 3 {
+                    let message =
+                        format!("a facet needs 3 vertices, this one has {}", corners.len());
+                    return Err(error(message));
+                }
+                push_triangle(&mut mesh, normal, [corners[0], corners[1], corners[2]]);
+            }
+            (_, Some(_)) => return Err(error(format!("'{}' inside a facet", keyword))),
+            (_, None) => return Err(error(format!("'{}' outside a facet", keyword))),
+        }
+    }
+    if facet.is_some() {
+        return Err(ModelError::parse(
+            file,
+            last,
+            "the file ends inside a facet",
+        ));
+    }
+    Ok(mesh)
+}
+
+fn vector(mut words: str::SplitWhitespace) -> Result<Vec3, String> {
+    let mut value = || -> Result<f32, String> {
+        let word = words.next().ok_or("expected 3 numbers")?;
+        word.parse()
+            .map_err(|_| format!("'{}' isn't a number", word))
+    };
+    let vector = Vec3::new(value()?, value()?, value()?);
+    match words.next() {
+        Some(word) => Err(format!("expected 3 numbers, found '{}' after them", word)),
+        None => Ok(vector),
+    }
+}
+
+// Plenty of files leave the normal at zero, so it's worked out from the corners then
+fn push_triangle(mesh: &mut Mesh, normal: Vec3, corners: [Vec3; 3]) {
+    let normal = normal
+        .try_normalize()
+        .or_else(|| face_normal(corners).try_normalize())
+        .unwrap_or(Vec3::Y);
+    for corner in corners {
+        mesh.indices.push(mesh.vertices.len() as u32);
+        mesh.vertices.push(F32vec3::new(corner, normal));
+    }
+}
+
+fn face_normal([a, b, c]: [Vec3; 3]) -> Vec3 {
+    (b - a).cross(c - a)
+}
+
+fn facet_normals(mesh: &Mesh) -> impl Iterator<Item = (Vec3, [Vec3; 3])> + '_ {
+    mesh.triangles().map(|triangle| {
+        let corners = triangle.map(|vertex| vertex.pos());
+        let normal = face_normal(corners).try_normalize().unwrap_or(Vec3::ZERO);
+        (normal, corners)
+    })
+}
+
+// Binary STL, with each facet's normal worked out from its corners
+pub fn write_binary(out: &mut dyn Write, mesh: &Mesh) -> io::Result<()> {
+    // Anything but "solid" at the start, so it isn't mistaken for ASCII
+    let mut header = [0u8; HEADER];
+    let title = b"binary STL";
+    header[..title.len()].copy_from_slice(title);
+    out.write_all(&header)?;
+    out.write_all(&(mesh.triangle_count() as u32).to_le_bytes())?;
+    for (normal, corners) in facet_normals(mesh) {
+        for vector in [normal, corners[0], corners[1], corners[2]] {
+            for value in [vector.x, vector.y, vector.z] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+        out.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+pub fn write_ascii(out: &mut dyn Write, mesh: &Mesh, name: &str) -> io::Result<()> {
+    writeln!(out, "solid {}", name)?;
+    for (normal, corners) in facet_normals(mesh) {
+        writeln!(
+            out,
+            "  facet normal {:e} {:e} {:e}",
+            normal.x, normal.y, normal.z
+        )?;
+        writeln!(out, "    outer loop")?;
+        for corner in corners {
+            writeln!(
+                out,
+                "      vertex {:e} {:e} {:e}",
+                corner.x, corner.y, corner.z
+            )?;
+        }
+        writeln!(out, "    endloop")?;
+        writeln!(out, "  endfacet")?;
+    }
+    writeln!(out, "endsolid {}", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(mesh: &Mesh, ascii: bool) -> Vec<u8> {
+        let mut bytes = vec![];
+        match ascii {
+            true => write_ascii(&mut bytes, mesh, "ball").unwrap(),
+            false => write_binary(&mut bytes, mesh).unwrap(),
+        }
+        bytes
+    }
+
+    // Every triangle comes back with the same corners, and its normal is the face's
+    fn check_round_trip(ascii: bool) {
+        let mesh = Mesh::icosphere(1.5, 1);
+        let read = parse(&written(&mesh, ascii), "ball.stl").unwrap();
+        assert_eq!(read.triangle_count(), mesh.triangle_count());
+        assert_eq!(read.vertices.len(), 3 * mesh.triangle_count());
+        for (before, after) in mesh.triangles().zip(read.triangles()) {
+            let corners = before.map(|vertex| vertex.pos());
+            assert_eq!(after.map(|vertex| vertex.pos()), corners);
+            let normal = face_normal(corners).normalize();
+            for vertex in &after {
+                assert!(Vec3::from(vertex.normal).approx_eq(normal, 1e-5));
+            }
+        }
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        check_round_trip(true);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        check_round_trip(false);
+    }
+
+    #[test]
+    fn binary_files_that_start_with_solid_are_still_binary() {
+        let mesh = Mesh::icosphere(1.0, 0);
+        let mut bytes = written(&mesh, false);
+        bytes[..11].copy_from_slice(b"solid ball\n");
+        assert_eq!(parse(&bytes, "ball.stl").unwrap().triangle_count(), 20);
+    }
+
+    #[test]
+    fn ascii_files_are_told_apart_by_their_size() {
+        // Long enough to have a "triangle count", which doesn't fit the size
+        let text = String::from_utf8(written(&Mesh::icosphere(1.0, 0), true)).unwrap();
+        assert!(binary_count(text.as_bytes()).is_some());
+        assert_eq!(
+            parse(text.as_bytes(), "ball.stl").unwrap().triangle_count(),
+            20
+        );
+    }
+
+    #[test]
+    fn zero_normals_are_worked_out_from_the_corners() {
+        let text = "solid\n\
+                    facet normal 0 0 0\n\
+                    outer loop\n\
+                    vertex 0 0 0\n\
+                    vertex 1 0 0\n\
+                    vertex 0 1 0\n\
+                    endloop\n\
+                    endfacet\n\
+                    endsolid\n";
+        let mesh = parse(text.as_bytes(), "flat.stl").unwrap();
+        for vertex in &mesh.vertices {
+            assert_eq!(Vec3::from(vertex.normal), Vec3::Z);
+        }
+    }
+
+    #[test]
+    fn cut_short_binary_files_are_rejected() {
+        let bytes = written(&Mesh::icosphere(1.0, 0), false);
+        let cut = &bytes[..bytes.len() - 10];
+        match parse(cut, "ball.stl") {
+            Err(ModelError::Invalid { message, .. }) => {
+                assert!(message.contains("20 triangles"), "{}", message)
+            }
+            other => panic!("{:?}", other.map(|mesh| mesh.triangle_count())),
+        }
+        assert!(parse(&bytes[..40], "ball.stl").is_err());
+    }
+
+    #[test]
+    fn data_after_the_last_triangle_is_rejected() {
+        let mut bytes = written(&Mesh::icosphere(1.0, 0), false);
+        bytes.extend_from_slice(&[0; TRIANGLE]);
+        assert!(matches!(
+            parse(&bytes, "ball.stl"),
+            Err(ModelError::Invalid { .. })
+        ));
+
+        let mut text = written(&Mesh::icosphere(1.0, 0), true);
+        text.extend_from_slice(b"vertex 0 0 0\n");
+        match parse(&text, "ball.stl") {
+            Err(ModelError::Parse { line, message, .. }) => {
+                assert_eq!(line, 2 + 20 * 7 + 1);
+                assert!(message.contains("outside a facet"), "{}", message);
+            }
+            other => panic!("{:?}", other.map(|mesh| mesh.triangle_count())),
+        }
+    }
+
+    #[test]
+    fn facets_need_three_corners() {
+        let text = "solid\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nendloop\nendfacet\n";
+        match parse(text.as_bytes(), "two.stl") {
+            Err(ModelError::Parse { line, .. }) => assert_eq!(line, 7),
+            other => panic!("{:?}", other.map(|mesh| mesh.triangle_count())),
+        }
+        let text = "solid\nfacet normal 0 0 1\nouter loop\n";
+        assert!(parse(text.as_bytes(), "open.stl").is_err());
+    }
+}
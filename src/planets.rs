@@ -0,0 +1,142 @@
+use camera::orbit::OrbitCamera;
+use camera::Camera;
+use drawable::instance_group::InstanceGroup;
+use drawable::scene_graph::{NodeId, SceneGraph};
+use drawable::shape::Shape;
+use drawable::{DrawUniforms, Drawable};
+use glium::{DrawParameters, Frame};
+use math::{Transform, Vec3};
+use runnable::scene::Scene;
+use shader::ProgramLibrary;
+use std::io::{self, Write};
+use util::attribute::Instance;
+use util::bufferable::Backend;
+use util::Manipulate;
+
+// Distance from the parent, radius, radians turned around the parent each tick, colour
+struct Body(f32, f32, f32, [f32; 4]);
+
+const SUN: Body = Body(0.0, 0.12, 0.0, [1.0, 0.85, 0.2, 1.0]);
+const PLANETS: [(Body, &[Body]); 3] = [
+    (Body(0.3, 0.03, 0.02, [0.7, 0.7, 0.7, 1.0]), &[]),
+    (
+        Body(0.55, 0.05, 0.01, [0.2, 0.5, 1.0, 1.0]),
+        &[Body(0.1, 0.015, 0.05, [0.9, 0.9, 0.9, 1.0])],
+    ),
+    (
+        Body(0.85, 0.06, 0.006, [1.0, 0.4, 0.2, 1.0]),
+        &[
+            Body(0.1, 0.012, 0.04, [0.8, 0.7, 0.6, 1.0]),
+            Body(0.15, 0.01, -0.03, [0.6, 0.6, 0.8, 1.0]),
+        ],
+    ),
+];
+
+///
+/// A small solar system built on the scene graph.
+/// Every body hangs off a node that spins around its parent's centre,
+/// so moons are carried around the sun by their planet's orbit.
+///
+pub struct Planets {
+    graph: SceneGraph,
+    // Node each body turns around its parent with and how far it turns each tick
+    orbits: Vec<(NodeId, f32)>,
+    // The sun first, then each planet followed by its moons
+    bodies: Vec<NodeId>,
+}
+
+impl Planets {
+    pub fn default(display: &dyn Backend) -> Self {
+        let mut planets = Planets {
+            graph: SceneGraph::new(),
+            orbits: vec![],
+            bodies: vec![],
+        };
+        let sun = planets.add_body(display, None, &SUN);
+        for (planet, moons) in PLANETS.iter() {
+            let planet = planets.add_body(display, Some(sun), planet);
+            for moon in moons.iter() {
+                planets.add_body(display, Some(planet), moon);
+            }
+        }
+        planets
+    }
+
+    // An orbit node on the parent with the body out along its x axis, and the body's path drawn around the parent
+    fn add_body(&mut self, display: &dyn Backend, parent: Option<NodeId>, body: &Body) -> NodeId {
+        let &Body(distance, radius, speed, color) = body;
+        let orbit = match parent {
+            Some(parent) => self.graph.add_child(parent, Transform::IDENTITY),
+            None => self.graph.add_root(Transform::IDENTITY),
+        };
+        let node = self
+            .graph
+            .add_child(orbit, Transform::from_translation(Vec3::X * distance));
+        let circle = Shape::circle(display, radius, 48);
+        self.graph.attach_shape(node, circle, display)[0].color = color;
+        if let Some(parent) = parent {
+            let path = [color[0] * 0.4, color[1] * 0.4, color[2] * 0.4, 1.0];
+            self.attach_path(display, parent, distance, path);
+            self.orbits.push((orbit, speed));
+        }
+        self.bodies.push(node);
+        node
+    }
+
+    // The circle a body follows around its parent, dimmer than the body
+    fn attach_path(&mut self, display: &dyn Backend, node: NodeId, radius: f32, color: [f32; 4]) {
+        let attr = Instance {
+            color,
+            ..Instance::default()
+        };
+        let circle = Shape::circle(display, radius, 48);
+        let group = InstanceGroup::from_transforms(circle, &[attr], display);
+        self.graph.attach_instances(node, group);
+    }
+
+    pub fn positions(&self) -> Vec<Vec3> {
+        self.bodies
+            .iter()
+            .map(|&body| self.graph.world_position(body))
+            .collect()
+    }
+}
+
+impl Drawable for Planets {
+    fn draw(
+        &self,
+        target: &mut Frame,
+        programs: &ProgramLibrary,
+        params: &DrawParameters,
+        uniforms: DrawUniforms,
+    ) {
+        self.graph.draw(target, programs, params, uniforms);
+    }
+
+    fn update(&mut self) {
+        for &(orbit, speed) in self.orbits.iter() {
+            self.graph.rotate_node(orbit, Vec3::Z, speed);
+        }
+    }
+}
+
+impl Manipulate for Planets {
+    fn rotate(&mut self, axis: Vec3, angle: f32) {
+        self.graph.rotate(axis, angle);
+    }
+}
+
+// World positions of the bodies, the sun first
+impl Scene for Planets {
+    fn dump(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "x,y,z")?;
+        for p in self.positions() {
+            writeln!(out, "{},{},{}", p.x, p.y, p.z)?;
+        }
+        Ok(())
+    }
+
+    fn camera(&self) -> Box<dyn Camera> {
+        Box::new(OrbitCamera::new(Vec3::ZERO, 1.2))
+    }
+}
@@ -1,9 +1,17 @@
 use super::engine::Runnable;
+use super::scene::{SceneParams, SceneRegistry};
 use drawable::Drawable;
 use glium::glutin::event_loop::EventLoop;
+use model;
 use std::any::Any;
 use std::cell::RefCell;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::ops::Deref;
+use std::path::Path;
+use std::time::Instant;
+use util::bufferable::Headless;
 use util::Manipulate;
 
 // Holds the event loop that will run the engine
@@ -42,4 +50,48 @@ impl App {
             engine.handle_events(&ev, control_flow);
         });
     }
+
+    ///
+    /// Runs a scene for a number of ticks without a window, event loop or GL context,
+    /// then writes its state to `path` and its mesh to `export` if there is one,
+    /// as text if `ascii` is set.
+    /// Scenes that need the GPU fail to build.
+    ///
+    pub fn run_headless(
+        scenes: &SceneRegistry,
+        name: &str,
+        params: &SceneParams,
+        ticks: u64,
+        path: &Path,
+        export: Option<&Path>,
+        ascii: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let start = Instant::now();
+        let mut scene = scenes.build(name, &Headless, params)?;
+        for _ in 0..ticks {
+            scene.update();
+        }
+        let mut out = BufWriter::new(File::create(path)?);
+        scene.dump(&mut out)?;
+        out.flush()?;
+        println!(
+            "Ran {} for {} ticks in {:?}, state written to {}",
+            name,
+            ticks,
+            start.elapsed(),
+            path.display()
+        );
+        if let Some(export) = export {
+            let mesh = scene
+                .mesh()
+                .ok_or_else(|| format!("'{}' doesn't have a mesh to export", name))?;
+            model::save(export, &mesh, ascii)?;
+            println!(
+                "Exported {} triangles to {}",
+                mesh.triangle_count(),
+                export.display()
+            );
+        }
+        Ok(())
+    }
 }
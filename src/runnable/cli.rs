@@ -0,0 +1,412 @@
+use gol::pattern::Pattern;
+use gol::rule::Rule;
+use gol::topology::{Topology, TOPOLOGIES};
+use runnable::engine::{DEFAULT_SCENE, DEFAULT_SHADER_DIR};
+use runnable::scene::{SceneParams, SceneRegistry};
+use runnable::timestep::{DEFAULT_MAX_CATCH_UP, DEFAULT_TICK_RATE, MAX_TICK_RATE, MIN_TICK_RATE};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+// Options that are switched on just by being there
+const FLAGS: &[&str] = &["-h", "--help", "--no-vsync", "--check-gpu-boids", "--ascii"];
+// Options followed by a value
+const VALUED: &[&str] = &[
+    "-s",
+    "--scene",
+    "--size",
+    "--seed",
+    "--boids",
+    "--grid",
+    "--rule",
+    "--topology",
+    "--pattern",
+    "--model",
+    "--tick-rate",
+    "--max-catch-up",
+    "--headless",
+    "--out",
+    "--shaders",
+    "--define",
+    "--export",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliError {
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue {
+        option: String,
+        value: String,
+        reason: String,
+    },
+    UnknownScene(String, Vec<&'static str>),
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::UnknownOption(option) => write!(f, "unknown option '{}'", option),
+            CliError::MissingValue(option) => write!(f, "option '{}' needs a value", option),
+            CliError::InvalidValue {
+                option,
+                value,
+                reason,
+            } => write!(f, "invalid value '{}' for '{}': {}", value, option, reason),
+            CliError::UnknownScene(name, scenes) => write!(
+                f,
+                "unknown scene '{}', expected one of: {}",
+                name,
+                scenes.join(", ")
+            ),
+        }
+    }
+}
+
+impl Error for CliError {}
+
+///
+/// Everything that can be set from the command line.
+/// The defaults match what the engine did before there were any options.
+///
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub scene: String,
+    pub width: u32,
+    pub height: u32,
+    pub vsync: bool,
+    pub seed: Option<u64>,
+    pub params: SceneParams,
+    // Simulation ticks per second, independent of the frame rate
+    pub tick_rate: f64,
+    pub max_catch_up: u32,
+    // Ticks to run without a window before dumping the scene's state
+    pub headless: Option<u64>,
+    pub output: Option<PathBuf>,
+    // Where a headless run also saves the scene's mesh, as STL or PLY by the extension
+    pub export: Option<PathBuf>,
+    // Write the exported mesh as text rather than binary
+    pub ascii: bool,
+    // Directory to load and watch shaders in, the default one is only used if it's there
+    pub shaders: Option<PathBuf>,
+    // Added to every shader, as #define NAME VALUE
+    pub defines: Vec<(String, String)>,
+    // Compare the GPU boids against the CPU ones instead of opening a window
+    pub check_gpu_boids: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            scene: DEFAULT_SCENE.to_string(),
+            width: 800,
+            height: 800,
+            vsync: true,
+            seed: None,
+            params: SceneParams::default(),
+            tick_rate: DEFAULT_TICK_RATE,
+            max_catch_up: DEFAULT_MAX_CATCH_UP,
+            headless: None,
+            output: None,
+            export: None,
+            ascii: false,
+            shaders: None,
+            defines: vec![],
+            check_gpu_boids: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Run(Box<Options>),
+    Help,
+}
+
+// Text for --help, listing the scenes in the order the number keys pick them
+pub fn usage(program: &str, scenes: &SceneRegistry) -> String {
+    let defaults = Options::default();
+    let mut text = format!(
+        "Usage: {} [OPTIONS]\n\
+         \n\
+         Options:\n\
+         \x20 -s, --scene NAME       scene to start with (default: {})\n\
+         \x20     --size WxH         window size in pixels (default: {}x{})\n\
+         \x20     --no-vsync         don't wait for vsync between frames\n\
+         \x20     --seed N           seed for everything that's randomised\n\
+         \x20     --boids N          number of boids (default: {})\n\
+         \x20     --grid WxH         Game of Life grid size (default: {}x{})\n\
+         \x20     --rule RULE        Game of Life rule, e.g. B36/S23 or highlife (default: {})\n\
+         \x20     --topology NAME    how the Game of Life grid's edges join, one of\n\
+         \x20                        {} (default: {})\n\
+         \x20     --pattern PATH     start the Game of Life with a .rle, .lif or .cells pattern\n\
+         \x20                        and its rule, PATH@X,Y puts its top left cell at X,Y\n\
+         \x20                        (default: centred)\n\
+         \x20     --model PATH       show a .obj, .stl or .ply file in the model scene\n\
+         \x20     --tick-rate HZ     simulation ticks per second (default: {})\n\
+         \x20     --max-catch-up N   most ticks run in one frame after a stall (default: {})\n\
+         \x20     --headless TICKS   run TICKS ticks without a window, dump the state and exit\n\
+         \x20     --out PATH         where --headless writes to (default: SCENE-TICKS.txt)\n\
+         \x20     --export PATH      with --headless, also save the scene's mesh as .stl or .ply\n\
+         \x20     --ascii            write --export files as text rather than binary\n\
+         \x20     --shaders DIR      load shaders from DIR and reload them when they change\n\
+         \x20                        (default: {} if it's there)\n\
+         \x20     --define NAME[=V]  add #define NAME V to every shader, can be repeated\n\
+         \x20     --check-gpu-boids  compare the GPU boids against the CPU ones and exit,\n\
+         \x20                        with 1 if they differ or can't be run\n\
+         \x20 -h, --help             show this help\n\
+         \n\
+         Keys:\n\
+         \x20 1-9    switch scene\n\
+         \x20 space  pause or resume\n\
+         \x20 .      step one tick while paused\n\
+         \x20 [ ]    halve or double the simulation speed, 0 resets it\n\
+         \x20 - =    Game of Life: halve or double the generations per tick\n\
+         \x20 arrows Game of Life: move the view\n\
+         \x20 p      Game of Life: save the cells in view to gol-GENERATION.rle\n\
+         \x20        model: save the model as it's turned now to model-TICK.stl\n\
+         \x20 f      model: turn the faces inside out, for files saved with the wrong winding\n\
+         \x20 z x    boids: lower or raise the separation weight\n\
+         \x20 c v    boids: lower or raise the alignment weight\n\
+         \x20 b n    boids: lower or raise the cohesion weight\n\
+         \n\
+         Cameras:\n\
+         \x20 orbit (boids)   left drag rolls the scene like a ball, right drag pans, scroll zooms\n\
+         \x20 fly (landscape) WASD moves, E and Q go up and down, shift is faster, drag looks\n\
+         \x20 pan/zoom (gol)  drag pans, scroll zooms around the cursor\n\
+         \x20 orbit (model)   as for boids, while the model turns on its own\n\
+         \n\
+         Scenes:\n",
+        program,
+        defaults.scene,
+        defaults.width,
+        defaults.height,
+        defaults.params.boids,
+        defaults.params.grid_width,
+        defaults.params.grid_height,
+        defaults.params.rule,
+        topology_names().join(", "),
+        defaults.params.topology.name(),
+        defaults.tick_rate,
+        defaults.max_catch_up,
+        DEFAULT_SHADER_DIR,
+    );
+    for (i, name) in scenes.names().enumerate() {
+        text += &format!("  {}  {}\n", i + 1, name);
+    }
+    text
+}
+
+fn topology_names() -> Vec<&'static str> {
+    TOPOLOGIES.iter().map(|&(name, _)| name).collect()
+}
+
+fn invalid(option: &str, value: &str, reason: impl ToString) -> CliError {
+    CliError::InvalidValue {
+        option: option.to_string(),
+        value: value.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+fn parse_number<T>(option: &str, value: &str) -> Result<T, CliError>
+where
+    T: std::str::FromStr,
+    T::Err: ToString,
+{
+    value.parse().map_err(|err| invalid(option, value, err))
+}
+
+fn parse_count(option: &str, value: &str) -> Result<usize, CliError> {
+    match parse_number(option, value)? {
+        0 => Err(invalid(option, value, "must be at least 1")),
+        n => Ok(n),
+    }
+}
+
+// Parses sizes written as WIDTHxHEIGHT, neither of which can be zero
+fn parse_size(option: &str, value: &str) -> Result<(usize, usize), CliError> {
+    let (width, height) = value
+        .split_once(['x', 'X'])
+        .ok_or_else(|| invalid(option, value, "expected WIDTHxHEIGHT"))?;
+    let side = |side: &str| {
+        parse_count(option, side)
+            .map_err(|_| invalid(option, value, "sides must be whole numbers of at least 1"))
+    };
+    Ok((side(width)?, side(height)?))
+}
+
+// A pattern file, optionally followed by where its top left cell goes as @X,Y
+fn parse_pattern(option: &str, value: &str) -> Result<(Pattern, Option<(i64, i64)>), CliError> {
+    let at = value.rsplit_once('@').and_then(|(path, at)| {
+        let (x, y) = at.split_once(',')?;
+        Some((path, (x.trim().parse().ok()?, y.trim().parse().ok()?)))
+    });
+    let (path, at) = match at {
+        Some((path, at)) => (path, Some(at)),
+        None => (value, None),
+    };
+    let pattern = Pattern::load(path).map_err(|err| invalid(option, value, err))?;
+    Ok((pattern, at))
+}
+
+// A define written as NAME or NAME=VALUE, the name being one GLSL would take
+fn parse_define(option: &str, value: &str) -> Result<(String, String), CliError> {
+    let (name, define) = value.split_once('=').unwrap_or((value, ""));
+    let mut chars = name.chars();
+    let starts_well = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    if !starts_well || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(invalid(option, value, "expected NAME or NAME=VALUE"));
+    }
+    Ok((name.to_string(), define.to_string()))
+}
+
+// A rule string, or the name of one of the presets
+fn parse_rule(option: &str, value: &str) -> Result<Rule, CliError> {
+    let rule = if value.contains(|c: char| c.is_ascii_digit() || c == '/') {
+        value.parse()
+    } else {
+        Rule::preset(value)
+    };
+    rule.map_err(|err| invalid(option, value, err))
+}
+
+///
+/// Parses the arguments after the program name.
+/// Options take their value either as the next argument or after an '='.
+///
+pub fn parse<I>(args: I, scenes: &SceneRegistry) -> Result<Command, CliError>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (option, inline) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => {
+                (option.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+        let takes_value = if VALUED.contains(&option.as_str()) {
+            true
+        } else if FLAGS.contains(&option.as_str()) {
+            false
+        } else {
+            return Err(CliError::UnknownOption(option));
+        };
+        let value = match (takes_value, inline) {
+            (false, None) => String::new(),
+            (false, Some(value)) => {
+                return Err(invalid(&option, &value, "this option doesn't take a value"))
+            }
+            (true, Some(value)) => value,
+            (true, None) => args
+                .next()
+                .ok_or_else(|| CliError::MissingValue(option.clone()))?,
+        };
+
+        match option.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--no-vsync" => options.vsync = false,
+            "--check-gpu-boids" => options.check_gpu_boids = true,
+            "--ascii" => options.ascii = true,
+            "-s" | "--scene" => {
+                if !scenes.contains(&value) {
+                    return Err(CliError::UnknownScene(value, scenes.names().collect()));
+                }
+                options.scene = value;
+            }
+            "--size" => {
+                let (width, height) = parse_size(&option, &value)?;
+                let too_big = |_| invalid(&option, &value, "too large");
+                options.width = u32::try_from(width).map_err(too_big)?;
+                options.height = u32::try_from(height).map_err(too_big)?;
+            }
+            "--seed" => options.seed = Some(parse_number(&option, &value)?),
+            "--boids" => options.params.boids = parse_count(&option, &value)?,
+            "--grid" => {
+                let (width, height) = parse_size(&option, &value)?;
+                options.params.grid_width = width;
+                options.params.grid_height = height;
+            }
+            "--rule" => options.params.rule = parse_rule(&option, &value)?,
+            "--topology" => {
+                options.params.topology = Topology::from_name(&value).ok_or_else(|| {
+                    let expected = format!("expected one of {}", topology_names().join(", "));
+                    invalid(&option, &value, expected)
+                })?
+            }
+            "--pattern" => {
+                let (pattern, at) = parse_pattern(&option, &value)?;
+                options.params.pattern = Some(Box::new(pattern));
+                options.params.pattern_at = at;
+            }
+            "--model" => options.params.model = Some(PathBuf::from(value)),
+            "--tick-rate" => {
+                let rate: f64 = parse_number(&option, &value)?;
+                if !(MIN_TICK_RATE..=MAX_TICK_RATE).contains(&rate) {
+                    let reason = format!("must be between {} and {}", MIN_TICK_RATE, MAX_TICK_RATE);
+                    return Err(invalid(&option, &value, reason));
+                }
+                options.tick_rate = rate;
+            }
+            "--headless" => options.headless = Some(parse_number(&option, &value)?),
+            "--out" => options.output = Some(PathBuf::from(value)),
+            "--export" => options.export = Some(PathBuf::from(value)),
+            "--shaders" => options.shaders = Some(PathBuf::from(value)),
+            "--define" => options.defines.push(parse_define(&option, &value)?),
+            "--max-catch-up" => {
+                options.max_catch_up = match parse_number(&option, &value)? {
+                    0 => return Err(invalid(&option, &value, "must be at least 1")),
+                    n => n,
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+    if let (Some(export), None) = (&options.export, options.headless) {
+        let export = export.display().to_string();
+        return Err(invalid("--export", &export, "only works with --headless"));
+    }
+    if options.ascii && options.export.is_none() {
+        return Err(invalid("--ascii", "", "only works with --export"));
+    }
+    Ok(Command::Run(Box::new(options)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> Result<Options, CliError> {
+        let args = args.iter().map(|arg| arg.to_string());
+        match parse(args, &SceneRegistry::default())? {
+            Command::Run(options) => Ok(*options),
+            _ => panic!("expected options to run with"),
+        }
+    }
+
+    #[test]
+    fn tick_rates_in_range_are_taken() {
+        assert_eq!(run(&["--tick-rate", "0.001"]).unwrap().tick_rate, 0.001);
+        assert_eq!(run(&["--tick-rate", "120"]).unwrap().tick_rate, 120.0);
+        assert_eq!(run(&["--tick-rate", "10000"]).unwrap().tick_rate, 10000.0);
+    }
+
+    #[test]
+    fn tick_rates_that_would_break_the_clock_are_rejected() {
+        for rate in &["1e-300", "0", "-60", "20000", "inf", "NaN"] {
+            match run(&["--tick-rate", rate]) {
+                Err(CliError::InvalidValue { option, value, .. }) => {
+                    assert_eq!(option, "--tick-rate");
+                    assert_eq!(value, *rate);
+                }
+                other => panic!("{} gave {:?}", rate, other.map(|o| o.tick_rate)),
+            }
+        }
+    }
+}
@@ -1,59 +1,130 @@
-use boids::Boids;
-use drawable::Drawable;
-use glium::{glutin, Display, Program, Surface};
-use gol::GameOfLife;
-use std::time::SystemTime;
+use camera::orbit::OrbitCamera;
+use camera::Camera;
+use drawable::{DrawUniforms, Drawable};
+use glium::backend::glutin::DisplayCreationError;
+use glium::{glutin, Display, Surface};
+use runnable::cli::Options;
+use runnable::scene::{Scene, SceneParams, SceneRegistry};
+use runnable::timestep::Timestep;
+use shader::{ProgramLibrary, ProgramSource};
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Instant;
 use util::Manipulate;
-use winit::dpi::LogicalSize;
-use winit::event::{Event, KeyboardInput, WindowEvent};
+use winit::dpi::{LogicalSize, PhysicalSize};
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 
-// The default shader that is stored in the engine
-pub const BASE_VSHADER: &str = r#"
-        #version 140
-        in vec3 position;
-        in vec3 normal;
-        in vec3 world_position;
-        in mat4 rotation_matrix;
-        in vec4 color;
-        uniform mat4 perspective;
-        out vec3 v_normal;
-        out vec4 v_col;
-
-        void main() {
-            v_col = color;
-            v_normal = transpose(inverse(mat3(rotation_matrix))) * normal;
-            vec4 pos = vec4(position, 1.0) * rotation_matrix * perspective;
-            gl_Position = pos + vec4(world_position, 1.0);
-        }
-    "#;
-
-pub const BASE_FSHADER: &str = r#"
-        #version 140
-        in vec3 v_normal;
-        in vec4 v_col;
-        uniform vec3 u_light;
-        out vec4 color;
-        void main() {
-            float brightness = dot(normalize(v_normal), normalize(u_light));
-            // vec4 dark_color = vec4(v_col.xyz * 0.6, v_col.w);
-            vec4 dark_color = v_col;
-            vec4 regular_color = v_col;
-            color = mix(dark_color, regular_color, brightness);
-        }
-    "#;
+// The default shaders, built in so the engine runs from anywhere.
+// Copies in the shaders directory replace them and are reloaded when they change
+pub const BASE_VSHADER: &str = include_str!("../../shaders/base.vert");
+
+pub const BASE_FSHADER: &str = include_str!("../../shaders/base.frag");
+
+// Scene shown when the engine starts
+pub const DEFAULT_SCENE: &str = "gol";
+
+// Where shaders are loaded from if it's there and no other directory is given
+pub const DEFAULT_SHADER_DIR: &str = "shaders";
+
+const SCENE_KEYS: [VirtualKeyCode; 9] = [
+    VirtualKeyCode::Key1,
+    VirtualKeyCode::Key2,
+    VirtualKeyCode::Key3,
+    VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5,
+    VirtualKeyCode::Key6,
+    VirtualKeyCode::Key7,
+    VirtualKeyCode::Key8,
+    VirtualKeyCode::Key9,
+];
 
 pub struct Engine {
-    pub objects: Vec<GameOfLife>,
-    pub programs: Vec<Program>,
+    pub objects: Vec<Box<dyn Scene>>,
+    pub programs: ProgramLibrary,
     pub display: Display,
+    pub scenes: SceneRegistry,
+    scene: String,
+    params: SceneParams,
+    timestep: Timestep,
+    camera: Box<dyn Camera>,
+}
+
+impl Engine {
+    // Set up an engine with a window and starting scene picked on the command line
+    pub fn with_options(
+        event_loop: &EventLoop<()>,
+        options: &Options,
+        scenes: SceneRegistry,
+    ) -> Result<Self, Box<dyn Error>> {
+        let start = Instant::now();
+        let display = create_display(event_loop, options.width, options.height, options.vsync)?;
+        let mut programs = ProgramLibrary::new(&display);
+        // Shaders in a directory of their own can still include the built in base shader
+        programs.add_include("base.frag", BASE_FSHADER);
+        for (name, value) in options.defines.iter() {
+            programs.define(name, value);
+        }
+        programs.add("base", ProgramSource::new(BASE_VSHADER, BASE_FSHADER))?;
+        let shader_dir = options
+            .shaders
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_SHADER_DIR));
+        if options.shaders.is_some() || shader_dir.is_dir() {
+            let errors = programs.load_dir(&shader_dir).map_err(|err| {
+                format!(
+                    "couldn't read shaders from '{}': {}",
+                    shader_dir.display(),
+                    err
+                )
+            })?;
+            // Broken shaders on disk leave the built in ones running until they're fixed
+            for err in errors {
+                eprintln!("{}", err);
+            }
+        }
+        let mut engine = Self {
+            objects: vec![],
+            programs,
+            display,
+            scenes,
+            scene: String::new(),
+            params: options.params.clone(),
+            timestep: Timestep::new(options.tick_rate, options.max_catch_up),
+            camera: Box::new(OrbitCamera::default()),
+        };
+        engine.switch_scene(&options.scene)?;
+        println!("Init time: {:?}", start.elapsed());
+        Ok(engine)
+    }
+
+    ///
+    /// Replaces the running scene with a freshly built one and its camera.
+    /// The old scene is dropped, freeing its GPU buffers, only once the new one
+    /// has been built, so a scene that fails to build leaves the old one running.
+    ///
+    pub fn switch_scene(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        let mut scene = self.scenes.build(name, &self.display, &self.params)?;
+        scene.load_programs(&mut self.programs)?;
+        let (width, height) = self.display.get_framebuffer_dimensions();
+        scene.resize(width, height);
+        self.camera = scene.camera();
+        self.camera.resize(width, height);
+        self.objects.clear();
+        self.objects.push(scene);
+        self.scene = name.to_string();
+        self.display
+            .gl_window()
+            .window()
+            .set_title(&format!("OpenGL Template - {}", self.scene));
+        Ok(())
+    }
 }
 
 // Trait for structs that hold a vector of objects that implement HasPos
 // as well as a vector of programs (shaders) to draw the objects
 impl Updatable for Engine {
-    type RefType = GameOfLife;
-    type Type = Engine;
+    type RefType = Box<dyn Scene>;
 
     fn mut_objects(&mut self) -> &mut Vec<Self::RefType> {
         &mut self.objects
@@ -61,59 +132,117 @@ impl Updatable for Engine {
     fn ref_objects(&self) -> &Vec<Self::RefType> {
         &self.objects
     }
-    fn ref_programs(&self) -> &Vec<Program> {
+    fn ref_programs(&self) -> &ProgramLibrary {
         &self.programs
     }
+    fn mut_programs(&mut self) -> &mut ProgramLibrary {
+        &mut self.programs
+    }
     fn ref_display(&self) -> &Display {
         &self.display
     }
+    fn mut_timestep(&mut self) -> &mut Timestep {
+        &mut self.timestep
+    }
+    fn ref_timestep(&self) -> &Timestep {
+        &self.timestep
+    }
+    fn mut_camera(&mut self) -> &mut dyn Camera {
+        &mut *self.camera
+    }
+    fn ref_camera(&self) -> &dyn Camera {
+        &*self.camera
+    }
 
-    // Set up an engine on a given event loop with predefined objects
-    fn init(event_loop: &EventLoop<()>) -> Self::Type {
-        let start = SystemTime::now();
-        let display = Self::default_display(event_loop);
-        let obj = GameOfLife::default(&display);
-        let programs =
-            vec![Program::from_source(&display, BASE_VSHADER, BASE_FSHADER, None).unwrap()];
-        println!(
-            "Init time: {:?}",
-            SystemTime::now().duration_since(start).unwrap()
-        );
-        Self {
-            objects: vec![obj],
-            programs,
-            display,
+    // Number keys switch to the scene registered in that position,
+    // space pauses, '.' steps once while paused, '[' and ']' halve and double the speed and '0' resets it.
+    // Any other key goes to the scene
+    fn handle_keys(&mut self, input: &KeyboardInput) {
+        if input.state != ElementState::Pressed {
+            return;
+        }
+        let key = match input.virtual_keycode {
+            Some(key) => key,
+            None => return,
+        };
+        let timestep = &mut self.timestep;
+        match key {
+            VirtualKeyCode::Space => {
+                timestep.set_paused(!timestep.paused());
+                println!(
+                    "{}",
+                    if timestep.paused() {
+                        "Paused"
+                    } else {
+                        "Resumed"
+                    }
+                );
+            }
+            VirtualKeyCode::Period => timestep.step_once(),
+            VirtualKeyCode::LBracket | VirtualKeyCode::RBracket | VirtualKeyCode::Key0 => {
+                let scale = match key {
+                    VirtualKeyCode::LBracket => timestep.time_scale() / 2.0,
+                    VirtualKeyCode::RBracket => timestep.time_scale() * 2.0,
+                    _ => 1.0,
+                };
+                timestep.set_time_scale(scale.clamp(1.0 / 64.0, 64.0));
+                println!("Time scale: {}x", timestep.time_scale());
+            }
+            _ => {
+                let name = SCENE_KEYS
+                    .iter()
+                    .position(|&k| k == key)
+                    .and_then(|index| self.scenes.name(index));
+                match name {
+                    Some(name) => {
+                        if let Err(err) = self.switch_scene(name) {
+                            println!("Couldn't switch to scene '{}': {}", name, err);
+                        }
+                    }
+                    None => self
+                        .objects
+                        .iter_mut()
+                        .for_each(|scene| scene.handle_key(key)),
+                }
+            }
         }
     }
 }
 
+// Make a window of the given size with a depth buffer
+pub fn create_display(
+    ev: &EventLoop<()>,
+    width: u32,
+    height: u32,
+    vsync: bool,
+) -> Result<Display, DisplayCreationError> {
+    let wb = glutin::window::WindowBuilder::new()
+        .with_title("OpenGL Template")
+        .with_inner_size(LogicalSize {
+            width: width as f64,
+            height: height as f64,
+        })
+        .with_resizable(true);
+    let cb = glutin::ContextBuilder::new()
+        .with_depth_buffer(24)
+        .with_vsync(vsync);
+    Display::new(wb, cb, ev)
+}
+
 // Trait that makes methods for references to all necessary objects
 pub trait Updatable {
     type RefType: Drawable + Manipulate;
-    type Type;
-
-    // Make a default display given an event loop
-    fn default_display(ev: &EventLoop<()>) -> Display {
-        let wb = glutin::window::WindowBuilder::new()
-            .with_title("OpenGL Template")
-            .with_inner_size(LogicalSize {
-                width: 800.0,
-                height: 800.0,
-            })
-            .with_resizable(false);
-        let cb = glutin::ContextBuilder::new()
-            .with_depth_buffer(24)
-            .with_vsync(true);
-        let display = Display::new(wb, cb, &ev).unwrap();
-        display
-    }
 
     fn mut_objects(&mut self) -> &mut Vec<Self::RefType>;
     fn ref_objects(&self) -> &Vec<Self::RefType>;
-    fn ref_programs(&self) -> &Vec<Program>;
+    fn ref_programs(&self) -> &ProgramLibrary;
+    fn mut_programs(&mut self) -> &mut ProgramLibrary;
     fn ref_display(&self) -> &Display;
-    // Set up the engine
-    fn init(event_loop: &EventLoop<()>) -> Self::Type;
+    fn mut_timestep(&mut self) -> &mut Timestep;
+    fn ref_timestep(&self) -> &Timestep;
+    fn mut_camera(&mut self) -> &mut dyn Camera;
+    fn ref_camera(&self) -> &dyn Camera;
+    fn handle_keys(&mut self, _input: &KeyboardInput) {}
 }
 
 // Trait for holding the main functions of the engine
@@ -123,8 +252,9 @@ where
 {
     fn window_handle(&mut self, window_event: &WindowEvent, control_flow: &mut ControlFlow);
     fn handle_events(&mut self, ev: &Event<()>, control_flow: &mut ControlFlow);
-    fn handle_keys(&mut self, _input: &KeyboardInput) {}
+    fn resize(&mut self, _size: PhysicalSize<u32>) {}
     fn update(&mut self) {}
+    fn reload_shaders(&mut self) {}
     fn draw(&mut self);
 }
 
@@ -133,11 +263,16 @@ where
     U: Updatable<RefType = T>,
     T: Drawable + Manipulate,
 {
-    // Handle window closes and send keyboard inputs to key handler
+    // Handle window closes and resizes, and send keyboard inputs to key handler
+    // and everything from the mouse and keyboard to the camera
     fn window_handle(&mut self, window_event: &WindowEvent, control_flow: &mut ControlFlow) {
+        self.mut_camera().handle_event(window_event);
         match window_event {
             WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
             WindowEvent::KeyboardInput { input, .. } => self.handle_keys(input),
+            WindowEvent::Resized(size) => self.resize(*size),
+            // Moving to a monitor with a different DPI changes the size in pixels too
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => self.resize(**new_inner_size),
             _ => (),
         }
     }
@@ -146,8 +281,16 @@ where
     fn handle_events(&mut self, ev: &Event<()>, control_flow: &mut ControlFlow) {
         match ev {
             Event::WindowEvent { event, .. } => self.window_handle(event, control_flow),
+            // Run however many fixed ticks have built up since the last frame, then draw.
+            // The camera moves in real time so it can still be flown around while paused
             Event::MainEventsCleared => {
-                self.update();
+                let ticks = self.mut_timestep().advance(Instant::now());
+                for _ in 0..ticks {
+                    self.update();
+                }
+                let dt = self.ref_timestep().frame_time().as_secs_f32();
+                self.mut_camera().update(dt);
+                self.reload_shaders();
                 self.ref_display().gl_window().window().request_redraw();
             }
             Event::RedrawRequested(_) => {
@@ -157,28 +300,46 @@ where
         }
     }
 
+    // Resize the surface and tell the objects, minimised windows report a size of zero
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        self.ref_display().gl_window().resize(size);
+        self.mut_camera().resize(size.width, size.height);
+        self.mut_objects()
+            .iter_mut()
+            .for_each(|obj| obj.resize(size.width, size.height));
+    }
+
+    // Rebuild the programs whose shader files changed, keeping the old ones if they don't compile
+    fn reload_shaders(&mut self) {
+        for (name, result) in self.mut_programs().reload_changed(Instant::now()) {
+            match result {
+                Ok(()) => println!("Reloaded shaders for '{}'", name),
+                Err(err) => eprintln!("{}\nStill drawing with the last '{}' that built", err, name),
+            }
+        }
+    }
+
     // Update the objects in the vector
     fn update(&mut self) {
-        let start = SystemTime::now();
-        let objects = self.mut_objects();
-        objects.iter_mut().for_each(|obj| {
-            // obj.rotate_axis(1, 0.005);
-            obj.update()
-        });
-        println!("Update time: {:?}", start.elapsed().unwrap());
+        self.mut_objects().iter_mut().for_each(|obj| obj.update());
     }
 
     fn draw(&mut self) {
-        let start = SystemTime::now();
-
         // Grab the target frame from the display
         let mut target = self.ref_display().draw();
         // Clear the background
         target.clear_color_and_depth((0.0, 0.0, 0.0, 1.0), 20.0);
 
-        // Use the program at the index of the id of each object
+        // Each object picks its own programs out of the library
         let programs = self.ref_programs();
         let objects = self.ref_objects();
+        // Build the projection from the real framebuffer size so nothing gets stretched
+        let (width, height) = target.get_dimensions();
+        let uniforms = DrawUniforms::from_camera(self.ref_camera(), width, height)
+            .with_alpha(self.ref_timestep().alpha());
         let params = glium::DrawParameters {
             depth: glium::Depth {
                 test: glium::draw_parameters::DepthTest::IfLess,
@@ -189,12 +350,10 @@ where
         };
 
         for s in objects.iter() {
-            // Draw the object onto the frame with the given shader
-            s.draw(&mut target, &programs[0], &params, Default::default());
+            s.draw(&mut target, programs, &params, uniforms);
         }
 
         // Finish with the frame
         target.finish().unwrap();
-        println!("Frame time: {:?}", start.elapsed().unwrap());
     }
 }
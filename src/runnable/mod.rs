@@ -1,2 +1,5 @@
 pub(crate) mod app;
+pub(crate) mod cli;
 pub(crate) mod engine;
+pub(crate) mod scene;
+pub(crate) mod timestep;
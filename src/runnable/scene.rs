@@ -0,0 +1,256 @@
+use boids::gpu::GpuBoids;
+use boids::{Boids, NUM_BOIDS};
+use camera::orbit::OrbitCamera;
+use camera::Camera;
+use drawable::mesh::Mesh;
+use drawable::Drawable;
+use gol;
+use gol::pattern::Pattern;
+use gol::rule::Rule;
+use gol::topology::Topology;
+use gol::GameOfLife;
+use landscape::Landscape;
+use planets::Planets;
+use shader::{ProgramLibrary, ShaderError};
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use util::bufferable::Backend;
+use util::Manipulate;
+use viewer::ModelViewer;
+use winit::event::VirtualKeyCode;
+
+///
+/// Anything the engine can show: it draws, updates and can be turned,
+/// and can write out its simulation state for headless runs.
+///
+pub trait Scene: Drawable + Manipulate {
+    fn dump(&self, out: &mut dyn Write) -> io::Result<()>;
+
+    // The camera the scene starts with when it's switched to
+    fn camera(&self) -> Box<dyn Camera> {
+        Box::new(OrbitCamera::default())
+    }
+
+    // Its triangles with the instance transforms applied, for scenes that can be exported
+    fn mesh(&self) -> Option<Mesh> {
+        None
+    }
+
+    // Keys the engine doesn't use itself are passed on to the scene
+    fn handle_key(&mut self, _key: VirtualKeyCode) {}
+
+    // Adds any programs of its own, called once there's a display and before it's drawn
+    fn load_programs(&mut self, _programs: &mut ProgramLibrary) -> Result<(), ShaderError> {
+        Ok(())
+    }
+}
+
+///
+/// Starting parameters handed to every scene builder.
+/// Each scene reads the ones that apply to it.
+///
+#[derive(Clone, Debug)]
+pub struct SceneParams {
+    pub boids: usize,
+    pub grid_width: usize,
+    pub grid_height: usize,
+    pub rule: Rule,
+    pub topology: Topology,
+    // Cells the Game of Life starts with instead of random ones
+    pub pattern: Option<Box<Pattern>>,
+    // Where the pattern's top left cell goes, None centres it on the grid
+    pub pattern_at: Option<(i64, i64)>,
+    // A file for the model scene to show instead of the primitives
+    pub model: Option<PathBuf>,
+}
+
+impl Default for SceneParams {
+    fn default() -> Self {
+        SceneParams {
+            boids: NUM_BOIDS,
+            grid_width: gol::WIDTH,
+            grid_height: gol::HEIGHT,
+            rule: Rule::default(),
+            topology: Topology::default(),
+            pattern: None,
+            pattern_at: None,
+            model: None,
+        }
+    }
+}
+
+// Loads the pattern from the command line, or fills the view at random without one
+// The rule a Game of Life starts with, the pattern's own if it has one
+fn starting_rule(params: &SceneParams) -> Rule {
+    params
+        .pattern
+        .as_ref()
+        .and_then(|pattern| pattern.rule)
+        .unwrap_or(params.rule)
+}
+
+fn start_gol(gol: &mut GameOfLife, params: &SceneParams) {
+    match (&params.pattern, params.pattern_at) {
+        (Some(pattern), Some((x, y))) => gol.load_pattern(pattern, x, y),
+        (Some(pattern), None) => gol.load_pattern_centred(pattern),
+        (None, _) => gol.randomize(0.5),
+    }
+}
+
+// Builds a scene on a display, or says why it couldn't
+pub type SceneBuilder = fn(&dyn Backend, &SceneParams) -> Result<Box<dyn Scene>, Box<dyn Error>>;
+
+///
+/// Scenes by name, in the order they were registered.
+/// Scenes are only built when they're switched to, so the ones not
+/// being shown hold no GPU buffers.
+///
+pub struct SceneRegistry {
+    scenes: Vec<(&'static str, SceneBuilder)>,
+}
+
+impl Default for SceneRegistry {
+    fn default() -> Self {
+        let mut registry = SceneRegistry::new();
+        registry.register("gol", |display, params| {
+            let rule = starting_rule(params);
+            if params.topology == Topology::Unbounded && rule.births(0) {
+                return Err(format!(
+                    "an unbounded grid can't run {}, every empty cell would be born",
+                    rule
+                )
+                .into());
+            }
+            let mut gol = GameOfLife::new(
+                display,
+                params.grid_width,
+                params.grid_height,
+                params.topology,
+                params.rule,
+            );
+            start_gol(&mut gol, params);
+            Ok(Box::new(gol))
+        });
+        registry.register("hashlife", |display, params| {
+            let rule = starting_rule(params);
+            if rule.states() != 2 || rule.births(0) {
+                return Err(format!(
+                    "HashLife can't run {}, only two state rules without B0",
+                    rule
+                )
+                .into());
+            }
+            let mut gol =
+                GameOfLife::hashlife(display, params.grid_width, params.grid_height, rule);
+            start_gol(&mut gol, params);
+            Ok(Box::new(gol))
+        });
+        registry.register("boids", |display, params| {
+            Ok(Box::new(Boids::default(display, params.boids)))
+        });
+        registry.register("boids3d", |display, params| {
+            Ok(Box::new(Boids::volume(display, params.boids, 1.0)))
+        });
+        registry.register("boids-obstacles", |display, params| {
+            Ok(Box::new(Boids::obstacle_course(display, params.boids)))
+        });
+        registry.register("boids-gpu", |backend, params| {
+            let display = backend
+                .display()
+                .ok_or("the GPU boids can't run without a display")?;
+            Ok(Box::new(GpuBoids::default(display, params.boids)?))
+        });
+        registry.register("landscape", |display, _| {
+            Ok(Box::new(Landscape::default(display)))
+        });
+        registry.register("planets", |display, _| {
+            Ok(Box::new(Planets::default(display)))
+        });
+        registry.register("model", |display, params| {
+            let viewer = match &params.model {
+                Some(path) => ModelViewer::load(display, path)?,
+                None => ModelViewer::primitives(display),
+            };
+            Ok(Box::new(viewer))
+        });
+        registry
+    }
+}
+
+impl SceneRegistry {
+    pub fn new() -> Self {
+        SceneRegistry { scenes: vec![] }
+    }
+
+    // Registering a name again replaces its builder but keeps its place
+    pub fn register(&mut self, name: &'static str, builder: SceneBuilder) {
+        match self.scenes.iter_mut().find(|(n, _)| *n == name) {
+            Some(scene) => scene.1 = builder,
+            None => self.scenes.push((name, builder)),
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.scenes.iter().map(|&(name, _)| name)
+    }
+
+    pub fn name(&self, index: usize) -> Option<&'static str> {
+        self.scenes.get(index).map(|&(name, _)| name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.scenes.iter().any(|&(n, _)| n == name)
+    }
+
+    pub fn build(
+        &self,
+        name: &str,
+        display: &dyn Backend,
+        params: &SceneParams,
+    ) -> Result<Box<dyn Scene>, Box<dyn Error>> {
+        match self.scenes.iter().find(|&&(n, _)| n == name) {
+            Some((_, builder)) => builder(display, params),
+            None => Err(format!("no scene called '{}'", name).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::bufferable::Headless;
+
+    fn build_gol(topology: Topology, rule: &str) -> Result<Box<dyn Scene>, Box<dyn Error>> {
+        let params = SceneParams {
+            grid_width: 16,
+            grid_height: 16,
+            rule: rule.parse().unwrap(),
+            topology,
+            ..SceneParams::default()
+        };
+        SceneRegistry::default().build("gol", &Headless, &params)
+    }
+
+    #[test]
+    fn b0_rules_only_run_on_grids_with_edges() {
+        assert!(build_gol(Topology::Unbounded, "B3/S23").is_ok());
+        assert!(build_gol(Topology::Torus, "B03/S23").is_ok());
+        let error = build_gol(Topology::Unbounded, "B03/S23").err().unwrap();
+        assert!(error.to_string().contains("B03/S23"), "{}", error);
+    }
+
+    #[test]
+    fn a_patterns_own_rule_is_checked_too() {
+        let mut pattern = Pattern::new(1, 1);
+        pattern.rule = Some("B0/S8".parse().unwrap());
+        let params = SceneParams {
+            topology: Topology::Unbounded,
+            pattern: Some(Box::new(pattern)),
+            ..SceneParams::default()
+        };
+        assert!(SceneRegistry::default()
+            .build("gol", &Headless, &params)
+            .is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_TICK_RATE: f64 = 60.0;
+pub const DEFAULT_MAX_CATCH_UP: u32 = 5;
+// Slower than this and a tick outlasts any sensible run, faster and it rounds
+// down towards a zero-length tick
+pub const MIN_TICK_RATE: f64 = 0.001;
+pub const MAX_TICK_RATE: f64 = 10_000.0;
+
+///
+/// Fixed timestep clock: real time is banked in an accumulator and spent in
+/// whole ticks, so simulations advance at the same rate whatever the frame rate.
+/// What's left over after the last tick becomes the interpolation alpha.
+///
+pub struct Timestep {
+    tick: Duration,
+    // Most ticks run for one frame, the rest of a long stall is dropped
+    max_catch_up: u32,
+    time_scale: f64,
+    paused: bool,
+    step_requested: bool,
+    accumulator: Duration,
+    last: Option<Instant>,
+    // Real time between the last two calls to advance, whether paused or not
+    frame: Duration,
+}
+
+impl Default for Timestep {
+    fn default() -> Self {
+        Self::new(DEFAULT_TICK_RATE, DEFAULT_MAX_CATCH_UP)
+    }
+}
+
+impl Timestep {
+    pub fn new(tick_rate: f64, max_catch_up: u32) -> Self {
+        assert!(
+            (MIN_TICK_RATE..=MAX_TICK_RATE).contains(&tick_rate),
+            "tick rate must be between {} and {} Hz",
+            MIN_TICK_RATE,
+            MAX_TICK_RATE
+        );
+        assert!(max_catch_up > 0, "at least one tick has to run per frame");
+        Timestep {
+            tick: Duration::from_secs_f64(1.0 / tick_rate),
+            max_catch_up,
+            time_scale: 1.0,
+            paused: false,
+            step_requested: false,
+            accumulator: Duration::ZERO,
+            last: None,
+            frame: Duration::ZERO,
+        }
+    }
+
+    ///
+    /// Banks the time since the last call and returns how many ticks to run.
+    /// While paused nothing is banked, but a requested single step still runs.
+    ///
+    pub fn advance(&mut self, now: Instant) -> u32 {
+        let elapsed = self.last.map_or(Duration::ZERO, |last| now - last);
+        self.last = Some(now);
+        self.frame = elapsed;
+        if self.paused {
+            return std::mem::take(&mut self.step_requested) as u32;
+        }
+
+        self.accumulator += elapsed.mul_f64(self.time_scale);
+        let mut ticks = 0;
+        while self.accumulator >= self.tick && ticks < self.max_catch_up {
+            self.accumulator -= self.tick;
+            ticks += 1;
+        }
+        // Too far behind to catch up, so give up on the backlog rather than fall further behind
+        if self.accumulator >= self.tick {
+            self.accumulator = Duration::ZERO;
+        }
+        ticks
+    }
+
+    // How far between the last tick and the next one the current frame is, from 0 to 1.
+    // Paused frames show the latest tick as it is
+    pub fn alpha(&self) -> f32 {
+        if self.paused {
+            return 1.0;
+        }
+        (self.accumulator.as_secs_f64() / self.tick.as_secs_f64()).min(1.0) as f32
+    }
+
+    // Unscaled, for things like cameras that move in real time
+    pub fn frame_time(&self) -> Duration {
+        self.frame
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.step_requested = false;
+    }
+
+    // Runs exactly one tick on the next frame, only while paused
+    pub fn step_once(&mut self) {
+        if self.paused {
+            self.step_requested = true;
+        }
+    }
+
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        assert!(time_scale > 0.0, "time scale must be positive");
+        self.time_scale = time_scale;
+    }
+}
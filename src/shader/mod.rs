@@ -0,0 +1,464 @@
+pub(crate) mod preprocess;
+
+use self::preprocess::{Expanded, SourceLine};
+use glium::program::{ProgramCreationError, SourceCode};
+use glium::{Display, Program};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display as Disp, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io(PathBuf, io::Error),
+    // An #include that couldn't be followed, and the line it's on
+    Include {
+        at: SourceLine,
+        message: String,
+    },
+    Compile {
+        program: String,
+        stage: &'static str,
+        log: String,
+        // The lines the log points at, traced back through the includes
+        lines: Vec<SourceLine>,
+    },
+    Link {
+        program: String,
+        log: String,
+    },
+    // Anything else the driver refused, like a stage it doesn't support
+    Unsupported {
+        program: String,
+        reason: String,
+    },
+}
+
+impl Disp for ShaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShaderError::Io(path, e) => write!(f, "couldn't read '{}': {}", path.display(), e),
+            ShaderError::Include { at, message } => write!(f, "{}\n  at {}", message, at),
+            ShaderError::Compile {
+                program,
+                stage,
+                log,
+                lines,
+            } => {
+                write!(
+                    f,
+                    "couldn't compile the {} shader of '{}':\n{}",
+                    stage,
+                    program,
+                    log.trim_end()
+                )?;
+                for line in lines {
+                    write!(f, "\n  at {}", line)?;
+                }
+                Ok(())
+            }
+            ShaderError::Link { program, log } => {
+                write!(f, "couldn't link '{}':\n{}", program, log.trim_end())
+            }
+            ShaderError::Unsupported { program, reason } => {
+                write!(f, "couldn't build '{}': {}", program, reason)
+            }
+        }
+    }
+}
+
+impl Error for ShaderError {}
+
+// Where the text of one shader stage comes from
+#[derive(Clone, Debug)]
+pub enum Source {
+    Text(String),
+    File(PathBuf),
+}
+
+///
+/// The shaders that make up a program, plus defines for just this program.
+/// Geometry shaders are optional.
+///
+#[derive(Clone, Debug)]
+pub struct ProgramSource {
+    pub vertex: Source,
+    pub fragment: Source,
+    pub geometry: Option<Source>,
+    pub defines: Vec<(String, String)>,
+}
+
+impl ProgramSource {
+    pub fn new(vertex: &str, fragment: &str) -> Self {
+        ProgramSource {
+            vertex: Source::Text(vertex.to_string()),
+            fragment: Source::Text(fragment.to_string()),
+            geometry: None,
+            defines: vec![],
+        }
+    }
+
+    pub fn from_files<P: Into<PathBuf>>(vertex: P, fragment: P) -> Self {
+        ProgramSource {
+            vertex: Source::File(vertex.into()),
+            fragment: Source::File(fragment.into()),
+            geometry: None,
+            defines: vec![],
+        }
+    }
+
+    pub fn with_geometry(self, geometry: Source) -> Self {
+        ProgramSource {
+            geometry: Some(geometry),
+            ..self
+        }
+    }
+
+    // Adds `#define name value` to every stage
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+// Picks a program out of a library, only meaningful to the library that gave it out
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ProgramHandle(usize);
+
+impl ProgramHandle {
+    // The engine's own program, always the first one added
+    pub const BASE: ProgramHandle = ProgramHandle(0);
+}
+
+// How often the files behind the programs are checked for changes
+pub const RELOAD_INTERVAL: Duration = Duration::from_millis(250);
+
+// A file a program was built from and when it was last changed, None if it wasn't there
+#[derive(Clone, Debug)]
+struct Watched {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl Watched {
+    fn new(path: PathBuf) -> Self {
+        let modified = modified(&path);
+        Watched { path, modified }
+    }
+
+    fn changed(&self) -> bool {
+        modified(&self.path) != self.modified
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+struct Entry {
+    name: String,
+    // What the program was last asked to be built from, which might not have built
+    source: ProgramSource,
+    // The last program that built
+    program: Program,
+    files: Vec<Watched>,
+}
+
+// A program from disk that's never built, so there's nothing to draw with yet
+struct Pending {
+    name: String,
+    source: ProgramSource,
+    files: Vec<Watched>,
+}
+
+///
+/// Every program the engine can draw with, by name.
+/// Sources are put through `#include` and define expansion before compiling,
+/// and kept so programs can be rebuilt. Drawables hold handles rather than programs.
+/// Programs built from files are rebuilt when the files change; if that fails
+/// the last program that worked stays in use until the files are fixed.
+///
+pub struct ProgramLibrary {
+    display: Display,
+    programs: Vec<Entry>,
+    pending: Vec<Pending>,
+    // Snippets `#include "name"` falls back to when there's no file by that name
+    includes: HashMap<String, String>,
+    // Added to every program built after they're set
+    defines: Vec<(String, String)>,
+    last_reload: Option<Instant>,
+}
+
+impl ProgramLibrary {
+    pub fn new(display: &Display) -> Self {
+        ProgramLibrary {
+            display: display.clone(),
+            programs: vec![],
+            pending: vec![],
+            includes: HashMap::new(),
+            defines: vec![],
+            last_reload: None,
+        }
+    }
+
+    pub fn add_include(&mut self, name: &str, text: &str) {
+        self.includes.insert(name.to_string(), text.to_string());
+    }
+
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.defines.retain(|(n, _)| n != name);
+        self.defines.push((name.to_string(), value.to_string()));
+    }
+
+    ///
+    /// Builds a program and gives back its handle.
+    /// Adding a name again rebuilds it under the same handle.
+    /// If the new source doesn't build, the old program is kept and its files
+    /// are still watched, so fixing them is picked up by `reload_changed`.
+    ///
+    pub fn add(&mut self, name: &str, source: ProgramSource) -> Result<ProgramHandle, ShaderError> {
+        let mut files = vec![];
+        let built = self.build(name, &source, &mut files);
+        let files: Vec<Watched> = files.into_iter().map(Watched::new).collect();
+        self.pending.retain(|pending| pending.name != name);
+        let handle = self.handle(name);
+        let program = match (built, handle) {
+            (Ok(program), _) => program,
+            (Err(err), Some(handle)) => {
+                let entry = &mut self.programs[handle.0];
+                entry.source = source;
+                entry.files = files;
+                return Err(err);
+            }
+            (Err(err), None) => {
+                if !files.is_empty() {
+                    self.pending.push(Pending {
+                        name: name.to_string(),
+                        source,
+                        files,
+                    });
+                }
+                return Err(err);
+            }
+        };
+        let entry = Entry {
+            name: name.to_string(),
+            source,
+            program,
+            files,
+        };
+        match handle {
+            Some(handle) => {
+                self.programs[handle.0] = entry;
+                Ok(handle)
+            }
+            None => {
+                self.programs.push(entry);
+                Ok(ProgramHandle(self.programs.len() - 1))
+            }
+        }
+    }
+
+    ///
+    /// The program already called `name` if there is one, so shaders loaded from disk
+    /// win over built in ones. If the ones on disk haven't built yet, `source` is used
+    /// until they do, and the files are still watched.
+    ///
+    pub fn handle_or_add(
+        &mut self,
+        name: &str,
+        source: ProgramSource,
+    ) -> Result<ProgramHandle, ShaderError> {
+        if let Some(handle) = self.handle(name) {
+            return Ok(handle);
+        }
+        let pending = self
+            .pending
+            .iter()
+            .position(|p| p.name == name)
+            .map(|index| self.pending.remove(index));
+        let added = self.add(name, source);
+        if let Some(pending) = pending {
+            match added {
+                Ok(handle) => {
+                    let entry = &mut self.programs[handle.0];
+                    entry.source = pending.source;
+                    entry.files = pending.files;
+                }
+                Err(_) => self.pending.push(pending),
+            }
+        }
+        added
+    }
+
+    ///
+    /// Adds a program for every `NAME.vert` in `dir` with a `NAME.frag` beside it,
+    /// and `NAME.geom` too if it's there. A program that doesn't build is reported
+    /// rather than stopping the rest, and is still watched for changes.
+    ///
+    pub fn load_dir(&mut self, dir: &Path) -> io::Result<Vec<ShaderError>> {
+        let mut names = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "vert") {
+                if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+
+        let mut errors = vec![];
+        for name in names {
+            let fragment = dir.join(format!("{}.frag", name));
+            if !fragment.is_file() {
+                continue;
+            }
+            let mut source =
+                ProgramSource::from_files(dir.join(format!("{}.vert", name)), fragment);
+            let geometry = dir.join(format!("{}.geom", name));
+            if geometry.is_file() {
+                source = source.with_geometry(Source::File(geometry));
+            }
+            if let Err(err) = self.add(&name, source) {
+                errors.push(err);
+            }
+        }
+        Ok(errors)
+    }
+
+    ///
+    /// Rebuilds every program whose files have changed since it was last built,
+    /// checking at most once every `RELOAD_INTERVAL`. Gives back the name of each
+    /// program it tried and how that went.
+    ///
+    pub fn reload_changed(&mut self, now: Instant) -> Vec<(String, Result<(), ShaderError>)> {
+        if let Some(last) = self.last_reload {
+            if now.duration_since(last) < RELOAD_INTERVAL {
+                return vec![];
+            }
+        }
+        self.last_reload = Some(now);
+
+        let mut changed: Vec<(String, ProgramSource)> = self
+            .programs
+            .iter()
+            .filter(|entry| entry.files.iter().any(Watched::changed))
+            .map(|entry| (entry.name.clone(), entry.source.clone()))
+            .collect();
+        changed.extend(
+            self.pending
+                .iter()
+                .filter(|pending| pending.files.iter().any(Watched::changed))
+                .map(|pending| (pending.name.clone(), pending.source.clone())),
+        );
+        changed
+            .into_iter()
+            .map(|(name, source)| {
+                let result = self.add(&name, source).map(|_| ());
+                (name, result)
+            })
+            .collect()
+    }
+
+    pub fn handle(&self, name: &str) -> Option<ProgramHandle> {
+        self.programs
+            .iter()
+            .position(|entry| entry.name == name)
+            .map(ProgramHandle)
+    }
+
+    pub fn get(&self, handle: ProgramHandle) -> &Program {
+        &self.programs[handle.0].program
+    }
+
+    fn build(
+        &self,
+        name: &str,
+        source: &ProgramSource,
+        files: &mut Vec<PathBuf>,
+    ) -> Result<Program, ShaderError> {
+        let vertex = self.expand(name, "vert", &source.vertex, &source.defines, files)?;
+        let fragment = self.expand(name, "frag", &source.fragment, &source.defines, files)?;
+        let geometry = match source.geometry {
+            Some(ref geometry) => {
+                Some(self.expand(name, "geom", geometry, &source.defines, files)?)
+            }
+            None => None,
+        };
+        let code = SourceCode {
+            vertex_shader: &vertex.text,
+            tessellation_control_shader: None,
+            tessellation_evaluation_shader: None,
+            geometry_shader: geometry.as_ref().map(|g| g.text.as_str()),
+            fragment_shader: &fragment.text,
+        };
+        Program::new(&self.display, code)
+            .map_err(|err| creation_error(name, err, &vertex, &fragment, geometry.as_ref()))
+    }
+
+    // A program's own defines win over the library's, GLSL won't take a name defined twice
+    fn expand(
+        &self,
+        name: &str,
+        extension: &str,
+        source: &Source,
+        defines: &[(String, String)],
+        files: &mut Vec<PathBuf>,
+    ) -> Result<Expanded, ShaderError> {
+        let mut merged: Vec<(String, String)> = self
+            .defines
+            .iter()
+            .filter(|(n, _)| defines.iter().all(|(other, _)| other != n))
+            .cloned()
+            .collect();
+        merged.extend(defines.iter().cloned());
+        match source {
+            Source::Text(text) => preprocess::expand(
+                text,
+                &format!("{}.{}", name, extension),
+                None,
+                &merged,
+                &self.includes,
+                files,
+            ),
+            Source::File(path) => preprocess::expand_file(path, &merged, &self.includes, files),
+        }
+    }
+}
+
+fn creation_error(
+    program: &str,
+    err: ProgramCreationError,
+    vertex: &Expanded,
+    fragment: &Expanded,
+    geometry: Option<&Expanded>,
+) -> ShaderError {
+    use glium::program::ShaderType;
+    let program = program.to_string();
+    match err {
+        ProgramCreationError::CompilationError(log, stage) => {
+            let (stage, expanded) = match stage {
+                ShaderType::Vertex => ("vertex", Some(vertex)),
+                ShaderType::Fragment => ("fragment", Some(fragment)),
+                ShaderType::Geometry => ("geometry", geometry),
+                _ => ("unknown", None),
+            };
+            let lines = expanded.map_or_else(Vec::new, |e| e.lines_in_log(&log));
+            ShaderError::Compile {
+                program,
+                stage,
+                log,
+                lines,
+            }
+        }
+        ProgramCreationError::LinkingError(log) => ShaderError::Link { program, log },
+        other => ShaderError::Unsupported {
+            program,
+            reason: other.to_string(),
+        },
+    }
+}
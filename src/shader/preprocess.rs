@@ -0,0 +1,405 @@
+use shader::ShaderError;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// Where one line of an expanded shader came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLine {
+    pub file: String,
+    // Counted from 1, like compilers do
+    pub line: usize,
+    pub text: String,
+}
+
+impl Display for SourceLine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.text.trim())
+    }
+}
+
+///
+/// A shader with its `#include`s pasted in and the defines added after `#version`.
+/// Every line remembers which file and line it came from, so the line numbers
+/// in the driver's error log can be traced back to what was written.
+///
+#[derive(Clone, Debug, Default)]
+pub struct Expanded {
+    pub text: String,
+    origins: Vec<(Rc<str>, usize)>,
+}
+
+impl Expanded {
+    // The line the compiler calls `line`, counting from 1
+    pub fn origin(&self, line: usize) -> Option<SourceLine> {
+        let &(ref file, number) = self.origins.get(line.checked_sub(1)?)?;
+        let text = self.text.lines().nth(line - 1).unwrap_or("").to_string();
+        Some(SourceLine {
+            file: file.to_string(),
+            line: number,
+            text,
+        })
+    }
+
+    // The lines a compile log complains about, in the order it mentions them
+    pub fn lines_in_log(&self, log: &str) -> Vec<SourceLine> {
+        let mut lines: Vec<SourceLine> = vec![];
+        for number in log.lines().filter_map(log_line_number) {
+            if let Some(line) = self.origin(number) {
+                if !lines.contains(&line) {
+                    lines.push(line);
+                }
+            }
+        }
+        lines
+    }
+
+    fn push(&mut self, line: &str, file: &Rc<str>, number: usize) {
+        self.text.push_str(line);
+        self.text.push('\n');
+        self.origins.push((file.clone(), number));
+    }
+}
+
+///
+/// Expands `text`, which came from `file`.
+/// Includes are paths relative to the directory of the file doing the including,
+/// falling back to the snippet of that name in `includes` if there's no such file.
+/// Every path tried is added to `files`, whether it could be read or not,
+/// so a broken shader can still be watched for the fix.
+///
+pub fn expand(
+    text: &str,
+    file: &str,
+    dir: Option<&Path>,
+    defines: &[(String, String)],
+    includes: &HashMap<String, String>,
+    files: &mut Vec<PathBuf>,
+) -> Result<Expanded, ShaderError> {
+    let mut expanded = Expanded::default();
+    let mut stack = vec![file.to_string()];
+    let mut context = Context {
+        includes,
+        stack: &mut stack,
+        files,
+    };
+    expand_into(&mut expanded, text, &Rc::from(file), dir, &mut context)?;
+    inject_defines(&mut expanded, defines);
+    Ok(expanded)
+}
+
+// Reads a shader from disk and expands it
+pub fn expand_file(
+    path: &Path,
+    defines: &[(String, String)],
+    includes: &HashMap<String, String>,
+    files: &mut Vec<PathBuf>,
+) -> Result<Expanded, ShaderError> {
+    add_file(files, path);
+    let text = fs::read_to_string(path).map_err(|e| ShaderError::Io(path.to_path_buf(), e))?;
+    let file = path.display().to_string();
+    expand(&text, &file, path.parent(), defines, includes, files)
+}
+
+// What stays the same all the way down the includes
+struct Context<'a> {
+    includes: &'a HashMap<String, String>,
+    // The files being expanded, outermost first, to catch includes going round in circles
+    stack: &'a mut Vec<String>,
+    files: &'a mut Vec<PathBuf>,
+}
+
+fn add_file(files: &mut Vec<PathBuf>, path: &Path) {
+    if !files.iter().any(|f| f == path) {
+        files.push(path.to_path_buf());
+    }
+}
+
+fn expand_into(
+    expanded: &mut Expanded,
+    text: &str,
+    file: &Rc<str>,
+    dir: Option<&Path>,
+    context: &mut Context,
+) -> Result<(), ShaderError> {
+    for (index, line) in text.lines().enumerate() {
+        let directive = line.trim_start();
+        if !directive.starts_with("#include") {
+            expanded.push(line, file, index + 1);
+            continue;
+        }
+        let at = SourceLine {
+            file: file.to_string(),
+            line: index + 1,
+            text: line.to_string(),
+        };
+        let include_error = |message: String| ShaderError::Include {
+            at: at.clone(),
+            message,
+        };
+        let name = include_name(&directive["#include".len()..])
+            .ok_or_else(|| include_error("expected a name in quotes or <>".to_string()))?;
+
+        // The path is watched even when a snippet stands in, so adding the file is noticed
+        let path = dir.unwrap_or_else(|| Path::new(".")).join(name);
+        add_file(context.files, &path);
+        let (text, path) = match (fs::read_to_string(&path), context.includes.get(name)) {
+            (Ok(text), _) => (text, Some(path)),
+            (Err(ref e), Some(text)) if e.kind() == ErrorKind::NotFound => (text.clone(), None),
+            (Err(e), _) => {
+                return Err(include_error(format!("couldn't read '{}': {}", name, e)));
+            }
+        };
+        let include = match path {
+            Some(ref path) => path.display().to_string(),
+            None => name.to_string(),
+        };
+        if context.stack.contains(&include) {
+            return Err(include_error(format!(
+                "'{}' ends up including itself: {} -> {}",
+                name,
+                context.stack.join(" -> "),
+                include
+            )));
+        }
+
+        context.stack.push(include.clone());
+        let include_dir = path.as_ref().and_then(|p| p.parent()).or(dir);
+        let include = Rc::from(include.as_str());
+        expand_into(expanded, &text, &include, include_dir, context)?;
+        context.stack.pop();
+    }
+    Ok(())
+}
+
+// The name in `"name"` or `<name>`
+fn include_name(rest: &str) -> Option<&str> {
+    let rest = rest.trim();
+    let close = match rest.chars().next()? {
+        '"' => '"',
+        '<' => '>',
+        _ => return None,
+    };
+    let end = rest[1..].find(close)? + 1;
+    let name = &rest[1..end];
+    if name.is_empty() || !rest[end + 1..].trim().is_empty() {
+        return None;
+    }
+    Some(name)
+}
+
+// `#version` has to come first, so the defines go straight after it
+fn inject_defines(expanded: &mut Expanded, defines: &[(String, String)]) {
+    if defines.is_empty() {
+        return;
+    }
+    let mut lines: Vec<&str> = expanded.text.lines().collect();
+    let at = lines
+        .iter()
+        .position(|line| line.trim_start().starts_with("#version"))
+        .map_or(0, |version| version + 1);
+    let define_lines: Vec<String> = defines
+        .iter()
+        .map(|(name, value)| format!("#define {} {}", name, value).trim_end().to_string())
+        .collect();
+    lines.splice(at..at, define_lines.iter().map(|line| line.as_str()));
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    let file: Rc<str> = Rc::from("<defines>");
+    let origins = (1..=defines.len()).map(|number| (file.clone(), number));
+    expanded.origins.splice(at..at, origins);
+    expanded.text = text;
+}
+
+///
+/// The line number in one line of a compile log.
+/// Drivers write it differently: `0(12) : error` from NVIDIA, `0:12(5): error`
+/// from Mesa and `ERROR: 0:12: ...` from AMD and Intel, the 0 being the source string.
+///
+fn log_line_number(log: &str) -> Option<usize> {
+    let bytes = log.as_bytes();
+    for (i, window) in bytes.windows(2).enumerate() {
+        let starts_number = window[0] == b'0' && (window[1] == b'(' || window[1] == b':');
+        if !starts_number || (i > 0 && bytes[i - 1].is_ascii_digit()) {
+            continue;
+        }
+        let digits: String = log[i + 2..]
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        let after = log[i + 2 + digits.len()..].chars().next();
+        if !digits.is_empty() && matches!(after, Some(')') | Some(':') | Some('(')) {
+            return digits.parse().ok();
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn snippets(snippets: &[(&str, &str)]) -> HashMap<String, String> {
+        snippets
+            .iter()
+            .map(|&(name, text)| (name.to_string(), text.to_string()))
+            .collect()
+    }
+
+    fn defines(defines: &[(&str, &str)]) -> Vec<(String, String)> {
+        defines
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    // Expands with the includes as snippets, there being no such files
+    fn expand_with(
+        text: &str,
+        defines: &[(String, String)],
+        includes: &HashMap<String, String>,
+    ) -> Result<Expanded, ShaderError> {
+        expand(text, "main.frag", None, defines, includes, &mut vec![])
+    }
+
+    fn origin(expanded: &Expanded, line: usize) -> (String, usize) {
+        let origin = expanded.origin(line).unwrap();
+        (origin.file, origin.line)
+    }
+
+    #[test]
+    fn defines_go_after_the_version() {
+        let text = "// comment\n#version 330\nvoid main() {}\n";
+        let defines = defines(&[("LIT", ""), ("COUNT", "4")]);
+        let expanded = expand_with(text, &defines, &HashMap::new()).unwrap();
+        assert_eq!(
+            expanded.text,
+            "// comment\n#version 330\n#define LIT\n#define COUNT 4\nvoid main() {}\n"
+        );
+        assert_eq!(origin(&expanded, 2), ("main.frag".to_string(), 2));
+        assert_eq!(origin(&expanded, 4), ("<defines>".to_string(), 2));
+        assert_eq!(origin(&expanded, 5), ("main.frag".to_string(), 3));
+        assert_eq!(expanded.origin(6), None);
+        assert_eq!(expanded.origin(0), None);
+
+        // Without a version they go first
+        let expanded = expand_with("void main() {}\n", &defines, &HashMap::new()).unwrap();
+        assert_eq!(
+            expanded.text,
+            "#define LIT\n#define COUNT 4\nvoid main() {}\n"
+        );
+    }
+
+    #[test]
+    fn both_kinds_of_include_name() {
+        let includes = snippets(&[("light.glsl", "float light;"), ("fog.glsl", "float fog;")]);
+        let text = "#include \"light.glsl\"\n  #include <fog.glsl>  \n";
+        let expanded = expand_with(text, &[], &includes).unwrap();
+        assert_eq!(expanded.text, "float light;\nfloat fog;\n");
+
+        assert_eq!(include_name(" \"a.glsl\""), Some("a.glsl"));
+        assert_eq!(include_name(" <dir/a.glsl> "), Some("dir/a.glsl"));
+        for bad in &[
+            " a.glsl",
+            " \"a.glsl",
+            " <a.glsl\"",
+            " \"\"",
+            " \"a\" b",
+            "",
+        ] {
+            assert_eq!(include_name(bad), None, "{}", bad);
+        }
+        match expand_with("\n#include light.glsl\n", &[], &includes) {
+            Err(ShaderError::Include { at, .. }) => assert_eq!(at.line, 2),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn compile_log_lines_map_back_through_includes() {
+        let includes = snippets(&[("light.glsl", "float a;\nfloat b = oops;\n")]);
+        let text = "#version 330\n#include \"light.glsl\"\nvoid main() { x; }\n";
+        let expanded = expand_with(text, &defines(&[("LIT", "")]), &includes).unwrap();
+        // The version, the define, the two included lines and main
+        let log = "0(4) : error C1008: undefined variable \"oops\"\n\
+                   0:5(15): error: `x' undeclared\n\
+                   ERROR: 0:4: 'oops' : undeclared identifier\n\
+                   ERROR: 2 compilation errors.\n";
+        let lines = expanded.lines_in_log(log);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].to_string(), "light.glsl:2: float b = oops;");
+        assert_eq!(lines[1].to_string(), "main.frag:3: void main() { x; }");
+    }
+
+    #[test]
+    fn log_line_numbers_in_each_drivers_format() {
+        assert_eq!(
+            log_line_number("0(12) : error C0000: syntax error"),
+            Some(12)
+        );
+        assert_eq!(log_line_number("0:7(5): error: syntax error"), Some(7));
+        assert_eq!(log_line_number("ERROR: 0:31: 'x' : undeclared"), Some(31));
+        assert_eq!(log_line_number("ERROR: 2 compilation errors."), None);
+        // A number ending in 0 isn't the source string
+        assert_eq!(log_line_number("error 10(4): nope"), None);
+        assert_eq!(log_line_number("0:x: nope"), None);
+    }
+
+    #[test]
+    fn includes_that_go_round_in_circles_are_errors() {
+        let includes = snippets(&[
+            ("a.glsl", "float a;\n#include \"b.glsl\"\n"),
+            ("b.glsl", "#include <a.glsl>\n"),
+        ]);
+        match expand_with("#include \"a.glsl\"\n", &[], &includes) {
+            Err(ShaderError::Include { at, message }) => {
+                assert_eq!((at.file.as_str(), at.line), ("b.glsl", 1));
+                assert!(
+                    message.contains("main.frag -> a.glsl -> b.glsl -> a.glsl"),
+                    "{}",
+                    message
+                );
+            }
+            other => panic!("{:?}", other),
+        }
+        let includes = snippets(&[("self.glsl", "#include \"self.glsl\"\n")]);
+        assert!(matches!(
+            expand_with("#include \"self.glsl\"\n", &[], &includes),
+            Err(ShaderError::Include { .. })
+        ));
+    }
+
+    #[test]
+    fn includes_are_found_next_to_the_file_including_them() {
+        let dir = env::temp_dir().join(format!("preprocess-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("main.frag"), "#include \"lib/light.glsl\"\nmain\n").unwrap();
+        fs::write(dir.join("lib/light.glsl"), "light\n#include <fog.glsl>\n").unwrap();
+        fs::write(dir.join("lib/fog.glsl"), "fog\n#include \"common.glsl\"\n").unwrap();
+        let includes = snippets(&[("common.glsl", "common"), ("fog.glsl", "unused")]);
+        let mut files = vec![];
+        let expanded = expand_file(&dir.join("main.frag"), &[], &includes, &mut files);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // A file beats a snippet with the same name, which stands in where there's no file
+        let expanded = expanded.unwrap();
+        assert_eq!(expanded.text, "light\nfog\ncommon\nmain\n");
+        let fog = dir.join("lib").join("fog.glsl").display().to_string();
+        assert_eq!(origin(&expanded, 2), (fog, 1));
+        assert_eq!(origin(&expanded, 3), ("common.glsl".to_string(), 1));
+        // Every path is watched, including the one that wasn't there
+        let watched = [
+            "main.frag",
+            "lib/light.glsl",
+            "lib/fog.glsl",
+            "lib/common.glsl",
+        ];
+        let watched: Vec<PathBuf> = watched.iter().map(|file| dir.join(file)).collect();
+        assert_eq!(files, watched);
+    }
+}
@@ -1,167 +1,185 @@
-use drawable::shape::Shape;
-use rand::{thread_rng, Rng};
+use math::{Mat3, Quaternion, Transform, Vec3};
+use rand::Rng;
 use std::ops;
-use util::vertex::F32vec3;
+use util::random;
 use util::Manipulate;
 
+///
+/// Per-instance data as the shaders read it, a model matrix and a colour.
+/// Built from an `Instance` whenever its buffer is uploaded.
+///
 #[derive(Copy, Clone, Debug)]
 pub struct Attr {
-    pub world_position: [f32; 3],
-    pub rotation_matrix: [[f32; 4]; 4],
-    pub color: [f32; 4],
+    model: [[f32; 4]; 4],
+    color: [f32; 4],
 }
-glium::implement_vertex!(Attr, world_position, rotation_matrix, color);
+glium::implement_vertex!(Attr, model, color);
 
-impl Default for Attr {
-    fn default() -> Self {
-        let world_position = [0.0; 3];
-        let rotation_matrix = [
-            [1.0, 0.0, 0.0, 0.0],
-            [0.0, 1.0, 0.0, 0.0],
-            [0.0, 0.0, 1.0, 0.0],
-            [0.0, 0.0, 0.0, 1.0],
-        ];
-        let color = [1.0, 0.0, 0.0, 1.0];
+impl From<&Instance> for Attr {
+    fn from(instance: &Instance) -> Self {
         Attr {
-            world_position,
-            rotation_matrix,
-            color,
+            model: instance.transform.matrix().into(),
+            color: instance.color,
         }
     }
 }
 
-impl From<&[f32; 3]> for Attr {
-    fn from(value: &[f32; 3]) -> Self {
-        let rotation_matrix = [
-            [1.0, 0.0, 0.0, 0.0],
-            [0.0, 1.0, 0.0, 0.0],
-            [0.0, 0.0, 1.0, 0.0],
-            [0.0, 0.0, 0.0, 1.0],
-        ];
-        let color = [1.0, 0.0, 0.0, 1.0];
-        Attr {
-            world_position: *value,
-            rotation_matrix,
-            color,
-        }
+///
+/// One instance on the CPU side. The transform stays here and only
+/// the matrix made from it goes to the GPU, see `Attr`.
+///
+#[derive(Copy, Clone, Debug)]
+pub struct Instance {
+    pub transform: Transform,
+    pub color: [f32; 4],
+}
+
+impl Default for Instance {
+    fn default() -> Self {
+        Instance::from(Transform::IDENTITY)
     }
 }
 
-impl From<[f32; 3]> for Attr {
-    fn from(value: [f32; 3]) -> Self {
-        let rotation_matrix = [
-            [1.0, 0.0, 0.0, 0.0],
-            [0.0, 1.0, 0.0, 0.0],
-            [0.0, 0.0, 1.0, 0.0],
-            [0.0, 0.0, 0.0, 1.0],
-        ];
-        let color = [1.0, 0.0, 0.0, 1.0];
-        Attr {
-            world_position: value,
-            rotation_matrix,
-            color,
+impl From<Transform> for Instance {
+    fn from(transform: Transform) -> Self {
+        Instance {
+            transform,
+            color: [1.0, 0.0, 0.0, 1.0],
         }
     }
 }
 
-#[allow(dead_code)]
-impl Attr {
-    pub fn translate(&mut self, x: f32, y: f32, z: f32) {
-        self.world_position[0] += x;
-        self.world_position[1] += y;
-        self.world_position[2] += z;
+impl From<Vec3> for Instance {
+    fn from(value: Vec3) -> Self {
+        Instance::from(Transform::from_translation(value))
     }
+}
 
-    pub fn set(&mut self, x: f32, y: f32, z: f32) {
-        self.world_position[0] = x;
-        self.world_position[1] = y;
-        self.world_position[2] = z;
+impl From<&[f32; 3]> for Instance {
+    fn from(value: &[f32; 3]) -> Self {
+        Instance::from(Vec3::from(*value))
     }
+}
 
-    pub fn x(&self) -> f32 {
-        self.world_position[0]
+impl From<[f32; 3]> for Instance {
+    fn from(value: [f32; 3]) -> Self {
+        Instance::from(Vec3::from(value))
+    }
+}
+
+impl Instance {
+    pub fn position(&self) -> Vec3 {
+        self.transform.translation
     }
 
-    pub fn y(&self) -> f32 {
-        self.world_position[1]
+    pub fn set_position(&mut self, position: Vec3) {
+        self.transform.translation = position;
     }
 
-    pub fn z(&self) -> f32 {
-        self.world_position[2]
+    // Swings the instance around `point` in world space, see `Transform::rotate_about`
+    pub fn rotate_about(&mut self, point: Vec3, axis: Vec3, angle: f32) {
+        self.transform.rotate_about(point, axis, angle);
     }
 
     pub fn randomize(&mut self) {
-        self.world_position
-            .iter_mut()
-            .for_each(|p| *p = thread_rng().gen_range(-2.0..2.0));
-        self.color
-            .iter_mut()
-            .for_each(|p| *p = thread_rng().gen_range(0.0..1.0));
+        random::with_rng(|rng| {
+            let mut position = Vec3::ZERO;
+            for axis in 0..3 {
+                position[axis] = rng.gen_range(-2.0..2.0);
+            }
+            self.set_position(position);
+            self.color
+                .iter_mut()
+                .for_each(|p| *p = rng.gen_range(0.0..1.0));
+        });
     }
 
     pub fn random() -> Self {
-        let mut a = Attr::default();
+        let mut a = Instance::default();
         a.randomize();
         a
     }
-}
-
-impl Manipulate for Attr {
-    fn rotate_axis(&mut self, axis: usize, ang: f32) {
-        let cos = ang.cos();
-        let sin = ang.sin();
-        let mut tmp: [f32; 4] = [0.0; 4];
 
-        for i in 0..4 {
-            tmp[i] = self.rotation_matrix[axis][i];
+    // Rotates the instance so its local +y axis points along `direction`, keeping +z as up where possible
+    pub fn look_along(&mut self, direction: Vec3) {
+        let heading = match direction.try_normalize() {
+            Some(heading) => heading,
+            None => return,
+        };
+        let mut right = heading.cross(Vec3::Z);
+        if right.length_sq() < 1e-8 {
+            right = heading.cross(Vec3::X);
         }
+        let right = right.normalize();
+        let basis = Mat3::from_cols(right, heading, right.cross(heading));
+        self.transform.rotation = Quaternion::from_mat3(&basis);
+    }
+}
 
-        for i in 0..4 {
-            let a = tmp[i];
-            let b = self.rotation_matrix[axis ^ 1][i];
-            let c = self.rotation_matrix[axis ^ 2][i];
+impl Manipulate for Instance {
+    fn rotate(&mut self, axis: Vec3, angle: f32) {
+        self.transform.rotate(axis, angle);
+    }
+}
 
-            self.rotation_matrix[axis][i] = a * cos - b * sin;
-            self.rotation_matrix[axis ^ 1][i] = a * sin + b * cos;
-            self.rotation_matrix[axis ^ 2][i] = c;
-        }
+impl ops::Add<Vec3> for Instance {
+    type Output = Instance;
+    fn add(mut self, other: Vec3) -> Self::Output {
+        self += other;
+        self
     }
 }
 
-impl ops::Add<F32vec3> for Attr {
-    type Output = Attr;
-    fn add(self, other: F32vec3) -> Self::Output {
-        Attr::from([
-            self.x() + other.x(),
-            self.y() + other.y(),
-            self.z() + other.z(),
-        ])
+impl ops::AddAssign<Vec3> for Instance {
+    fn add_assign(&mut self, other: Vec3) {
+        self.transform.translate(other);
     }
 }
 
-impl ops::AddAssign<F32vec3> for Attr {
-    fn add_assign(&mut self, other: F32vec3) {
-        self.world_position[0] += other.position[0];
-        self.world_position[1] += other.position[1];
-        self.world_position[2] += other.position[2];
+impl ops::Sub<Vec3> for Instance {
+    type Output = Instance;
+    fn sub(mut self, other: Vec3) -> Self::Output {
+        self -= other;
+        self
     }
 }
 
-impl ops::Sub<F32vec3> for Attr {
-    type Output = Attr;
-    fn sub(self, other: F32vec3) -> Self::Output {
-        Attr::from([
-            self.world_position[0] - other.position[0],
-            self.world_position[1] - other.position[1],
-            self.world_position[2] - other.position[2],
-        ])
+impl ops::SubAssign<Vec3> for Instance {
+    fn sub_assign(&mut self, other: Vec3) {
+        self.transform.translate(-other);
     }
 }
 
-impl ops::SubAssign<F32vec3> for Attr {
-    fn sub_assign(&mut self, other: F32vec3) {
-        self.world_position[0] -= other.position[0];
-        self.world_position[1] -= other.position[1];
-        self.world_position[2] -= other.position[2];
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    #[test]
+    fn many_small_rotations_dont_drift() {
+        let axis = Vec3::new(1.0, 2.0, 3.0).normalize();
+        let mut instance = Instance::from(Vec3::new(0.5, 0.0, 0.0));
+        // Ten whole turns about the axis, and ten around a point off to the side
+        for _ in 0..10000 {
+            instance.rotate(axis, TAU / 1000.0);
+        }
+        for _ in 0..10000 {
+            instance.rotate_about(Vec3::new(0.0, 1.0, 0.0), Vec3::Z, TAU / 1000.0);
+        }
+        assert!((instance.transform.rotation.length() - 1.0).abs() < 1e-5);
+        let model = Attr::from(&instance).model;
+        let expected: [[f32; 4]; 4] = Transform::from_translation(Vec3::new(0.5, 0.0, 0.0))
+            .matrix()
+            .into();
+        for (column, expected) in model.iter().zip(expected.iter()) {
+            for (a, b) in column.iter().zip(expected.iter()) {
+                assert!(
+                    (a - b).abs() < 1e-3,
+                    "{:?} drifted from {:?}",
+                    model,
+                    expected
+                );
+            }
+        }
     }
 }
@@ -1,12 +1,49 @@
 use glium::vertex::PerInstance;
 use glium::{Display, Vertex, VertexBuffer};
+use math::Vec3;
+use rayon::prelude::*;
 use std::ops::{Index, IndexMut};
 use std::slice::{Iter, IterMut};
+use util::attribute::{Attr, Instance};
 use util::Manipulate;
 
+///
+/// Where GPU buffers get made: on a display, or nowhere when a simulation
+/// runs headless and only its CPU side is needed.
+///
+pub trait Backend {
+    fn display(&self) -> Option<&Display>;
+}
+
+impl Backend for Display {
+    fn display(&self) -> Option<&Display> {
+        Some(self)
+    }
+}
+
+impl Backend for Option<Display> {
+    fn display(&self) -> Option<&Display> {
+        self.as_ref()
+    }
+}
+
+// No GL at all, buffer objects only keep their data
+pub struct Headless;
+
+impl Backend for Headless {
+    fn display(&self) -> Option<&Display> {
+        None
+    }
+}
+
+///
+/// CPU side data with an optional copy on the GPU.
+/// The data is the source of truth, `update_buffer` uploads it
+/// and does nothing when there's no buffer.
+///
 pub struct BufferObject<T: Vertex + Manipulate> {
-    data: Box<Vec<T>>,
-    buffer: Box<VertexBuffer<T>>,
+    data: Vec<T>,
+    buffer: Option<VertexBuffer<T>>,
 }
 
 // unsafe impl<T: Vertex> Send for BufferObject<T> {}
@@ -25,16 +62,10 @@ impl<T: Vertex + Manipulate> IndexMut<usize> for BufferObject<T> {
 }
 
 impl<T: Vertex + Manipulate> BufferObject<T> {
-    pub fn iter(&self) -> Iter<T> {
-        self.data.iter()
-    }
-
-    pub fn iter_mut(&mut self) -> IterMut<T> {
-        self.data.iter_mut()
-    }
-
     pub fn update_buffer(&self) {
-        self.buffer.write(&self.data);
+        if let Some(buffer) = &self.buffer {
+            buffer.write(&self.data);
+        }
     }
 
     pub fn ref_data(&self) -> &[T] {
@@ -45,20 +76,21 @@ impl<T: Vertex + Manipulate> BufferObject<T> {
         self.data.as_mut()
     }
 
+    // Only for drawing, which headless buffers can't do
     pub fn ref_vbo(&self) -> &VertexBuffer<T> {
-        &self.buffer
-    }
-
-    pub fn per_instance(&self) -> PerInstance<'_> {
-        self.buffer.per_instance().unwrap()
+        self.buffer
+            .as_ref()
+            .expect("headless buffer objects have no GPU buffer")
     }
 }
 
 pub trait Bufferable {
     type Type: Vertex + Manipulate;
-    fn new_vbo(display: &Display, vertices: &[Self::Type]) -> BufferObject<Self::Type> {
-        let data = Box::from(Vec::from(vertices));
-        let buffer = Box::from(VertexBuffer::new(display, &vertices).unwrap());
+    fn new_vbo(display: &dyn Backend, vertices: &[Self::Type]) -> BufferObject<Self::Type> {
+        let data = Vec::from(vertices);
+        let buffer = display
+            .display()
+            .map(|display| VertexBuffer::new(display, vertices).unwrap());
         BufferObject { data, buffer }
     }
 }
@@ -68,7 +100,87 @@ impl<T: Vertex + Manipulate> Bufferable for T {
 }
 
 impl<T: Vertex + Manipulate> Manipulate for BufferObject<T> {
-    fn rotate_axis(&mut self, axis: usize, ang: f32) {
-        self.data.iter_mut().for_each(|p| p.rotate_axis(axis, ang));
+    fn rotate(&mut self, axis: Vec3, angle: f32) {
+        self.data.iter_mut().for_each(|p| p.rotate(axis, angle));
+    }
+}
+
+///
+/// Instances kept on the CPU with their full transforms, uploaded as `Attr`s.
+/// As with `BufferObject` the instances are the source of truth.
+///
+pub struct InstanceBuffer {
+    data: Vec<Instance>,
+    buffer: Option<VertexBuffer<Attr>>,
+}
+
+impl Index<usize> for InstanceBuffer {
+    type Output = Instance;
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.data[index]
+    }
+}
+
+impl IndexMut<usize> for InstanceBuffer {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.data[index]
+    }
+}
+
+impl InstanceBuffer {
+    pub fn new(display: &dyn Backend, instances: &[Instance]) -> Self {
+        let buffer = display
+            .display()
+            .map(|display| VertexBuffer::new(display, &Self::attributes(instances)).unwrap());
+        InstanceBuffer {
+            data: Vec::from(instances),
+            buffer,
+        }
+    }
+
+    fn attributes(instances: &[Instance]) -> Vec<Attr> {
+        instances.par_iter().map(Attr::from).collect()
+    }
+
+    pub fn iter(&self) -> Iter<'_, Instance> {
+        self.data.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, Instance> {
+        self.data.iter_mut()
+    }
+
+    pub fn update_buffer(&self) {
+        self.write(&self.data);
+    }
+
+    // Uploads other instances in place of these ones, which stay as they are
+    pub fn write(&self, instances: &[Instance]) {
+        if let Some(buffer) = &self.buffer {
+            buffer.write(&Self::attributes(instances));
+        }
+    }
+
+    pub fn ref_data(&self) -> &[Instance] {
+        self.data.as_slice()
+    }
+
+    pub fn mut_data(&mut self) -> &mut [Instance] {
+        self.data.as_mut()
+    }
+
+    // Only for drawing, which headless buffers can't do
+    pub fn per_instance(&self) -> PerInstance<'_> {
+        self.buffer
+            .as_ref()
+            .expect("headless buffer objects have no GPU buffer")
+            .per_instance()
+            .unwrap()
+    }
+}
+
+impl Manipulate for InstanceBuffer {
+    fn rotate(&mut self, axis: Vec3, angle: f32) {
+        self.data.iter_mut().for_each(|p| p.rotate(axis, angle));
     }
 }
@@ -1,20 +1,239 @@
-use glium::program::ComputeShader;
-use glium::Display;
+use boids::FlockParams;
+use glium::program::{BlockLayout, ComputeShader, ProgramCreationError};
+use glium::uniforms::{LayoutMismatchError, UniformBlock};
+use glium::{Display, VertexBuffer};
+use math::{Vec3, Vec4};
 
-/// Unused as of now
+// Boids per work group, must match local_size_x in the shader
+const GROUP_SIZE: u32 = 64;
 
-pub const BASE_CSHADER: &str = r#"
+///
+/// One flocking step per invocation, the same rules as the CPU `flock_forces`
+/// without obstacles, predators or goals.
+/// Reads last frame's buffers and writes the other pair, so every boid sees the same state.
+/// Neighbours are found by checking every other boid, which is fine on a GPU for tens of thousands.
+///
+pub const BOIDS_CSHADER: &str = r#"
     #version 430 core
-    
-    struct Boid {
-        vec2 pos;
-        vec2 vel;
-        vec2 acc;
-    }
-    layout (local_size_x = 1, local_size_y = 1, local_size_z = 1) in;
-    
+    layout (local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+    layout (std430) readonly buffer PositionsIn { vec4 positions_in[]; };
+    layout (std430) readonly buffer VelocitiesIn { vec4 velocities_in[]; };
+    layout (std430) writeonly buffer PositionsOut { vec4 positions_out[]; };
+    layout (std430) writeonly buffer VelocitiesOut { vec4 velocities_out[]; };
+
+    uniform uint count;
+    uniform float perception;
+    uniform float separation;
+    uniform float alignment;
+    uniform float cohesion;
+    uniform float centering;
+    uniform float max_speed;
+    uniform float max_force;
+
+    vec3 limit(vec3 v, float max_length) {
+        float length_sq = dot(v, v);
+        if (length_sq <= max_length * max_length) {
+            return v;
+        }
+        return v / sqrt(length_sq) * max_length;
+    }
+
+    vec3 steer(vec3 desired, vec3 velocity) {
+        if (dot(desired, desired) == 0.0) {
+            return vec3(0.0);
+        }
+        return limit(desired / length(desired) * max_speed - velocity, max_force);
+    }
+
+    void main() {
+        uint i = gl_GlobalInvocationID.x;
+        if (i >= count) {
+            return;
+        }
+        vec3 position = positions_in[i].xyz;
+        vec3 velocity = velocities_in[i].xyz;
+
+        vec3 away = vec3(0.0);
+        vec3 heading = vec3(0.0);
+        vec3 centre = vec3(0.0);
+        uint neighbours = 0;
+        float perception_sq = perception * perception;
+        for (uint j = 0; j < count; j++) {
+            vec3 offset = position - positions_in[j].xyz;
+            float dist_sq = dot(offset, offset);
+            if (j == i || dist_sq == 0.0 || dist_sq > perception_sq) {
+                continue;
+            }
+            away += offset / dist_sq;
+            heading += velocities_in[j].xyz;
+            centre += positions_in[j].xyz;
+            neighbours++;
+        }
+
+        vec3 acceleration = vec3(0.0);
+        if (neighbours > 0) {
+            centre /= float(neighbours);
+            acceleration = steer(away, velocity) * separation
+                + steer(heading, velocity) * alignment
+                + steer(centre - position, velocity) * cohesion;
+        }
+        acceleration -= position * centering;
+
+        velocity = limit(velocity + acceleration, max_speed);
+        positions_out[i] = vec4(position + velocity, 1.0);
+        velocities_out[i] = vec4(velocity, 0.0);
+    }
 "#;
 
-pub fn base_cshader(display: &Display) -> ComputeShader {
-    ComputeShader::from_source(display, &BASE_CSHADER).expect("Couldn't compile code!")
+// A boid's position as the shader sees it, padded to a vec4 for std430
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct GpuPosition {
+    pub boid_position: [f32; 4],
+}
+glium::implement_vertex!(GpuPosition, boid_position);
+
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct GpuVelocity {
+    pub boid_velocity: [f32; 4],
+}
+glium::implement_vertex!(GpuVelocity, boid_velocity);
+
+// Both are laid out exactly like a bare vec4, so arrays of them match `vec4 name[]` blocks
+impl UniformBlock for GpuPosition {
+    fn matches(layout: &BlockLayout, base_offset: usize) -> Result<(), LayoutMismatchError> {
+        <[f32; 4]>::matches(layout, base_offset)
+    }
+
+    fn build_layout(base_offset: usize) -> BlockLayout {
+        <[f32; 4]>::build_layout(base_offset)
+    }
+}
+
+impl UniformBlock for GpuVelocity {
+    fn matches(layout: &BlockLayout, base_offset: usize) -> Result<(), LayoutMismatchError> {
+        <[f32; 4]>::matches(layout, base_offset)
+    }
+
+    fn build_layout(base_offset: usize) -> BlockLayout {
+        <[f32; 4]>::build_layout(base_offset)
+    }
+}
+
+fn pad(v: &Vec3, w: f32) -> [f32; 4] {
+    v.extend(w).into()
+}
+
+fn unpad(v: [f32; 4]) -> Vec3 {
+    Vec4::from(v).truncate()
+}
+
+///
+/// Boids stepped by a compute shader.
+/// Positions and velocities live in shader storage buffers that are also
+/// vertex buffers, so the instances are drawn straight from what the shader wrote
+/// without a round trip through the CPU.
+///
+pub struct BoidsCompute {
+    shader: ComputeShader,
+    positions: [VertexBuffer<GpuPosition>; 2],
+    velocities: [VertexBuffer<GpuVelocity>; 2],
+    // Which of each pair holds the latest state
+    current: usize,
+    count: usize,
+}
+
+impl BoidsCompute {
+    pub fn new(
+        display: &Display,
+        positions: &[Vec3],
+        velocities: &[Vec3],
+    ) -> Result<Self, ProgramCreationError> {
+        assert_eq!(positions.len(), velocities.len());
+        if !ComputeShader::is_supported(display) {
+            return Err(ProgramCreationError::CompilationNotSupported);
+        }
+        let shader = ComputeShader::from_source(display, BOIDS_CSHADER)?;
+
+        let positions: Vec<GpuPosition> = positions
+            .iter()
+            .map(|p| GpuPosition {
+                boid_position: pad(p, 1.0),
+            })
+            .collect();
+        let velocities: Vec<GpuVelocity> = velocities
+            .iter()
+            .map(|v| GpuVelocity {
+                boid_velocity: pad(v, 0.0),
+            })
+            .collect();
+        let count = positions.len();
+        Ok(Self {
+            shader,
+            positions: [
+                VertexBuffer::dynamic(display, &positions).unwrap(),
+                VertexBuffer::empty_dynamic(display, count).unwrap(),
+            ],
+            velocities: [
+                VertexBuffer::dynamic(display, &velocities).unwrap(),
+                VertexBuffer::empty_dynamic(display, count).unwrap(),
+            ],
+            current: 0,
+            count,
+        })
+    }
+
+    // Runs one step on the GPU, then swaps which buffers are current
+    pub fn dispatch(&mut self, params: &FlockParams) {
+        if self.count == 0 {
+            return;
+        }
+        let (read, write) = (self.current, 1 - self.current);
+        let uniforms = glium::uniform! {
+            PositionsIn: &*self.positions[read],
+            VelocitiesIn: &*self.velocities[read],
+            PositionsOut: &*self.positions[write],
+            VelocitiesOut: &*self.velocities[write],
+            count: self.count as u32,
+            perception: params.perception,
+            separation: params.separation,
+            alignment: params.alignment,
+            cohesion: params.cohesion,
+            centering: params.centering,
+            max_speed: params.max_speed,
+            max_force: params.max_force,
+        };
+        let groups = (self.count as u32).div_ceil(GROUP_SIZE);
+        self.shader.execute(uniforms, groups, 1, 1);
+        self.current = write;
+    }
+
+    pub fn positions(&self) -> &VertexBuffer<GpuPosition> {
+        &self.positions[self.current]
+    }
+
+    pub fn velocities(&self) -> &VertexBuffer<GpuVelocity> {
+        &self.velocities[self.current]
+    }
+
+    // Copies the latest positions back to the CPU, which stalls until the GPU is done
+    pub fn read_positions(&self) -> Vec<Vec3> {
+        self.positions()
+            .read()
+            .unwrap()
+            .iter()
+            .map(|p| unpad(p.boid_position))
+            .collect()
+    }
+
+    pub fn read_velocities(&self) -> Vec<Vec3> {
+        self.velocities()
+            .read()
+            .unwrap()
+            .iter()
+            .map(|v| unpad(v.boid_velocity))
+            .collect()
+    }
 }
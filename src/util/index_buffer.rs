@@ -0,0 +1,221 @@
+use glium::index::{IndicesSource, PrimitiveType};
+use glium::IndexBuffer;
+use util::bufferable::Backend;
+
+///
+/// Vertex indices, 16 bit where every index fits since they take half the memory.
+/// With primitive restart on, the largest value of the type starts a new strip
+/// instead of naming a vertex.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Indices {
+    // The narrowest type that holds every index, keeping its largest value free for restarts
+    pub fn compact(indices: &[u32]) -> Self {
+        if indices.iter().all(|&i| i < u16::MAX as u32) {
+            Indices::U16(indices.iter().map(|&i| i as u16).collect())
+        } else {
+            Indices::U32(indices.to_vec())
+        }
+    }
+
+    // Strips joined with restarts between them, to be drawn with primitive restart on
+    pub fn from_strips(strips: &[Vec<u32>]) -> Self {
+        let joined: Vec<u32> = strips
+            .iter()
+            .enumerate()
+            .flat_map(|(i, strip)| {
+                let restart = if i == 0 { None } else { Some(u32::MAX) };
+                restart.into_iter().chain(strip.iter().copied())
+            })
+            .collect();
+        // Compact first, with the restarts as whatever the chosen type uses
+        let max = joined.iter().filter(|&&i| i != u32::MAX).max();
+        if max.is_none_or(|&max| max < u16::MAX as u32) {
+            Indices::U16(
+                joined
+                    .iter()
+                    .map(|&i| i.min(u16::MAX as u32) as u16)
+                    .collect(),
+            )
+        } else {
+            Indices::U32(joined)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> u32 {
+        match self {
+            Indices::U16(indices) => indices[index] as u32,
+            Indices::U32(indices) => indices[index],
+        }
+    }
+
+    pub fn to_u32(&self) -> Vec<u32> {
+        (0..self.len()).map(|i| self.get(i)).collect()
+    }
+
+    // The value that starts a new strip when primitive restart is on
+    pub fn restart_index(&self) -> u32 {
+        match self {
+            Indices::U16(_) => u16::MAX as u32,
+            Indices::U32(_) => u32::MAX,
+        }
+    }
+}
+
+enum Buffer {
+    U16(IndexBuffer<u16>),
+    U32(IndexBuffer<u32>),
+}
+
+///
+/// Indices with an optional copy on the GPU, like `BufferObject` is for vertices.
+/// The data is the source of truth, `update_buffer` uploads it.
+///
+pub struct IndexObject {
+    data: Indices,
+    primitive: PrimitiveType,
+    restart: bool,
+    buffer: Option<Buffer>,
+}
+
+impl IndexObject {
+    pub fn new(display: &dyn Backend, primitive: PrimitiveType, data: Indices) -> Self {
+        let buffer = display.display().map(|display| match data {
+            Indices::U16(ref indices) => {
+                Buffer::U16(IndexBuffer::dynamic(display, primitive, indices).unwrap())
+            }
+            Indices::U32(ref indices) => {
+                Buffer::U32(IndexBuffer::dynamic(display, primitive, indices).unwrap())
+            }
+        });
+        IndexObject {
+            data,
+            primitive,
+            restart: false,
+            buffer,
+        }
+    }
+
+    // Separate strips in one buffer, drawn with primitive restart
+    pub fn strips(display: &dyn Backend, primitive: PrimitiveType, strips: &[Vec<u32>]) -> Self {
+        IndexObject {
+            restart: true,
+            ..IndexObject::new(display, primitive, Indices::from_strips(strips))
+        }
+    }
+
+    pub fn ref_data(&self) -> &Indices {
+        &self.data
+    }
+
+    // Changes to the indices have to keep how many there are and their type
+    pub fn mut_data(&mut self) -> &mut Indices {
+        &mut self.data
+    }
+
+    pub fn primitive(&self) -> PrimitiveType {
+        self.primitive
+    }
+
+    pub fn restart(&self) -> bool {
+        self.restart
+    }
+
+    pub fn update_buffer(&self) {
+        match (&self.buffer, &self.data) {
+            (Some(Buffer::U16(buffer)), Indices::U16(data)) => buffer.write(data),
+            (Some(Buffer::U32(buffer)), Indices::U32(data)) => buffer.write(data),
+            (None, _) => (),
+            _ => panic!("index data changed type after its buffer was made"),
+        }
+    }
+
+    // Only for drawing, which headless index objects can't do
+    pub fn source(&self) -> IndicesSource<'_> {
+        match self.buffer {
+            Some(Buffer::U16(ref buffer)) => buffer.into(),
+            Some(Buffer::U32(ref buffer)) => buffer.into(),
+            None => panic!("headless index objects have no GPU buffer"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::bufferable::Headless;
+
+    const RESTART: u32 = u16::MAX as u32;
+
+    #[test]
+    fn small_indices_are_16_bit() {
+        assert_eq!(Indices::compact(&[0, 1, 2]), Indices::U16(vec![0, 1, 2]));
+        assert_eq!(Indices::compact(&[]), Indices::U16(vec![]));
+        let largest = Indices::compact(&[0, RESTART - 1]);
+        assert_eq!(largest, Indices::U16(vec![0, u16::MAX - 1]));
+        assert_eq!(largest.restart_index(), RESTART);
+    }
+
+    #[test]
+    fn the_largest_16_bit_value_is_kept_for_restarts() {
+        // 65535 fits in a u16, but would be read as a restart
+        let indices = Indices::compact(&[0, RESTART, 1]);
+        assert_eq!(indices, Indices::U32(vec![0, RESTART, 1]));
+        assert_eq!(indices.restart_index(), u32::MAX);
+        assert_eq!(indices.to_u32(), vec![0, RESTART, 1]);
+    }
+
+    #[test]
+    fn strips_only_have_restarts_between_them() {
+        let strips = vec![vec![0, 1, 2], vec![3, 4], vec![5, 6, 7]];
+        let indices = Indices::from_strips(&strips);
+        let restart = u16::MAX;
+        assert_eq!(
+            indices,
+            Indices::U16(vec![0, 1, 2, restart, 3, 4, restart, 5, 6, 7])
+        );
+        assert_eq!(
+            Indices::from_strips(&strips[..1]),
+            Indices::U16(vec![0, 1, 2])
+        );
+        assert_eq!(Indices::from_strips(&[]), Indices::U16(vec![]));
+    }
+
+    #[test]
+    fn strips_with_large_indices_restart_at_the_32_bit_value() {
+        let strips = vec![vec![0, RESTART], vec![1, 2]];
+        let indices = Indices::from_strips(&strips);
+        assert_eq!(indices, Indices::U32(vec![0, RESTART, u32::MAX, 1, 2]));
+        // The restart is where the strips join and nowhere else
+        let restarts: Vec<usize> = (0..indices.len())
+            .filter(|&i| indices.get(i) == indices.restart_index())
+            .collect();
+        assert_eq!(restarts, vec![2]);
+    }
+
+    #[test]
+    fn index_objects_keep_their_data() {
+        let strips = vec![vec![0, 1], vec![2, 3]];
+        let mut object = IndexObject::strips(&Headless, PrimitiveType::LineStrip, &strips);
+        assert!(object.restart());
+        assert_eq!(object.primitive(), PrimitiveType::LineStrip);
+        if let Indices::U16(indices) = object.mut_data() {
+            indices[4] = 1;
+        }
+        // There's no GPU copy to upload to without a display
+        object.update_buffer();
+        assert_eq!(object.ref_data().to_u32(), vec![0, 1, RESTART, 2, 1]);
+    }
+}
@@ -1,10 +1,21 @@
-use std::iter::Zip;
-
 pub(crate) mod attribute;
 pub(crate) mod bufferable;
-mod compute_container;
+pub(crate) mod compute_container;
+pub(crate) mod index_buffer;
+pub(crate) mod random;
+pub(crate) mod spatial_hash;
 pub(crate) mod vertex;
 
+use math::Vec3;
+
+// Things that can be turned in place, each instance or vertex around its own origin
 pub trait Manipulate {
-    fn rotate_axis(&mut self, axis: usize, ang: f32);
+    // Turns `angle` radians anticlockwise around `axis`, which doesn't need to be normalised
+    fn rotate(&mut self, axis: Vec3, angle: f32);
+}
+
+impl<T: Manipulate + ?Sized> Manipulate for Box<T> {
+    fn rotate(&mut self, axis: Vec3, angle: f32) {
+        (**self).rotate(axis, angle);
+    }
 }
@@ -0,0 +1,33 @@
+use math::Vec3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+///
+/// Reseeds the generator for the current thread, so everything it
+/// randomises afterwards comes out the same on every run.
+/// Scenes are built on the main thread, so seeding there is enough.
+///
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+// Runs `f` with the current thread's generator
+pub fn with_rng<T>(f: impl FnOnce(&mut StdRng) -> T) -> T {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+// Random point in the cube reaching `half_width` from the origin along each axis
+pub fn in_cube(half_width: f32) -> Vec3 {
+    with_rng(|rng| {
+        Vec3::new(
+            rng.gen_range(-half_width..half_width),
+            rng.gen_range(-half_width..half_width),
+            rng.gen_range(-half_width..half_width),
+        )
+    })
+}
@@ -0,0 +1,170 @@
+use math::Vec3;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+type Cell = (i32, i32, i32);
+
+///
+/// Uniform grid over 3D space for finding points near each other.
+/// Rebuild it from the current positions each frame, then query it from
+/// as many threads as needed. Works best with cells about the size of the
+/// query radius.
+///
+pub struct SpatialHash {
+    cell_size: f32,
+    // Point indices grouped by the cell they fall in
+    indices: Vec<usize>,
+    // Start and end in `indices` of each occupied cell
+    cells: HashMap<Cell, (usize, usize)>,
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0, "cell size must be positive");
+        SpatialHash {
+            cell_size,
+            indices: vec![],
+            cells: HashMap::new(),
+        }
+    }
+
+    // Changing the cell size only takes effect on the next rebuild
+    pub fn set_cell_size(&mut self, cell_size: f32) {
+        assert!(cell_size > 0.0, "cell size must be positive");
+        self.cell_size = cell_size;
+    }
+
+    #[inline]
+    fn cell(&self, position: &Vec3) -> Cell {
+        (
+            (position[0] / self.cell_size).floor() as i32,
+            (position[1] / self.cell_size).floor() as i32,
+            (position[2] / self.cell_size).floor() as i32,
+        )
+    }
+
+    pub fn rebuild(&mut self, positions: &[Vec3]) {
+        let mut keyed: Vec<(Cell, usize)> = positions
+            .par_iter()
+            .enumerate()
+            .map(|(i, p)| (self.cell(p), i))
+            .collect();
+        keyed.par_sort_unstable();
+
+        self.indices.clear();
+        self.indices.extend(keyed.iter().map(|&(_, i)| i));
+        self.cells.clear();
+        let mut start = 0;
+        for chunk in keyed.chunk_by(|a, b| a.0 == b.0) {
+            self.cells.insert(chunk[0].0, (start, start + chunk.len()));
+            start += chunk.len();
+        }
+    }
+
+    ///
+    /// Indices of every point in the cells that overlap the sphere around `point`.
+    /// This can include points a little further away than `radius`,
+    /// so callers still need to check the distance.
+    ///
+    pub fn candidates(&self, point: &Vec3, radius: f32) -> impl Iterator<Item = usize> + '_ {
+        let reach = (radius / self.cell_size).ceil() as i32;
+        let (cx, cy, cz) = self.cell(point);
+        (cx - reach..=cx + reach)
+            .flat_map(move |x| (cy - reach..=cy + reach).map(move |y| (x, y)))
+            .flat_map(move |(x, y)| (cz - reach..=cz + reach).map(move |z| (x, y, z)))
+            .filter_map(move |cell| self.cells.get(&cell))
+            .flat_map(move |&(start, end)| self.indices[start..end].iter().copied())
+    }
+
+    // Indices of the points within `radius` of `point`
+    pub fn within<'a>(
+        &'a self,
+        point: &'a Vec3,
+        radius: f32,
+        positions: &'a [Vec3],
+    ) -> impl Iterator<Item = usize> + 'a {
+        let radius_sq = radius * radius;
+        self.candidates(point, radius)
+            .filter(move |&i| (positions[i] - *point).length_sq() <= radius_sq)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::time::Instant;
+
+    fn random_points(count: usize, half_width: f32, seed: u64) -> Vec<Vec3> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut coordinate = || rng.gen_range(-half_width..half_width);
+        (0..count)
+            .map(|_| Vec3::new(coordinate(), coordinate(), coordinate()))
+            .collect()
+    }
+
+    fn brute_force(point: &Vec3, radius: f32, positions: &[Vec3]) -> Vec<usize> {
+        (0..positions.len())
+            .filter(|&i| (positions[i] - *point).length_sq() <= radius * radius)
+            .collect()
+    }
+
+    #[test]
+    fn within_matches_brute_force() {
+        let positions = random_points(2000, 1.0, 7);
+        let mut hash = SpatialHash::new(0.1);
+        hash.rebuild(&positions);
+        // Radii smaller than, the same as and larger than a cell, around points in and out of the set
+        let outside = random_points(100, 1.5, 8);
+        for point in positions.iter().take(100).chain(&outside) {
+            for &radius in &[0.03, 0.1, 0.25] {
+                let mut found: Vec<usize> = hash.within(point, radius, &positions).collect();
+                found.sort_unstable();
+                assert_eq!(found, brute_force(point, radius, &positions));
+            }
+        }
+    }
+
+    #[test]
+    fn rebuilding_drops_old_points() {
+        let mut hash = SpatialHash::new(0.5);
+        hash.rebuild(&random_points(50, 1.0, 1));
+        let positions = random_points(10, 1.0, 2);
+        hash.rebuild(&positions);
+        let mut found: Vec<usize> = hash.candidates(&Vec3::ZERO, 2.0).collect();
+        found.sort_unstable();
+        assert_eq!(found, (0..10).collect::<Vec<_>>());
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture` to see the timings
+    #[test]
+    #[ignore]
+    fn bench_against_brute_force() {
+        let radius = 0.08;
+        for &count in &[1000, 10000, 50000] {
+            let positions = random_points(count, 1.0, 9);
+            let start = Instant::now();
+            let mut hash = SpatialHash::new(radius);
+            hash.rebuild(&positions);
+            let built = start.elapsed();
+            let hashed: usize = positions
+                .par_iter()
+                .map(|p| hash.within(p, radius, &positions).count())
+                .sum();
+            let hash_time = start.elapsed();
+
+            let start = Instant::now();
+            let brute: usize = positions
+                .par_iter()
+                .map(|p| brute_force(p, radius, &positions).len())
+                .sum();
+            let brute_time = start.elapsed();
+            assert_eq!(hashed, brute);
+            println!(
+                "{} points: spatial hash {:?} ({:?} to build), brute force {:?}",
+                count, hash_time, built, brute_time
+            );
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use camera::orbit::OrbitCamera;
+use camera::Camera;
+use drawable::mesh::Mesh;
+use drawable::shape::{HasShape, Shape};
+use drawable::shape_group::ShapeGroup;
+use drawable::{DrawUniforms, Drawable};
+use glium::{DrawParameters, Frame};
+use math::Vec3;
+use model::obj;
+use model::{self, ModelError};
+use runnable::engine::{BASE_FSHADER, BASE_VSHADER};
+use runnable::scene::Scene;
+use shader::{ProgramLibrary, ProgramSource, ShaderError};
+use std::f32::consts::FRAC_PI_6;
+use std::io::{self, Write};
+use std::iter::zip;
+use std::path::Path;
+use util::attribute::Instance;
+use util::bufferable::{Backend, InstanceBuffer};
+use util::index_buffer::Indices;
+use util::Manipulate;
+use winit::event::VirtualKeyCode;
+
+// Radians everything turns about the middle each tick
+const TURN_SPEED: f32 = 0.01;
+// Distance between the middles of the primitives
+const SPACING: f32 = 0.8;
+// Colour of parts without a material
+const GREY: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+
+// A part's name, shape and colour
+type Part = (String, Shape, [f32; 4]);
+
+///
+/// Shows a model loaded from a file, or every mesh primitive side by side without one,
+/// slowly turning about the middle of it all. Exporting it saves the meshes as they've
+/// been turned, so exporting before any ticks converts the file.
+///
+pub struct ModelViewer {
+    shapes: ShapeGroup<Shape>,
+    // One for each shape, for the dump
+    names: Vec<String>,
+    // Middle of the bounding box, what it turns about and the camera looks at
+    centre: Vec3,
+    // From the middle to the bounding box's corners
+    radius: f32,
+    // Ticks run so far, to name saved files by
+    ticks: u64,
+}
+
+impl ModelViewer {
+    // Reads an OBJ, STL or PLY file. OBJ parts are drawn in their material's diffuse colour
+    pub fn load(display: &dyn Backend, path: &Path) -> Result<Self, ModelError> {
+        let is_obj = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("obj"));
+        let (parts, meshes): (Vec<Part>, Vec<Mesh>) = if is_obj {
+            let obj = obj::load(path)?;
+            let parts = zip(obj.shapes(display), &obj.meshes)
+                .map(|(shape, part)| {
+                    let color = obj.material(part).map_or(GREY, |m| {
+                        [m.diffuse[0], m.diffuse[1], m.diffuse[2], m.opacity]
+                    });
+                    (part.name(), shape, color)
+                })
+                .collect();
+            (
+                parts,
+                obj.meshes.into_iter().map(|part| part.mesh).collect(),
+            )
+        } else {
+            let meshes = model::load(path)?;
+            let parts = meshes
+                .iter()
+                .map(|mesh| (String::new(), Shape::from_mesh(mesh, display), GREY))
+                .collect();
+            (parts, meshes)
+        };
+
+        let mut positions = meshes
+            .iter()
+            .flat_map(|mesh| mesh.vertices.iter().map(|v| v.pos()));
+        let first = positions.next().ok_or_else(|| {
+            ModelError::invalid(&path.display().to_string(), "there's nothing in it to show")
+        })?;
+        let (min, max) = positions.fold((first, first), |(min, max), p| (min.min(p), max.max(p)));
+
+        let mut shapes = ShapeGroup::default();
+        let mut names = vec![];
+        for (name, shape, color) in parts {
+            let instance = Instance {
+                color,
+                ..Instance::default()
+            };
+            shapes.push((shape, InstanceBuffer::new(display, &[instance])));
+            names.push(name);
+        }
+        Ok(ModelViewer {
+            shapes,
+            names,
+            centre: (min + max) * 0.5,
+            radius: (max - min).length() * 0.5,
+            ticks: 0,
+        })
+    }
+
+    // Every primitive in a row along x, tipped towards the camera
+    pub fn primitives(display: &dyn Backend) -> Self {
+        let primitives = vec![
+            ("cube", Shape::cube(display, 0.5, 2), [0.9, 0.3, 0.3, 1.0]),
+            (
+                "uv_sphere",
+                Shape::uv_sphere(display, 0.3, 32, 16),
+                [0.9, 0.6, 0.2, 1.0],
+            ),
+            (
+                "icosphere",
+                Shape::icosphere(display, 0.3, 2),
+                [0.9, 0.9, 0.3, 1.0],
+            ),
+            (
+                "cylinder",
+                Shape::cylinder(display, 0.25, 0.5, 32, 1),
+                [0.3, 0.9, 0.4, 1.0],
+            ),
+            (
+                "cone",
+                Shape::cone(display, 0.25, 0.5, 32, 1),
+                [0.3, 0.8, 0.9, 1.0],
+            ),
+            (
+                "torus",
+                Shape::torus(display, 0.2, 0.08, 32, 16),
+                [0.4, 0.4, 0.9, 1.0],
+            ),
+            (
+                "plane",
+                Shape::plane(display, 0.5, 0.5, 4, 4),
+                [0.8, 0.4, 0.9, 1.0],
+            ),
+        ];
+        let half_width = (primitives.len() - 1) as f32 * SPACING / 2.0;
+        let mut shapes = ShapeGroup::default();
+        let mut names = vec![];
+        for (i, (name, shape, color)) in primitives.into_iter().enumerate() {
+            let mut instance = Instance::from(Vec3::X * (i as f32 * SPACING - half_width));
+            instance.color = color;
+            instance.rotate(Vec3::X, FRAC_PI_6);
+            shapes.push((shape, InstanceBuffer::new(display, &[instance])));
+            names.push(name.to_string());
+        }
+        ModelViewer {
+            shapes,
+            names,
+            centre: Vec3::ZERO,
+            radius: half_width + SPACING / 2.0,
+            ticks: 0,
+        }
+    }
+
+    // Reverses the winding and normals of every face, for files saved inside out
+    fn turn_inside_out(&mut self) {
+        for shape in self.shapes.iter_mut_shapes() {
+            for vertex in shape.mut_vertices() {
+                vertex.normal = (-Vec3::from(vertex.normal)).into();
+            }
+            if let Some(indices) = shape.indices.as_mut() {
+                match indices.mut_data() {
+                    Indices::U16(indices) => indices.chunks_exact_mut(3).for_each(|t| t.swap(1, 2)),
+                    Indices::U32(indices) => indices.chunks_exact_mut(3).for_each(|t| t.swap(1, 2)),
+                }
+            }
+            shape.update_vbo();
+        }
+    }
+}
+
+impl Drawable for ModelViewer {
+    fn draw(
+        &self,
+        target: &mut Frame,
+        programs: &ProgramLibrary,
+        params: &DrawParameters,
+        uniforms: DrawUniforms,
+    ) {
+        self.shapes.draw(target, programs, params, uniforms);
+    }
+
+    fn update(&mut self) {
+        self.ticks += 1;
+        self.rotate(Vec3::Y, TURN_SPEED);
+    }
+}
+
+// Swings every part around the middle rather than turning each one where it is
+impl Manipulate for ModelViewer {
+    fn rotate(&mut self, axis: Vec3, angle: f32) {
+        let centre = self.centre;
+        for instances in self.shapes.transforms.iter_mut() {
+            instances
+                .iter_mut()
+                .for_each(|instance| instance.rotate_about(centre, axis, angle));
+        }
+    }
+}
+
+// Each part's name, triangle count, where its origin has been swung to and how it's turned
+impl Scene for ModelViewer {
+    fn dump(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "name,triangles,x,y,z,qx,qy,qz,qw")?;
+        let parts = zip(
+            &self.names,
+            zip(&self.shapes.shapes, &self.shapes.transforms),
+        );
+        for (name, (shape, instances)) in parts {
+            let triangles = shape
+                .ref_indices()
+                .map_or(0, |indices| indices.ref_data().len() / 3);
+            for instance in instances.iter() {
+                let (p, q) = (instance.transform.translation, instance.transform.rotation);
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{},{},{},{}",
+                    name, triangles, p.x, p.y, p.z, q.x, q.y, q.z, q.w
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn camera(&self) -> Box<dyn Camera> {
+        Box::new(OrbitCamera::new(self.centre, self.radius * 2.5))
+    }
+
+    fn mesh(&self) -> Option<Mesh> {
+        model::bake_group(&self.shapes).ok()
+    }
+
+    fn handle_key(&mut self, key: VirtualKeyCode) {
+        match key {
+            VirtualKeyCode::P => {
+                let path = format!("model-{}.stl", self.ticks);
+                match model::export(Path::new(&path), &self.shapes, false) {
+                    Ok(()) => println!("Saved the model as it's turned now to {}", path),
+                    Err(err) => eprintln!("Couldn't save {}: {}", path, err),
+                }
+            }
+            VirtualKeyCode::F => {
+                self.turn_inside_out();
+                println!("Turned the model's faces inside out");
+            }
+            _ => (),
+        }
+    }
+
+    // Built with LIT defined so faces turned from the light are darker and the shape shows
+    fn load_programs(&mut self, programs: &mut ProgramLibrary) -> Result<(), ShaderError> {
+        let source = ProgramSource::new(BASE_VSHADER, BASE_FSHADER).define("LIT", "");
+        let lit = programs.handle_or_add("lit", source)?;
+        self.shapes
+            .iter_mut_shapes()
+            .for_each(|shape| shape.set_program(lit));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::bufferable::Headless;
+
+    #[test]
+    fn turning_inside_out_flips_windings_and_normals() {
+        let mut viewer = ModelViewer::primitives(&Headless);
+        let before = viewer.mesh().unwrap();
+        viewer.handle_key(VirtualKeyCode::F);
+        let after = viewer.mesh().unwrap();
+        assert_eq!(after.triangle_count(), before.triangle_count());
+        for (was, now) in zip(before.triangles(), after.triangles()) {
+            assert_eq!(
+                [now[0].pos(), now[1].pos(), now[2].pos()],
+                [was[0].pos(), was[2].pos(), was[1].pos()]
+            );
+            for (was, now) in zip(&was, &[now[0], now[2], now[1]]) {
+                let flipped = -Vec3::from(was.normal);
+                assert!(Vec3::from(now.normal).approx_eq(flipped, 1e-6));
+            }
+        }
+    }
+}